@@ -0,0 +1,41 @@
+//! Whole files held in memory, for the interactive modes that search the
+//! same files again and again.
+
+use std::path::{Path, PathBuf};
+
+use crate::ignore::Ignores;
+use crate::vfs::{self, FileSystem};
+use crate::{Config, walk};
+
+pub(crate) struct File {
+    pub(crate) path: PathBuf,
+    pub(crate) contents: String,
+}
+
+/// Reads every file the search covers, in path order, leaving out any that
+/// can't be read as text. A directory is searched recursively even without
+/// `-r`, since there'd be nothing to search otherwise.
+pub(crate) fn load(config: &Config, fs: &dyn FileSystem) -> Vec<File> {
+    let root = Path::new(&config.input.file_path);
+    let is_dir = vfs::followed(fs, root).is_ok_and(|metadata| metadata.is_dir());
+    let paths = if config.input.recursive || is_dir {
+        let mut paths = walk::walk(
+            fs,
+            root,
+            &Ignores::new(config),
+            config.input.follow_links,
+            |_, metadata| config.skip_reason(metadata).is_none(),
+        );
+        paths.sort();
+        paths
+    } else {
+        vec![root.to_path_buf()]
+    };
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let contents = vfs::read_to_string(fs, &path).ok()?;
+            Some(File { path, contents })
+        })
+        .collect()
+}
@@ -0,0 +1,581 @@
+//! `--completions SHELL`: completion scripts for the shells people use.
+//!
+//! Every flag is listed once in [`FLAGS`], which each script is generated
+//! from, so a new flag only needs adding there to be completed everywhere.
+
+use std::fmt::Write;
+
+/// The shells `--completions` can write a script for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+}
+
+impl Shell {
+    pub(crate) fn parse(shell: &str) -> Option<Shell> {
+        match shell {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            "powershell" => Some(Shell::PowerShell),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) struct Flag {
+    pub(crate) short: Option<char>,
+    pub(crate) long: Option<&'static str>,
+    /// What the flag's value is, if it takes one as the next argument.
+    pub(crate) value: Option<&'static str>,
+    /// The values to offer, when there's a fixed set.
+    pub(crate) choices: &'static [&'static str],
+    pub(crate) help: &'static str,
+}
+
+const fn flag(short: Option<char>, long: &'static str, help: &'static str) -> Flag {
+    Flag {
+        short,
+        long: Some(long),
+        value: None,
+        choices: &[],
+        help,
+    }
+}
+
+const fn valued(long: &'static str, value: &'static str, help: &'static str) -> Flag {
+    Flag {
+        short: None,
+        long: Some(long),
+        value: Some(value),
+        choices: &[],
+        help,
+    }
+}
+
+const fn choice(long: &'static str, choices: &'static [&'static str], help: &'static str) -> Flag {
+    Flag {
+        short: None,
+        long: Some(long),
+        value: Some("WHICH"),
+        choices,
+        help,
+    }
+}
+
+/// The subcommands that can come before everything else.
+pub(crate) const SUBCOMMANDS: [(&str, &str); 5] = [
+    ("search", "Print matching lines"),
+    ("replace", "Print matching lines with each match replaced"),
+    ("count", "Print the number of matching lines"),
+    ("files", "Print the files with matches"),
+    ("index", "Build or update a search index"),
+];
+
+pub(crate) const FLAGS: &[Flag] = &[
+    Flag {
+        short: Some('i'),
+        long: None,
+        value: None,
+        choices: &[],
+        help: "Ignore case",
+    },
+    flag(
+        Some('s'),
+        "case-sensitive",
+        "Match case even if IGNORE_CASE is set",
+    ),
+    flag(
+        None,
+        "no-env",
+        "Ignore IGNORE_CASE, LANG, NO_COLOR, COLUMNS and the user's ignore file",
+    ),
+    flag(Some('r'), "recursive", "Search directories recursively"),
+    flag(Some('n'), "line-number", "Show line numbers"),
+    flag(
+        Some('N'),
+        "no-line-number",
+        "Don't show line numbers, even on a terminal",
+    ),
+    flag(None, "heading", "Print each file's path above its lines"),
+    flag(
+        None,
+        "no-heading",
+        "Print the path before every line, even on a terminal",
+    ),
+    flag(
+        Some('F'),
+        "follow",
+        "Keep printing lines appended to the file",
+    ),
+    flag(Some('c'), "count", "Print the number of matching lines"),
+    flag(None, "count-matches", "Print the number of matches"),
+    flag(None, "count-per-file", "Print a count for each file"),
+    flag(
+        None,
+        "count-files",
+        "Print the number of files with a match",
+    ),
+    flag(
+        None,
+        "include-zero",
+        "Include files with no matches in counts",
+    ),
+    flag(Some('o'), "only-matching", "Print only the matched text"),
+    flag(None, "column", "Print the column each match starts at"),
+    flag(
+        None,
+        "overlapping",
+        "With -o and --count-matches, report overlapping matches too",
+    ),
+    valued(
+        "synonyms",
+        "FILE",
+        "Also search for the alternatives this file lists for a query",
+    ),
+    flag(
+        None,
+        "escapes",
+        "Read escapes for tabs, control characters and hex codes in queries",
+    ),
+    flag(
+        None,
+        "vimgrep",
+        "Print every match as path:line:column:text",
+    ),
+    flag(
+        None,
+        "porcelain",
+        "Print lines in a stable, tab-separated format for scripts",
+    ),
+    flag(
+        None,
+        "explain",
+        "Show which query and engine found each match, and where",
+    ),
+    valued(
+        "group",
+        "N|NAME",
+        "Print only this capture group of a regex",
+    ),
+    valued("top", "N", "Print the N most frequent matches"),
+    flag(
+        None,
+        "histogram",
+        "Chart matches per directory, or per file with =file",
+    ),
+    flag(None, "unique", "Print each matching line once"),
+    flag(
+        None,
+        "unique-count",
+        "Print each matching line once with its count",
+    ),
+    flag(None, "stats", "Print statistics, or more with =full"),
+    choice(
+        "stats-format",
+        &["text", "json"],
+        "Print statistics as text or as JSON",
+    ),
+    flag(
+        Some('q'),
+        "quiet",
+        "Print nothing; exit with 0 if anything matched, 1 if not",
+    ),
+    flag(
+        None,
+        "message-on-no-match",
+        "Say so on stderr when nothing matches",
+    ),
+    flag(
+        None,
+        "quiet-no-match",
+        "Leave a search that finds nothing to the exit status",
+    ),
+    flag(
+        Some('l'),
+        "files-with-matches",
+        "Print only the paths of files that match",
+    ),
+    valued(
+        "max-total",
+        "N",
+        "Stop the whole search after N matching lines",
+    ),
+    valued("head", "N", "Print only the first N matching lines"),
+    valued("tail", "N", "Print only the last N matching lines"),
+    valued(
+        "timeout",
+        "SECONDS",
+        "Stop searching after this long, exiting with 3",
+    ),
+    valued(
+        "max-memory",
+        "SIZE",
+        "Read in chunks to stay under this much memory",
+    ),
+    valued(
+        "buffer-size",
+        "SIZE",
+        "Read files this many bytes at a time",
+    ),
+    valued("max-filesize", "SIZE", "Skip files larger than this"),
+    valued(
+        "newer-than",
+        "TIME",
+        "Skip files modified before this age or date",
+    ),
+    valued(
+        "older-than",
+        "TIME",
+        "Skip files modified after this age or date",
+    ),
+    choice(
+        "sort",
+        &["path", "modified", "size", "priority"],
+        "Sort files before searching",
+    ),
+    choice(
+        "sortr",
+        &["path", "modified", "size", "priority"],
+        "Sort files in reverse",
+    ),
+    choice(
+        "sort-lines",
+        &["alpha", "length", "none"],
+        "Sort the matching lines before printing them",
+    ),
+    choice(
+        "sort-scope",
+        &["file", "all"],
+        "Sort lines within each file, or all together",
+    ),
+    valued("from-line", "N", "Start at this line"),
+    valued("to-line", "N", "Stop after this line"),
+    valued("start-offset", "BYTES", "Start at this byte offset"),
+    valued("end-offset", "BYTES", "Stop at this byte offset"),
+    Flag {
+        short: Some('M'),
+        long: Some("max-columns"),
+        value: Some("N"),
+        choices: &[],
+        help: "Cut lines longer than this",
+    },
+    valued("tabs", "N", "Expand tabs to this width"),
+    flag(None, "wrap", "Wrap long lines to the terminal"),
+    valued(
+        "hyperlink-format",
+        "TEMPLATE",
+        "Make paths links, like file://{path}",
+    ),
+    flag(
+        None,
+        "glob-pattern",
+        "Treat * and ? in the query as wildcards",
+    ),
+    flag(
+        None,
+        "pcre2",
+        "Treat the query as a PCRE2 pattern, if built with PCRE2",
+    ),
+    choice(
+        "engine",
+        &["literal", "regex", "glob", "auto", "pcre2", "hyperscan"],
+        "How the query is interpreted",
+    ),
+    choice("color", &["auto", "always", "never"], "When to use colour"),
+    choice("lang", &["en", "de", "es", "fr"], "Language for messages"),
+    choice("error-format", &["text", "json"], "How errors are reported"),
+    valued("colors", "SPEC", "Set a colour, like match:fg:yellow"),
+    flag(None, "pager", "Page output through $PAGER"),
+    flag(None, "line-buffered", "Flush the output after every line"),
+    flag(
+        None,
+        "block-buffered",
+        "Flush the output only when the buffer fills",
+    ),
+    flag(None, "crlf", "End output lines with CRLF"),
+    flag(None, "null", "End output lines with a NUL byte"),
+    flag(None, "no-progress", "Don't show progress"),
+    flag(Some('v'), "verbose", "Explain what's being done"),
+    flag(None, "debug", "Print timings and other details"),
+    flag(None, "explain-skips", "Show which files would be searched"),
+    flag(None, "in-place", "With replace, rewrite the files"),
+    flag(
+        None,
+        "preserve-case",
+        "With replace, keep the case of each match",
+    ),
+    valued(
+        "backup-suffix",
+        "SUFFIX",
+        "With --in-place, keep the originals with this added to their names",
+    ),
+    valued(
+        "backup-dir",
+        "DIR",
+        "With --in-place, keep the originals in this directory",
+    ),
+    flag(
+        None,
+        "no-backup",
+        "With --in-place, don't keep the originals",
+    ),
+    flag(None, "passthru", "Print every line, highlighting matches"),
+    Flag {
+        short: Some('A'),
+        long: Some("after-context"),
+        value: Some("N"),
+        choices: &[],
+        help: "Print N lines after each match",
+    },
+    Flag {
+        short: Some('B'),
+        long: Some("before-context"),
+        value: Some("N"),
+        choices: &[],
+        help: "Print N lines before each match",
+    },
+    Flag {
+        short: Some('C'),
+        long: Some("context"),
+        value: Some("N"),
+        choices: &[],
+        help: "Print N lines around each match",
+    },
+    flag(
+        None,
+        "block-context",
+        "Print the paragraph around each match",
+    ),
+    flag(
+        None,
+        "show-function",
+        "Head matches with the function they're in",
+    ),
+    valued(
+        "function-start",
+        "TEXT",
+        "Treat lines containing this as function starts",
+    ),
+    flag(
+        None,
+        "json-input",
+        "Search the values in JSON, showing where each is",
+    ),
+    flag(None, "csv", "Search delimited files record by record"),
+    valued("field", "COLUMN", "With --csv, search this column"),
+    valued("delimiter", "CHAR", "With --csv, the field delimiter"),
+    flag(None, "html", "Search the text of HTML or XML, not the tags"),
+    valued(
+        "selector",
+        "SELECTOR",
+        "With --html, search only inside these elements",
+    ),
+    valued(
+        "group-separator",
+        "TEXT",
+        "Print this between blocks of context",
+    ),
+    flag(
+        None,
+        "update-index",
+        "Update an existing index before searching",
+    ),
+    flag(None, "tui", "Search interactively in a full-screen view"),
+    flag(
+        Some('I'),
+        "interactive",
+        "Prompt for one query after another",
+    ),
+    valued(
+        "git-rev",
+        "REV",
+        "Search files as they were at this revision",
+    ),
+    flag(None, "changed", "Search only files git sees as changed"),
+    valued(
+        "changed-since",
+        "REV",
+        "Search only files changed since this revision",
+    ),
+    flag(None, "no-ignore", "Don't follow any ignore files"),
+    flag(
+        None,
+        "no-ignore-vcs",
+        "Don't follow .gitignore files, and search .git",
+    ),
+    flag(
+        None,
+        "no-ignore-global",
+        "Don't follow the user's own ignore file",
+    ),
+    flag(
+        Some('L'),
+        "follow-links",
+        "Follow symlinks when searching recursively",
+    ),
+    flag(
+        None,
+        "no-sniff",
+        "Search files that look like images, audio, video or executables",
+    ),
+    choice(
+        "devices",
+        &["skip", "read"],
+        "Skip named pipes, sockets and devices, or read them",
+    ),
+    choice(
+        "directories",
+        &["error", "skip", "recurse"],
+        "What to do with a directory given without -r",
+    ),
+    valued("serve", "ADDRESS", "Answer JSON requests on a socket"),
+    choice(
+        "completions",
+        &["bash", "zsh", "fish", "powershell"],
+        "Print a shell completion script",
+    ),
+];
+
+/// Writes the completion script for `shell`.
+pub(crate) fn script(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash(),
+        Shell::Zsh => zsh(),
+        Shell::Fish => fish(),
+        Shell::PowerShell => powershell(),
+    }
+}
+
+/// Every spelling of every flag.
+fn names() -> impl Iterator<Item = String> {
+    FLAGS.iter().flat_map(names_of)
+}
+
+fn bash() -> String {
+    let mut script = String::from(
+        "_minigrep() {\n    local cur=\"${COMP_WORDS[COMP_CWORD]}\"\n    \
+         local prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n    case \"$prev\" in\n",
+    );
+    for flag in FLAGS.iter().filter(|flag| flag.value.is_some()) {
+        let names: Vec<String> = names_of(flag).collect();
+        let reply = if flag.choices.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); ",
+                flag.choices.join(" ")
+            )
+        };
+        let _ = writeln!(script, "        {}) {reply}return ;;", names.join("|"));
+    }
+    let subcommands: Vec<&str> = SUBCOMMANDS.iter().map(|(name, _)| *name).collect();
+    let _ = write!(
+        script,
+        "    esac\n    if [[ \"$cur\" == -* ]]; then\n        \
+         COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n    \
+         elif [[ $COMP_CWORD -eq 1 ]]; then\n        \
+         COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n    fi\n}}\n\
+         complete -o default -F _minigrep minigrep\n",
+        names().collect::<Vec<_>>().join(" "),
+        subcommands.join(" "),
+    );
+    script
+}
+
+fn zsh() -> String {
+    let mut script = String::from("#compdef minigrep\n\n_arguments -s \\\n");
+    for flag in FLAGS {
+        let help = flag.help.replace('\'', "'\\''").replace(['[', ']'], "");
+        let value = match (flag.value, flag.choices) {
+            (None, _) => String::new(),
+            (Some(value), []) => format!(":{}:", value.to_lowercase()),
+            (Some(value), choices) => format!(":{}:({})", value.to_lowercase(), choices.join(" ")),
+        };
+        for name in names_of(flag) {
+            let _ = writeln!(script, "  '{name}[{help}]{value}' \\");
+        }
+    }
+    script.push_str("  '*:file:_files'\n");
+    script
+}
+
+fn fish() -> String {
+    let mut script = String::new();
+    for (name, help) in SUBCOMMANDS {
+        let _ = writeln!(
+            script,
+            "complete -c minigrep -n __fish_use_subcommand -a {name} -d '{help}'"
+        );
+    }
+    for flag in FLAGS {
+        let mut line = String::from("complete -c minigrep");
+        if let Some(short) = flag.short {
+            let _ = write!(line, " -s {short}");
+        }
+        if let Some(long) = flag.long {
+            let _ = write!(line, " -l {long}");
+        }
+        if flag.value.is_some() {
+            line.push_str(" -r");
+        }
+        if !flag.choices.is_empty() {
+            let _ = write!(line, " -f -a '{}'", flag.choices.join(" "));
+        }
+        let _ = writeln!(script, "{line} -d '{}'", flag.help.replace('\'', "\\'"));
+    }
+    script
+}
+
+fn powershell() -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+    let mut script = String::from(
+        "Register-ArgumentCompleter -Native -CommandName minigrep -ScriptBlock {\n    \
+         param($wordToComplete, $commandAst, $cursorPosition)\n    $choices = @{\n",
+    );
+    for flag in FLAGS.iter().filter(|flag| !flag.choices.is_empty()) {
+        let choices: Vec<String> = flag.choices.iter().map(|c| quote(c)).collect();
+        for name in names_of(flag) {
+            let _ = writeln!(
+                script,
+                "        {} = @({})",
+                quote(&name),
+                choices.join(", ")
+            );
+        }
+    }
+    script.push_str(
+        "    }\n    $before = @($commandAst.CommandElements | \
+         Where-Object { $_.Extent.EndOffset -lt $cursorPosition })\n    \
+         $previous = if ($before.Count -gt 0) { $before[-1].ToString() } else { '' }\n    \
+         if ($choices.ContainsKey($previous)) {\n        \
+         $choices[$previous] | Where-Object { $_ -like \"$wordToComplete*\" } | ForEach-Object {\n            \
+         [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)\n        \
+         }\n        return\n    }\n    $flags = @(\n",
+    );
+    for (name, help) in SUBCOMMANDS {
+        let _ = writeln!(script, "        @({}, {}),", quote(name), quote(help));
+    }
+    for flag in FLAGS {
+        for name in names_of(flag) {
+            let _ = writeln!(script, "        @({}, {}),", quote(&name), quote(flag.help));
+        }
+    }
+    script.push_str(
+        "        $null\n    )\n    $flags | Where-Object { $_ -and $_[0] -like \"$wordToComplete*\" } | \
+         ForEach-Object {\n        \
+         [System.Management.Automation.CompletionResult]::new($_[0], $_[0], 'ParameterName', $_[1])\n    \
+         }\n}\n",
+    );
+    script
+}
+
+/// The short and long spellings of `flag`.
+fn names_of(flag: &Flag) -> impl Iterator<Item = String> {
+    let short = flag.short.map(|c| format!("-{c}"));
+    let long = flag.long.map(|long| format!("--{long}"));
+    short.into_iter().chain(long)
+}
@@ -0,0 +1,66 @@
+//! Searching `http://` and `https://` URLs as they download, using `curl`
+//! for the fetching so redirects, TLS and compression are taken care of.
+
+use std::io::{self, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+/// Whether the path argument names a URL rather than a file.
+pub(crate) fn is_url(path: &str) -> bool {
+    let scheme = path
+        .split_once("://")
+        .map(|(scheme, _)| scheme.to_ascii_lowercase());
+    matches!(scheme.as_deref(), Some("http" | "https"))
+}
+
+/// The body of a URL, read as `curl` receives it.
+pub(crate) struct Download {
+    curl: Child,
+    body: ChildStdout,
+}
+
+impl Download {
+    pub(crate) fn start(url: &str) -> io::Result<Download> {
+        // -f fails on HTTP errors instead of printing the error page, and
+        // curl reports what went wrong on stderr itself.
+        let mut curl = Command::new("curl")
+            .args([
+                "--silent",
+                "--show-error",
+                "--fail",
+                "--location",
+                "--compressed",
+                "--",
+            ])
+            .arg(url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("couldn't run curl: {e}")))?;
+        let body = curl
+            .stdout
+            .take()
+            .ok_or_else(|| io::Error::other("no output from curl"))?;
+        Ok(Download { curl, body })
+    }
+}
+
+impl Read for Download {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.body.read(buf)?;
+        // A body cut short by a failed download shouldn't look complete.
+        if n == 0 && !buf.is_empty() {
+            let status = self.curl.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("download failed: curl {status}")));
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        let _ = self.curl.kill();
+        let _ = self.curl.wait();
+    }
+}
@@ -0,0 +1,66 @@
+//! What a search reports as it goes, for programs that use minigrep as a
+//! library and want to show results their own way. [`search_events`]
+//! runs a search that hands each [`Event`] to a [`Handler`] in place of
+//! printing it.
+//!
+//! [`search_events`]: crate::search_events
+
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+/// Something that happened during a search.
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// A file is about to be searched.
+    FileStart(&'a Path),
+    Match(MatchData<'a>),
+    /// A line around a match, asked for with `-A`, `-B` or `-C`.
+    ContextLine(ContextLine<'a>),
+    /// A file has been searched.
+    FileEnd(FileStats<'a>),
+    /// Everything has been searched.
+    SearchEnd(Summary),
+}
+
+/// A line that matched.
+#[derive(Debug)]
+pub struct MatchData<'a> {
+    pub path: &'a Path,
+    pub line_number: usize,
+    pub line: &'a str,
+    /// The byte ranges of the matches in `line`, which are what `-o`
+    /// prints.
+    pub spans: Vec<Range<usize>>,
+}
+
+#[derive(Debug)]
+pub struct ContextLine<'a> {
+    pub path: &'a Path,
+    pub line_number: usize,
+    pub line: &'a str,
+}
+
+#[derive(Debug)]
+pub struct FileStats<'a> {
+    pub path: &'a Path,
+    /// How many lines matched.
+    pub matched_lines: usize,
+}
+
+/// The totals for the whole search, as `--stats` prints them.
+#[derive(Debug)]
+pub struct Summary {
+    pub files_searched: usize,
+    pub files_matched: usize,
+    pub matched_lines: usize,
+    pub elapsed: Duration,
+}
+
+/// Receives the events of a search, in the order they happen.
+pub trait Handler {
+    fn handle(&mut self, event: Event<'_>);
+}
+
+/// How the parts of a search pass an event on to the [`Handler`].
+pub(crate) type Emit<'a> = &'a dyn Fn(Event<'_>);
@@ -0,0 +1,92 @@
+//! Wildcards in the path argument, expanded the way a Unix shell would,
+//! for the Windows shells that pass `*.log` through as it is.
+//!
+//! Each component of the path can use the glob syntax of `--glob-pattern`.
+//! As in a shell, `*` and `?` don't match a leading `.` unless the pattern
+//! starts with one too. Windows matches names without regard to case, as
+//! its file systems do.
+
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+use crate::matcher;
+use crate::regex::Regex;
+use crate::vfs::FileSystem;
+
+/// Everything in `fs` that `pattern` matches, in order, or `None` if it has
+/// no wildcards or names something that exists as it is.
+pub(crate) fn expand(fs: &dyn FileSystem, pattern: &Path) -> Option<Vec<PathBuf>> {
+    if fs.metadata(pattern).is_ok() || !pattern.components().any(is_wildcard) {
+        return None;
+    }
+    let mut matches = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let glob = match component {
+            Component::Normal(name) if is_wildcard(component) => name.to_str(),
+            _ => None,
+        };
+        let Some(name) = glob.and_then(Name::new) else {
+            for path in &mut matches {
+                path.push(component);
+            }
+            continue;
+        };
+        matches = matches
+            .iter()
+            .flat_map(|dir| name.entries(fs, dir))
+            .collect();
+    }
+    // A name after the last wildcard may not be there in every match.
+    matches.retain(|path| fs.metadata(path).is_ok());
+    matches.sort();
+    Some(matches)
+}
+
+fn is_wildcard(component: Component) -> bool {
+    let Component::Normal(name) = component else {
+        return false;
+    };
+    name.to_str()
+        .is_some_and(|name| name.contains(['*', '?', '[']))
+}
+
+/// One component of a path pattern.
+struct Name {
+    regex: Regex,
+    /// Whether the pattern starts with `.`, so it can match hidden names.
+    hidden: bool,
+}
+
+impl Name {
+    fn new(glob: &str) -> Option<Name> {
+        let pattern = format!("^(?:{})$", matcher::glob_to_regex(glob));
+        Some(Name {
+            regex: Regex::new(&pattern, cfg!(windows)).ok()?,
+            hidden: glob.starts_with('.'),
+        })
+    }
+
+    fn matches(&self, name: &OsStr) -> bool {
+        name.to_str().is_some_and(|name| {
+            (self.hidden || !name.starts_with('.')) && self.regex.find_at(name, 0).is_some()
+        })
+    }
+
+    /// The entries of `dir` whose names match, which is none if it isn't a
+    /// directory that can be read.
+    fn entries(&self, fs: &dyn FileSystem, dir: &Path) -> Vec<PathBuf> {
+        let listing = match dir.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => dir,
+        };
+        let Ok(entries) = fs.read_dir(listing) else {
+            return Vec::new();
+        };
+        entries
+            .iter()
+            .filter_map(|entry| entry.path.file_name())
+            .filter(|name| self.matches(name))
+            .map(|name| dir.join(name))
+            .collect()
+    }
+}
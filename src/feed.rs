@@ -0,0 +1,68 @@
+//! Searching text that arrives a piece at a time, from a socket or a
+//! decompressor, say, where a line can be split between two pieces. The
+//! caller feeds in the pieces as they come and is told of each matching
+//! line as soon as it's complete.
+
+use std::error::Error;
+
+use crate::SearchOptions;
+use crate::matcher::Matcher;
+
+/// Searches text fed to it in chunks of any size.
+pub struct ChunkSearcher {
+    matcher: Matcher,
+    /// The start of a line whose end hasn't arrived yet.
+    partial: Vec<u8>,
+    /// The number of the last complete line.
+    line_number: usize,
+}
+
+impl ChunkSearcher {
+    pub fn new(search: &SearchOptions) -> Result<ChunkSearcher, Box<dyn Error>> {
+        Ok(ChunkSearcher {
+            matcher: Matcher::any(&search.queries, search.ignore_case, search.engine)?,
+            partial: Vec::new(),
+            line_number: 0,
+        })
+    }
+
+    /// Searches the lines `chunk` completes, calling `found` with the
+    /// number and text of each that matches. Whatever follows the last
+    /// `\n` is kept for the next chunk to finish.
+    pub fn feed(&mut self, chunk: &[u8], mut found: impl FnMut(usize, &str)) {
+        let mut rest = chunk;
+        while let Some(newline) = rest.iter().position(|&b| b == b'\n') {
+            let line = match self.partial.is_empty() {
+                true => &rest[..newline],
+                false => {
+                    self.partial.extend_from_slice(&rest[..newline]);
+                    &self.partial[..]
+                }
+            };
+            self.line_number += 1;
+            check(&self.matcher, self.line_number, line, &mut found);
+            self.partial.clear();
+            rest = &rest[newline + 1..];
+        }
+        self.partial.extend_from_slice(rest);
+    }
+
+    /// Searches the last line, which needn't end in `\n`, once there's
+    /// nothing more to feed.
+    pub fn finish(mut self, mut found: impl FnMut(usize, &str)) {
+        if !self.partial.is_empty() {
+            self.line_number += 1;
+            check(&self.matcher, self.line_number, &self.partial, &mut found);
+        }
+    }
+}
+
+/// Calls `found` if `line` matches, without the `\r` of a `\r\n` and with
+/// anything that isn't UTF-8 replaced.
+fn check(matcher: &Matcher, line_number: usize, line: &[u8], found: &mut impl FnMut(usize, &str)) {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let line = String::from_utf8_lossy(line);
+    if matcher.is_match(&line) {
+        found(line_number, &line);
+    }
+}
@@ -0,0 +1,87 @@
+//! Reading lines as they're appended to a file, for `--follow`.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::walk::identity;
+
+/// How long to wait before looking for new lines again once caught up.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The end of a file that's still being written to.
+///
+/// When the file is truncated, or replaced by a new file at the same path as
+/// log rotation does, reading starts over from the top of whatever is there
+/// now. Replacement is only noticed on Unix; elsewhere just truncation is.
+pub(crate) struct Tail {
+    path: PathBuf,
+    reader: BufReader<File>,
+    identity: Option<(u64, u64)>,
+    position: u64,
+    line_number: usize,
+    /// The line being read, which may still be waiting for its newline.
+    line: String,
+}
+
+impl Tail {
+    /// Starts reading `path` from byte `position`, which is taken to be the
+    /// start of the line after `line_number`.
+    pub(crate) fn new(path: &Path, position: u64, line_number: usize) -> io::Result<Tail> {
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(position))?;
+        Ok(Tail {
+            path: path.to_path_buf(),
+            identity: identity(&file.metadata()?),
+            reader: BufReader::new(file),
+            position,
+            line_number,
+            line: String::new(),
+        })
+    }
+
+    /// Returns the next complete line and its number, or `None` if no whole
+    /// line has been added since the last call.
+    pub(crate) fn next_line(&mut self) -> io::Result<Option<(usize, &str)>> {
+        if self.line.ends_with('\n') {
+            self.line.clear();
+        }
+        loop {
+            let read = self.reader.read_line(&mut self.line)?;
+            self.position += read as u64;
+            if self.line.ends_with('\n') {
+                self.line_number += 1;
+                let line = &self.line[..self.line.len() - 1];
+                return Ok(Some((
+                    self.line_number,
+                    line.strip_suffix('\r').unwrap_or(line),
+                )));
+            }
+            if read == 0 && !self.restart_if_replaced()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Reopens the file from the top if it's been truncated or replaced,
+    /// returning whether it was.
+    fn restart_if_replaced(&mut self) -> io::Result<bool> {
+        // Between the old file being moved away and the new one being
+        // created there's nothing at the path; keep waiting on the old one.
+        let Ok(metadata) = fs::metadata(&self.path) else {
+            return Ok(false);
+        };
+        if identity(&metadata) == self.identity && metadata.len() >= self.position {
+            return Ok(false);
+        }
+
+        let file = File::open(&self.path)?;
+        self.identity = identity(&file.metadata()?);
+        self.reader = BufReader::new(file);
+        self.position = 0;
+        self.line_number = 0;
+        self.line.clear();
+        Ok(true)
+    }
+}
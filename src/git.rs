@@ -0,0 +1,154 @@
+//! Reading files as they were at a git revision, for `--git-rev`, by asking
+//! the `git` command rather than looking at the working tree.
+
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Output, Stdio};
+
+/// A revision of the repository holding the searched path, with a
+/// `git cat-file --batch` process kept running to read files from it.
+pub(crate) struct Revision {
+    pub(crate) name: String,
+    /// The directory git is run in: the searched path, or its parent if
+    /// that's a file.
+    dir: PathBuf,
+    batch: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+}
+
+impl Revision {
+    pub(crate) fn open(name: &str, path: &Path) -> io::Result<Revision> {
+        let dir = match path.parent() {
+            Some(parent) if !path.is_dir() && !parent.as_os_str().is_empty() => parent,
+            Some(_) if !path.is_dir() => Path::new("."),
+            _ => path,
+        };
+        let mut batch = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["cat-file", "--batch", "--end-of-options"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let input = batch
+            .stdin
+            .take()
+            .ok_or_else(|| io::Error::other("no stdin"))?;
+        let output = batch
+            .stdout
+            .take()
+            .ok_or_else(|| io::Error::other("no stdout"))?;
+        Ok(Revision {
+            name: name.to_string(),
+            dir: dir.to_path_buf(),
+            batch,
+            input,
+            output: BufReader::new(output),
+        })
+    }
+
+    /// Lists the files under `path` in this revision, in git's order, as
+    /// paths that [`Revision::read`] accepts.
+    pub(crate) fn files(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let within = if path.is_dir() {
+            Path::new(".")
+        } else {
+            Path::new(path.file_name().unwrap_or(path.as_os_str()))
+        };
+        let listing = git(Command::new("git")
+            .arg("-C")
+            .arg(&self.dir)
+            .args(["ls-tree", "-r", "-z", "--end-of-options", &self.name, "--"])
+            .arg(within))?;
+        // Each entry is `MODE TYPE OBJECT\tPATH`; submodules are commits
+        // and symbolic links have mode 120000, and neither is searched.
+        let files = listing
+            .split(|&byte| byte == 0)
+            .filter_map(|entry| {
+                let entry = std::str::from_utf8(entry).ok()?;
+                let (meta, name) = entry.split_once('\t')?;
+                let mut meta = meta.split(' ');
+                let mode = meta.next()?;
+                (meta.next()? == "blob" && mode != "120000").then(|| self.dir.join(name))
+            })
+            .collect();
+        Ok(files)
+    }
+
+    /// Reads the file at `path`, one of those [`Revision::files`] listed.
+    pub(crate) fn read(&mut self, path: &Path) -> io::Result<Vec<u8>> {
+        let relative = path.strip_prefix(&self.dir).unwrap_or(path);
+        writeln!(self.input, "{}:./{}", self.name, relative.display())?;
+        self.input.flush()?;
+
+        let mut header = String::new();
+        self.output.read_line(&mut header)?;
+        let mut fields = header.split_whitespace();
+        let (kind, size) = (fields.nth(1), fields.next());
+        let size = match (kind, size.and_then(|size| size.parse::<usize>().ok())) {
+            (Some("blob"), Some(size)) => size,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "not in that revision",
+                ));
+            }
+        };
+        // The contents are followed by a newline.
+        let mut contents = vec![0; size + 1];
+        self.output.read_exact(&mut contents)?;
+        contents.pop();
+        Ok(contents)
+    }
+}
+
+impl Drop for Revision {
+    fn drop(&mut self) {
+        let _ = self.batch.kill();
+        let _ = self.batch.wait();
+    }
+}
+
+/// Runs a git command and returns its output, or what it said on failure.
+fn git(command: &mut Command) -> io::Result<Vec<u8>> {
+    let Output {
+        status,
+        stdout,
+        stderr,
+    } = command.stderr(Stdio::piped()).output()?;
+    if !status.success() {
+        let message = String::from_utf8_lossy(&stderr);
+        return Err(io::Error::other(format!("git: {}", message.trim())));
+    }
+    Ok(stdout)
+}
+
+/// For `--changed`, the files under `dir` that differ from `since` (or
+/// from the last commit) or that git doesn't track yet, relative to `dir`.
+/// Ignored files aren't included.
+pub(crate) fn changed(dir: &Path, since: Option<&str>) -> io::Result<HashSet<PathBuf>> {
+    let modified = git(Command::new("git").arg("-C").arg(dir).args([
+        "diff",
+        "--name-only",
+        "--relative",
+        "-z",
+        "--end-of-options",
+        since.unwrap_or("HEAD"),
+        "--",
+    ]))?;
+    let untracked = git(Command::new("git").arg("-C").arg(dir).args([
+        "ls-files",
+        "--others",
+        "--exclude-standard",
+        "-z",
+    ]))?;
+    Ok(modified
+        .split(|&byte| byte == 0)
+        .chain(untracked.split(|&byte| byte == 0))
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| std::str::from_utf8(name).ok())
+        .map(PathBuf::from)
+        .collect())
+}
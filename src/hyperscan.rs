@@ -0,0 +1,188 @@
+//! `--engine hyperscan`: patterns compiled into a Hyperscan (or
+//! Vectorscan) database and scanned with the library's SIMD matcher. Only
+//! built with the `hyperscan` feature, which links `libhs`.
+//!
+//! All of a search's queries go in one database, so each line is scanned
+//! once whatever the number of queries. Hyperscan reports every place a
+//! pattern matches rather than picking one the way a backtracking engine
+//! does, so a query's match is the leftmost and, among those starting
+//! there, the longest.
+
+use std::ffi::{CString, c_char, c_int, c_uint, c_ulonglong, c_void};
+use std::ops::Range;
+use std::ptr;
+
+use crate::regex::Error;
+
+const HS_SUCCESS: c_int = 0;
+const HS_FLAG_CASELESS: c_uint = 1;
+const HS_FLAG_UTF8: c_uint = 32;
+const HS_FLAG_UCP: c_uint = 64;
+const HS_FLAG_SOM_LEFTMOST: c_uint = 256;
+const HS_MODE_BLOCK: c_uint = 1;
+
+/// Only ever handed back to the library to free.
+#[repr(C)]
+struct CompileError {
+    _message: *const c_char,
+    _expression: c_int,
+}
+
+type MatchHandler = unsafe extern "C" fn(
+    id: c_uint,
+    from: c_ulonglong,
+    to: c_ulonglong,
+    flags: c_uint,
+    context: *mut c_void,
+) -> c_int;
+
+#[link(name = "hs")]
+unsafe extern "C" {
+    fn hs_compile_multi(
+        expressions: *const *const c_char,
+        flags: *const c_uint,
+        ids: *const c_uint,
+        elements: c_uint,
+        mode: c_uint,
+        platform: *const c_void,
+        database: *mut *mut c_void,
+        error: *mut *mut CompileError,
+    ) -> c_int;
+    fn hs_free_compile_error(error: *mut CompileError) -> c_int;
+    fn hs_free_database(database: *mut c_void) -> c_int;
+    fn hs_alloc_scratch(database: *const c_void, scratch: *mut *mut c_void) -> c_int;
+    fn hs_free_scratch(scratch: *mut c_void) -> c_int;
+    fn hs_scan(
+        database: *const c_void,
+        data: *const c_char,
+        length: c_uint,
+        flags: c_uint,
+        scratch: *mut c_void,
+        on_match: MatchHandler,
+        context: *mut c_void,
+    ) -> c_int;
+}
+
+/// A compiled Hyperscan database, with the scratch space scanning needs.
+pub(crate) struct Hyperscan {
+    database: *mut c_void,
+    scratch: *mut c_void,
+}
+
+/// A match of one of a database's patterns: the pattern's index in the
+/// list it was compiled from, and the span.
+pub(crate) type Match = (usize, Range<usize>);
+
+impl Hyperscan {
+    /// Compiles `patterns` into one database, each identified by its index.
+    pub(crate) fn new(patterns: &[&str], ignore_case: bool) -> Result<Hyperscan, Error> {
+        let invalid = |message| Error {
+            message,
+            position: None,
+        };
+        let owned = patterns
+            .iter()
+            .map(|pattern| CString::new(*pattern))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid("the pattern can't contain a NUL byte"))?;
+        let expressions: Vec<*const c_char> = owned.iter().map(|e| e.as_ptr()).collect();
+        let mut flag = HS_FLAG_UTF8 | HS_FLAG_UCP | HS_FLAG_SOM_LEFTMOST;
+        if ignore_case {
+            flag |= HS_FLAG_CASELESS;
+        }
+        let elements =
+            c_uint::try_from(patterns.len()).map_err(|_| invalid("too many patterns"))?;
+        let flags = vec![flag; patterns.len()];
+        let ids: Vec<c_uint> = (0..elements).collect();
+        let mut database = ptr::null_mut();
+        let mut error = ptr::null_mut();
+        // SAFETY: the three arrays each hold `elements` items, the
+        // expressions are NUL-terminated and outlive the call, a null
+        // platform means the current one, and the outputs are valid places
+        // to write to.
+        unsafe {
+            let result = hs_compile_multi(
+                expressions.as_ptr(),
+                flags.as_ptr(),
+                ids.as_ptr(),
+                elements,
+                HS_MODE_BLOCK,
+                ptr::null(),
+                &mut database,
+                &mut error,
+            );
+            if result != HS_SUCCESS {
+                hs_free_compile_error(error);
+                return Err(invalid(
+                    "the pattern isn't valid for Hyperscan, or uses something it doesn't support",
+                ));
+            }
+            let mut scratch = ptr::null_mut();
+            if hs_alloc_scratch(database, &mut scratch) != HS_SUCCESS {
+                hs_free_database(database);
+                return Err(invalid("Hyperscan couldn't allocate scratch space"));
+            }
+            Ok(Hyperscan { database, scratch })
+        }
+    }
+
+    /// Every match of every pattern in `text`: for each place a pattern's
+    /// match ends, the one starting leftmost. A text too long to scan has
+    /// none.
+    pub(crate) fn scan(&self, text: &str) -> Vec<Match> {
+        let mut matches = Vec::new();
+        let Ok(length) = c_uint::try_from(text.len()) else {
+            return matches;
+        };
+        // SAFETY: the database and scratch live until `drop`, the scratch is
+        // only used by one scan at a time since `Hyperscan` isn't `Sync`,
+        // and `matches` outlives the scan that writes to it.
+        unsafe {
+            hs_scan(
+                self.database,
+                text.as_ptr().cast(),
+                length,
+                0,
+                self.scratch,
+                on_match,
+                (&mut matches as *mut Vec<Match>).cast(),
+            );
+        }
+        matches
+    }
+}
+
+/// The leftmost-longest match of pattern `id` among what
+/// [`Hyperscan::scan`] found, starting at or after byte `start`. The whole
+/// line was scanned, so `^` and `\b` saw what's before `start`.
+pub(crate) fn find_at(matches: &[Match], id: usize, start: usize) -> Option<Range<usize>> {
+    matches
+        .iter()
+        .filter(|(pattern, span)| *pattern == id && span.start >= start)
+        .map(|(_, span)| span.clone())
+        .min_by_key(|span| (span.start, usize::MAX - span.end))
+}
+
+/// Collects each match reported.
+unsafe extern "C" fn on_match(
+    id: c_uint,
+    from: c_ulonglong,
+    to: c_ulonglong,
+    _flags: c_uint,
+    context: *mut c_void,
+) -> c_int {
+    // SAFETY: `context` is the `Vec` that `scan` passed in.
+    let matches = unsafe { &mut *context.cast::<Vec<Match>>() };
+    matches.push((id as usize, from as usize..to as usize));
+    0
+}
+
+impl Drop for Hyperscan {
+    fn drop(&mut self) {
+        // SAFETY: both came from the library in `new` and aren't used again.
+        unsafe {
+            hs_free_scratch(self.scratch);
+            hs_free_database(self.database);
+        }
+    }
+}
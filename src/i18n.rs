@@ -0,0 +1,216 @@
+//! Translations of the messages people see most: what went wrong with the
+//! command line, and why a file couldn't be searched.
+//!
+//! Messages are looked up by their English text, so the rest of minigrep
+//! keeps writing plain strings and only the places that print them pass
+//! them through [`Lang::translate`]. Anything not in the catalog is printed
+//! in English.
+
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::io;
+
+/// The language messages are printed in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Lang {
+    #[default]
+    English,
+    German,
+    Spanish,
+    French,
+}
+
+/// Each row is a message in English, German, Spanish and French. `{}` marks
+/// where [`Lang::format`] puts a value.
+pub(crate) const CATALOG: &[[&str; 4]] = &[
+    [
+        "Problem parsing arguments",
+        "Fehler in den Argumenten",
+        "Error en los argumentos",
+        "Erreur dans les arguments",
+    ],
+    [
+        "Application error",
+        "Anwendungsfehler",
+        "Error de la aplicación",
+        "Erreur de l'application",
+    ],
+    [
+        "Search timed out after {}s",
+        "Suche nach {} s abgebrochen",
+        "La búsqueda se interrumpió tras {} s",
+        "Recherche interrompue après {} s",
+    ],
+    [
+        "stopped after {} matches (--max-total)",
+        "nach {} Treffern angehalten (--max-total)",
+        "detenido tras {} coincidencias (--max-total)",
+        "arrêté après {} correspondances (--max-total)",
+    ],
+    [
+        "no matches found for \"{}\"",
+        "keine Treffer für \"{}\"",
+        "no hay coincidencias para \"{}\"",
+        "aucune correspondance pour \"{}\"",
+    ],
+    [
+        "Didn't get a query string",
+        "Kein Suchbegriff angegeben",
+        "Falta el texto a buscar",
+        "Aucun texte à rechercher",
+    ],
+    [
+        "Didn't get a file path",
+        "Kein Dateipfad angegeben",
+        "Falta la ruta del archivo",
+        "Aucun chemin de fichier",
+    ],
+    [
+        "Didn't get a replacement",
+        "Kein Ersatztext angegeben",
+        "Falta el texto de reemplazo",
+        "Aucun texte de remplacement",
+    ],
+    [
+        "the query is empty, and would match every line",
+        "der Suchbegriff ist leer und würde auf jede Zeile passen",
+        "el texto a buscar está vacío y coincidiría con todas las líneas",
+        "le texte à rechercher est vide et correspondrait à toutes les lignes",
+    ],
+    [
+        "-A, -B and -C need a number of lines",
+        "-A, -B und -C brauchen eine Anzahl Zeilen",
+        "-A, -B y -C necesitan un número de líneas",
+        "-A, -B et -C attendent un nombre de lignes",
+    ],
+    [
+        "--timeout needs a number of seconds",
+        "--timeout braucht eine Anzahl Sekunden",
+        "--timeout necesita un número de segundos",
+        "--timeout attend un nombre de secondes",
+    ],
+    [
+        "--color needs one of: auto, always, never",
+        "--color braucht eines von: auto, always, never",
+        "--color necesita uno de: auto, always, never",
+        "--color attend l'un de : auto, always, never",
+    ],
+    [
+        "--lang needs one of: en, de, es, fr",
+        "--lang braucht eines von: en, de, es, fr",
+        "--lang necesita uno de: en, de, es, fr",
+        "--lang attend l'un de : en, de, es, fr",
+    ],
+    [
+        "--follow needs a single file, not -r",
+        "--follow braucht eine einzelne Datei, nicht -r",
+        "--follow necesita un solo archivo, no -r",
+        "--follow attend un seul fichier, pas -r",
+    ],
+    [
+        "--in-place only works with replace",
+        "--in-place geht nur mit replace",
+        "--in-place solo funciona con replace",
+        "--in-place ne fonctionne qu'avec replace",
+    ],
+    // Stand-ins for the operating system's own messages, which are in
+    // English whatever the locale.
+    [
+        "file not found",
+        "Datei nicht gefunden",
+        "archivo no encontrado",
+        "fichier introuvable",
+    ],
+    [
+        "permission denied",
+        "Zugriff verweigert",
+        "permiso denegado",
+        "permission refusée",
+    ],
+    [
+        "is a directory",
+        "ist ein Verzeichnis",
+        "es un directorio",
+        "est un répertoire",
+    ],
+];
+
+impl Lang {
+    /// Reads a language code like `de`, or a locale like `de_DE.UTF-8`.
+    pub fn parse(code: &str) -> Option<Lang> {
+        let language = code.split(['_', '-', '.', '@']).next()?;
+        match language.to_ascii_lowercase().as_str() {
+            "en" | "c" | "posix" => Some(Lang::English),
+            "de" => Some(Lang::German),
+            "es" => Some(Lang::Spanish),
+            "fr" => Some(Lang::French),
+            _ => None,
+        }
+    }
+
+    /// The language of the first of `LC_ALL`, `LC_MESSAGES` and `LANG`
+    /// that's set, as POSIX orders them, or English.
+    pub fn from_env() -> Lang {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Lang::parse(&value))
+            .unwrap_or_default()
+    }
+
+    /// The language asked for by `--lang` in `args`, or by the environment,
+    /// for reporting a command line that couldn't be read into a
+    /// [`Config`](crate::Config).
+    pub fn from_args(args: &[OsString]) -> Lang {
+        let mut lang = None;
+        let mut no_env = false;
+        let mut args = args.iter().map(|arg| arg.to_str().unwrap_or_default());
+        while let Some(arg) = args.next() {
+            match arg {
+                "--lang" => lang = args.next().and_then(Lang::parse),
+                "--no-env" => no_env = true,
+                arg => {
+                    if let Some(code) = arg.strip_prefix("--lang=") {
+                        lang = Lang::parse(code);
+                    }
+                }
+            }
+        }
+        lang.unwrap_or_else(|| match no_env {
+            true => Lang::English,
+            false => Lang::from_env(),
+        })
+    }
+
+    /// `message` in this language, or as it is if there's no translation.
+    pub fn translate(self, message: &str) -> &str {
+        CATALOG
+            .iter()
+            .find(|row| row[0] == message)
+            .map_or(message, |row| row[self as usize])
+    }
+
+    /// Translates `message` and puts `value` in place of its `{}`.
+    pub fn format(self, message: &str, value: impl Display) -> String {
+        self.translate(message)
+            .replacen("{}", &value.to_string(), 1)
+    }
+
+    /// Describes `e` in this language. The commonest I/O errors get a
+    /// translated description in place of the system's English one.
+    pub fn describe(self, e: &(dyn Error + 'static)) -> String {
+        let io_message = e.downcast_ref::<io::Error>().and_then(|e| match e.kind() {
+            io::ErrorKind::NotFound => Some("file not found"),
+            io::ErrorKind::PermissionDenied => Some("permission denied"),
+            io::ErrorKind::IsADirectory => Some("is a directory"),
+            _ => None,
+        });
+        match io_message {
+            Some(message) if self != Lang::English => self.translate(message).to_string(),
+            _ => self.translate(&e.to_string()).to_string(),
+        }
+    }
+}
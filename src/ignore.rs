@@ -0,0 +1,300 @@
+//! Ignore files: `.gitignore`, and `.ignore` and `.rgignore` in the same
+//! syntax for projects outside git or rules only search tools should follow.
+//!
+//! Each directory's ignore files apply to everything beneath it, with the
+//! rules of deeper directories taking precedence, and within one directory
+//! later rules over earlier ones, so `!pattern` can re-include a file. An
+//! ignored directory isn't descended into at all.
+//!
+//! The user's own ignore file, `~/.config/minigrep/ignore`, applies to every
+//! recursive search as if it were in the directory searched, below all the
+//! others.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+use crate::Config;
+use crate::vfs::{self, FileSystem};
+
+/// The ignore files read in every directory, later ones taking precedence.
+const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", ".rgignore"];
+
+/// Which ignore rules a walk follows.
+pub(crate) struct Ignores {
+    files: Vec<&'static str>,
+    /// The rules from the user's ignore file.
+    global: Vec<Rule>,
+}
+
+impl Ignores {
+    /// Follows every ignore file found in the walked tree, and the user's.
+    pub(crate) fn all() -> Ignores {
+        Ignores::reading(global_file().as_deref())
+    }
+
+    /// Follows the ignore files the `--no-ignore` options leave.
+    pub(crate) fn new(config: &Config) -> Ignores {
+        // Where the user's file lives depends on the environment.
+        let global = match config.input.no_ignore_global || config.no_env {
+            true => None,
+            false => global_file(),
+        };
+        let mut ignores = Ignores::reading(global.as_deref());
+        ignores.files.retain(|&name| match name {
+            ".gitignore" => !config.input.no_ignore_vcs,
+            _ => !config.input.no_ignore,
+        });
+        ignores
+    }
+
+    /// Follows the ignore files in the walked tree, and `global` if given.
+    pub(crate) fn reading(global: Option<&Path>) -> Ignores {
+        let global = global.and_then(|path| Some(rules(&fs::read_to_string(path).ok()?, path)));
+        Ignores {
+            files: IGNORE_FILES.to_vec(),
+            global: global.unwrap_or_default(),
+        }
+    }
+
+    /// The rules to start a walk of `root` with.
+    pub(crate) fn top(&self, root: &Path) -> Option<Rc<Level>> {
+        (!self.global.is_empty()).then(|| {
+            Rc::new(Level {
+                dir: root.to_path_buf(),
+                rules: self.global.clone(),
+                outer: None,
+            })
+        })
+    }
+
+    /// Whether the walk shouldn't go into `dir` at all. Git's own store is
+    /// never worth searching when its ignore rules are being followed.
+    pub(crate) fn skips_dir(&self, dir: &Path) -> bool {
+        self.files.contains(&".gitignore") && dir.file_name().is_some_and(|name| name == ".git")
+    }
+
+    /// The rules that apply inside `dir`: those in its own ignore files on
+    /// top of those that `outer` holds for the directories above.
+    pub(crate) fn enter(
+        &self,
+        fs: &dyn FileSystem,
+        dir: &Path,
+        outer: Option<Rc<Level>>,
+    ) -> Option<Rc<Level>> {
+        let rules: Vec<Rule> = self
+            .files
+            .iter()
+            .filter_map(|name| {
+                let path = dir.join(name);
+                Some(rules(&vfs::read_to_string(fs, &path).ok()?, &path))
+            })
+            .flatten()
+            .collect();
+        if rules.is_empty() {
+            return outer;
+        }
+        Some(Rc::new(Level {
+            dir: dir.to_path_buf(),
+            rules,
+            outer,
+        }))
+    }
+}
+
+/// The ignore rules from one directory's files, and those from above it.
+pub(crate) struct Level {
+    dir: PathBuf,
+    rules: Vec<Rule>,
+    outer: Option<Rc<Level>>,
+}
+
+/// The rule that ignored a path, for `--explain-skips`.
+pub(crate) struct Ignored {
+    /// The ignore file the rule is in.
+    pub(crate) source: Rc<Path>,
+    /// Its line in the file, counting from 1.
+    pub(crate) line: usize,
+}
+
+impl fmt::Display for Ignored {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.source.display(), self.line)
+    }
+}
+
+impl Level {
+    /// The rule that ignores `path`, somewhere under this level's
+    /// directory, if it's ignored.
+    pub(crate) fn ignored(&self, path: &Path, is_dir: bool) -> Option<Ignored> {
+        let mut level = Some(self);
+        while let Some(current) = level {
+            if let Ok(relative) = path.strip_prefix(&current.dir) {
+                let relative = slashed(relative);
+                let last = current
+                    .rules
+                    .iter()
+                    .rev()
+                    .find(|rule| rule.matches(&relative, is_dir));
+                if let Some(rule) = last {
+                    return (!rule.negated).then(|| Ignored {
+                        source: Rc::clone(&rule.source),
+                        line: rule.line,
+                    });
+                }
+            }
+            level = current.outer.as_deref();
+        }
+        None
+    }
+}
+
+/// The rules in `text`, the contents of the ignore file at `source`.
+fn rules(text: &str, source: &Path) -> Vec<Rule> {
+    let source: Rc<Path> = Rc::from(source);
+    text.lines()
+        .enumerate()
+        .filter_map(|(i, line)| Rule::parse(line, &source, i + 1))
+        .collect()
+}
+
+/// One line of an ignore file.
+#[derive(Clone)]
+struct Rule {
+    /// The glob, which always matches from the ignore file's directory;
+    /// patterns without a `/` are given a leading `**/`.
+    glob: Vec<u8>,
+    /// A `!` rule re-includes what earlier rules ignored.
+    negated: bool,
+    /// A rule ending in `/` only matches directories.
+    dir_only: bool,
+    /// Where the rule was read from, and on which line.
+    source: Rc<Path>,
+    line: usize,
+}
+
+impl Rule {
+    fn parse(line: &str, source: &Rc<Path>, number: usize) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        if pattern.is_empty() {
+            return None;
+        }
+        let glob = match pattern.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if pattern.contains('/') => pattern.to_string(),
+            None => format!("**/{pattern}"),
+        };
+        Some(Rule {
+            glob: glob.into_bytes(),
+            negated,
+            dir_only,
+            source: Rc::clone(source),
+            line: number,
+        })
+    }
+
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && glob(&self.glob, relative.as_bytes())
+    }
+}
+
+/// Where the user's ignore file is: `$XDG_CONFIG_HOME/minigrep/ignore`,
+/// falling back to `~/.config`, or `%APPDATA%\minigrep\ignore` on Windows.
+fn global_file() -> Option<PathBuf> {
+    let config = if cfg!(windows) {
+        PathBuf::from(env::var_os("APPDATA")?)
+    } else {
+        match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        }
+    };
+    Some(config.join("minigrep").join("ignore"))
+}
+
+/// `path` with its components joined by `/`, whatever the platform uses.
+fn slashed(path: &Path) -> String {
+    let parts: Vec<_> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    parts.join("/")
+}
+
+/// Matches `text` against a gitignore-style glob, where `*` and `?` don't
+/// cross a `/`, `**/` matches any number of directories and a trailing `**`
+/// matches everything.
+pub(crate) fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            glob(rest, text)
+                || (0..text.len()).any(|i| text[i] == b'/' && glob(rest, &text[i + 1..]))
+        }
+        [b'*', b'*'] => true,
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob(rest, &text[i..])),
+        [b'?', rest @ ..] => text.first().is_some_and(|&c| c != b'/') && glob(rest, &text[1..]),
+        [b'[', class @ ..] => match (text.first(), class_end(class)) {
+            (Some(&c), Some(end)) => {
+                c != b'/' && in_class(&class[..end], c) && glob(&class[end + 1..], &text[1..])
+            }
+            // An unclosed `[` is just a character.
+            (Some(&c), None) => c == b'[' && glob(class, &text[1..]),
+            (None, _) => false,
+        },
+        [b'\\', c, rest @ ..] | [c, rest @ ..] => text.first() == Some(c) && glob(rest, &text[1..]),
+    }
+}
+
+/// Where the `]` closing a character class is, a `]` straight after the `[`
+/// (or its `!`) being part of the class.
+fn class_end(class: &[u8]) -> Option<usize> {
+    let start = match class.first() {
+        Some(b'!' | b'^') => 2,
+        _ => 1,
+    };
+    class
+        .iter()
+        .skip(start)
+        .position(|&c| c == b']')
+        .map(|i| i + start)
+}
+
+fn in_class(class: &[u8], c: u8) -> bool {
+    let (negated, mut members) = match class {
+        [b'!' | b'^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+    let mut found = false;
+    while let [first, rest @ ..] = members {
+        match rest {
+            [b'-', last, rest @ ..] => {
+                found |= (*first..=*last).contains(&c);
+                members = rest;
+            }
+            _ => {
+                found |= *first == c;
+                members = rest;
+            }
+        }
+    }
+    found != negated
+}
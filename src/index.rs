@@ -0,0 +1,308 @@
+//! An on-disk trigram index, built by `minigrep index DIR`, that lets
+//! recursive searches of that directory skip files that can't match.
+//!
+//! For each file the index records its size and modification time and every
+//! three-byte sequence in its lowercased text. A file is only ruled out when
+//! it is unchanged since it was indexed and lacks one of the query's
+//! trigrams; anything new or changed is searched as usual.
+//!
+//! Updating an index only reads the files whose size or modification time
+//! has changed, so it's cheap enough to do before every search.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::ignore::Ignores;
+use crate::vfs::{self, FileSystem, Metadata};
+use crate::walk;
+
+/// The name of the index file in the indexed directory.
+pub(crate) const INDEX_FILE: &str = ".minigrep-index";
+
+const HEADER: &str = "minigrep-index 2";
+
+type Trigram = [u8; 3];
+
+#[derive(Default)]
+pub(crate) struct Index {
+    files: Vec<Entry>,
+    ids: HashMap<PathBuf, u32>,
+    /// The files containing each trigram, as indexes into `files`, sorted.
+    postings: HashMap<Trigram, Vec<u32>>,
+}
+
+struct Entry {
+    /// Relative to the indexed directory.
+    path: PathBuf,
+    stamp: Stamp,
+    /// Whether the file could be read as UTF-8 text. Other files have no
+    /// trigrams and are never ruled out, but are remembered so updates
+    /// don't read them again.
+    text: bool,
+}
+
+/// How an [`Index::update`] changed the index.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Changes {
+    pub(crate) added: usize,
+    pub(crate) changed: usize,
+    pub(crate) removed: usize,
+}
+
+impl fmt::Display for Changes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} changed, {} removed",
+            self.added, self.changed, self.removed
+        )
+    }
+}
+
+/// What a file's metadata says about whether it has changed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: u128,
+    size: u64,
+}
+
+impl Stamp {
+    fn of(metadata: &Metadata) -> Option<Stamp> {
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Stamp {
+            modified: modified.as_nanos(),
+            size: metadata.len(),
+        })
+    }
+}
+
+impl Index {
+    /// Brings the index up to date with the regular files under `root` in
+    /// `fs`, reading only those that are new or have changed.
+    pub(crate) fn update(&mut self, fs: &dyn FileSystem, root: &Path) -> Changes {
+        let mut changes = Changes::default();
+        let mut kept = Vec::new();
+        let mut fresh = Vec::new();
+        for (relative, stamp) in scan(fs, root) {
+            match self.ids.get(&relative) {
+                Some(&id) if self.files[id as usize].stamp == stamp => kept.push(id),
+                Some(_) => {
+                    changes.changed += 1;
+                    fresh.push((relative, stamp));
+                }
+                None => {
+                    changes.added += 1;
+                    fresh.push((relative, stamp));
+                }
+            }
+        }
+        changes.removed = self.files.len() - kept.len() - changes.changed;
+
+        // Kept files stay in their old order, and new ones go after them, so
+        // every posting list stays sorted without sorting it again.
+        kept.sort_unstable();
+        let old = std::mem::take(self);
+        let mut renumbered = vec![None; old.files.len()];
+        let mut files: Vec<Option<Entry>> = old.files.into_iter().map(Some).collect();
+        for id in kept {
+            renumbered[id as usize] = Some(self.files.len() as u32);
+            if let Some(entry) = files[id as usize].take() {
+                self.add(entry);
+            }
+        }
+        for (trigram, ids) in old.postings {
+            let ids: Vec<u32> = ids
+                .iter()
+                .filter_map(|&id| renumbered[id as usize])
+                .collect();
+            if !ids.is_empty() {
+                self.postings.insert(trigram, ids);
+            }
+        }
+
+        for (path, stamp) in fresh {
+            let contents = vfs::read_to_string(fs, &root.join(&path));
+            if let Ok(contents) = &contents {
+                let id = self.files.len() as u32;
+                for trigram in trigrams(&contents.to_lowercase()) {
+                    self.postings.entry(trigram).or_default().push(id);
+                }
+            }
+            let text = contents.is_ok();
+            self.add(Entry { path, stamp, text });
+        }
+        changes
+    }
+
+    fn add(&mut self, entry: Entry) {
+        self.ids.insert(entry.path.clone(), self.files.len() as u32);
+        self.files.push(entry);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Writes the index into `root`, replacing any earlier one whole so a
+    /// search running at the same time never sees half of it.
+    pub(crate) fn save(&self, root: &Path) -> io::Result<PathBuf> {
+        let path = root.join(INDEX_FILE);
+        let partial = root.join(format!("{INDEX_FILE}.tmp"));
+        let mut out = BufWriter::new(File::create(&partial)?);
+
+        writeln!(out, "{HEADER}")?;
+        writeln!(out, "{}", self.files.len())?;
+        for entry in &self.files {
+            let Stamp { modified, size } = entry.stamp;
+            let kind = if entry.text { 't' } else { 'b' };
+            writeln!(out, "{modified} {size} {kind} {}", entry.path.display())?;
+        }
+        let mut postings: Vec<_> = self.postings.iter().collect();
+        postings.sort();
+        for (trigram, ids) in postings {
+            write!(
+                out,
+                "{:02x}{:02x}{:02x}",
+                trigram[0], trigram[1], trigram[2]
+            )?;
+            for id in ids {
+                write!(out, " {id}")?;
+            }
+            writeln!(out)?;
+        }
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+
+    /// Reads the index saved in `root` in `fs`, if there is one.
+    pub(crate) fn load(fs: &dyn FileSystem, root: &Path) -> io::Result<Option<Index>> {
+        let file = match fs.open(&root.join(INDEX_FILE)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "index file is damaged");
+        let mut lines = BufReader::new(file).lines();
+        let mut next_line = || lines.next().unwrap_or_else(|| Err(invalid()));
+
+        if next_line()? != HEADER {
+            return Err(invalid());
+        }
+        let count: u32 = next_line()?.parse().map_err(|_| invalid())?;
+        let mut index = Index::default();
+        for _ in 0..count {
+            let line = next_line()?;
+            let mut fields = line.splitn(4, ' ');
+            let mut field = || fields.next().ok_or_else(invalid);
+            let modified = field()?.parse().map_err(|_| invalid())?;
+            let size = field()?.parse().map_err(|_| invalid())?;
+            let text = match field()? {
+                "t" => true,
+                "b" => false,
+                _ => return Err(invalid()),
+            };
+            let path = PathBuf::from(field()?);
+            let stamp = Stamp { modified, size };
+            index.add(Entry { path, stamp, text });
+        }
+        for line in lines {
+            let line = line?;
+            let mut fields = line.split(' ');
+            let trigram = fields.next().and_then(parse_trigram).ok_or_else(invalid)?;
+            let ids = fields
+                .map(|id| id.parse::<u32>().ok().filter(|id| *id < count))
+                .collect::<Option<Vec<u32>>>()
+                .ok_or_else(invalid)?;
+            index.postings.insert(trigram, ids);
+        }
+        Ok(Some(index))
+    }
+
+    /// Works out which indexed files can't contain `query`, or returns
+    /// `None` if the index can't tell for this query.
+    pub(crate) fn narrow(&self, query: &str) -> Option<Narrowing<'_>> {
+        // Σ lowercases differently at the end of a word, so the query's
+        // trigrams might not appear in the lowercased file even when it
+        // matches.
+        if query.contains('Σ') {
+            return None;
+        }
+        let query = query.to_lowercase();
+        let mut wanted = trigrams(&query).into_iter();
+        let first = wanted.next()?;
+
+        let empty = Vec::new();
+        let postings = |trigram| self.postings.get(&trigram).unwrap_or(&empty);
+        let mut candidates: HashSet<u32> = postings(first).iter().copied().collect();
+        for trigram in wanted {
+            let ids = postings(trigram);
+            candidates.retain(|id| ids.binary_search(id).is_ok());
+        }
+        Some(Narrowing {
+            index: self,
+            candidates,
+        })
+    }
+}
+
+/// The files an [`Index`] has ruled out for one query.
+pub(crate) struct Narrowing<'a> {
+    index: &'a Index,
+    candidates: HashSet<u32>,
+}
+
+impl Narrowing<'_> {
+    /// Whether the file at `relative` (to the indexed directory) can be
+    /// skipped: it was indexed, hasn't changed since, and can't match.
+    pub(crate) fn rules_out(&self, relative: &Path, metadata: &Metadata) -> bool {
+        let Some(&id) = self.index.ids.get(relative) else {
+            return false;
+        };
+        let entry = &self.index.files[id as usize];
+        let unchanged = Stamp::of(metadata) == Some(entry.stamp);
+        unchanged && entry.text && !self.candidates.contains(&id)
+    }
+}
+
+/// Lists the regular files under `root` that can be indexed, relative to
+/// `root` and in order.
+fn scan(fs: &dyn FileSystem, root: &Path) -> Vec<(PathBuf, Stamp)> {
+    let mut found = Vec::new();
+    walk::walk(fs, root, &Ignores::all(), false, |path, metadata| {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        // The index file has one path per line.
+        let storable = relative.to_str().is_some_and(|s| !s.contains('\n'));
+        if let Some(stamp) = Stamp::of(metadata)
+            && metadata.is_file()
+            && storable
+            && relative != Path::new(INDEX_FILE)
+            && relative != Path::new(&format!("{INDEX_FILE}.tmp"))
+        {
+            found.push((relative.to_path_buf(), stamp));
+        }
+        false
+    });
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    found
+}
+
+fn trigrams(text: &str) -> HashSet<Trigram> {
+    text.as_bytes()
+        .windows(3)
+        .map(|window| [window[0], window[1], window[2]])
+        .collect()
+}
+
+fn parse_trigram(hex: &str) -> Option<Trigram> {
+    if hex.len() != 6 {
+        return None;
+    }
+    let byte = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([byte(0)?, byte(2)?, byte(4)?])
+}
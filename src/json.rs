@@ -0,0 +1,258 @@
+//! Just enough JSON for the `--serve` protocol and `--json-input`, and for
+//! `--error-format json`. Only quoting strings is built without the `json`
+//! feature.
+
+use std::fmt::Write;
+
+/// How many arrays and objects deep a document can go, so one made of
+/// nothing but `[` can't overflow the stack.
+#[cfg(feature = "json")]
+const MAX_DEPTH: usize = 512;
+
+#[cfg(feature = "json")]
+#[derive(Debug, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+#[cfg(feature = "json")]
+impl Value {
+    /// Looks up `key` in an object.
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a whole JSON document.
+#[cfg(feature = "json")]
+pub(crate) fn parse(text: &str) -> Result<Value, &'static str> {
+    let mut parser = Parser {
+        text,
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < text.len() {
+        return Err("unexpected text after the JSON value");
+    }
+    Ok(value)
+}
+
+/// Quotes `s` as a JSON string.
+pub(crate) fn string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(feature = "json")]
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    /// How many arrays and objects the parser is inside.
+    depth: usize,
+}
+
+#[cfg(feature = "json")]
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Value, &'static str> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{' | b'[') if self.depth == MAX_DEPTH => Err("nesting too deep"),
+            Some(b'{') => self.nested(Self::object),
+            Some(b'[') => self.nested(Self::array),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b't') => self.keyword("true", Value::Bool(true)),
+            Some(b'f') => self.keyword("false", Value::Bool(false)),
+            Some(b'n') => self.keyword("null", Value::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err("unexpected character in JSON"),
+            None => Err("unexpected end of JSON"),
+        }
+    }
+
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<Value, &'static str>,
+    ) -> Result<Value, &'static str> {
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Value, &'static str> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b'}') {
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err("expected a string key in JSON object");
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(b':') {
+                return Err("expected ':' in JSON object");
+            }
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            if self.eat(b'}') {
+                return Ok(Value::Object(members));
+            }
+            if !self.eat(b',') {
+                return Err("expected ',' or '}' in JSON object");
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, &'static str> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b']') {
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            if self.eat(b']') {
+                return Ok(Value::Array(items));
+            }
+            if !self.eat(b',') {
+                return Err("expected ',' or ']' in JSON array");
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, &'static str> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let Some(i) = rest.find(['"', '\\']) else {
+                return Err("unterminated JSON string");
+            };
+            s.push_str(&rest[..i]);
+            self.pos += i + 1;
+            if rest.as_bytes()[i] == b'"' {
+                return Ok(s);
+            }
+            let escaped = match self.next_byte() {
+                Some(b'"') => '"',
+                Some(b'\\') => '\\',
+                Some(b'/') => '/',
+                Some(b'b') => '\u{8}',
+                Some(b'f') => '\u{c}',
+                Some(b'n') => '\n',
+                Some(b'r') => '\r',
+                Some(b't') => '\t',
+                Some(b'u') => self.unicode_escape()?,
+                _ => return Err("bad escape in JSON string"),
+            };
+            s.push(escaped);
+        }
+    }
+
+    /// Decodes the digits after `\u`, including the second half of a
+    /// surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, &'static str> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or("bad \\u escape in JSON string");
+        }
+        if !self.text[self.pos..].starts_with("\\u") {
+            return Err("unpaired surrogate in JSON string");
+        }
+        self.pos += 2;
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err("unpaired surrogate in JSON string");
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+            .ok_or("bad \\u escape in JSON string")
+    }
+
+    fn hex4(&mut self) -> Result<u32, &'static str> {
+        let digits = self.text.get(self.pos..self.pos + 4);
+        let n = digits.and_then(|digits| u32::from_str_radix(digits, 16).ok());
+        self.pos += 4;
+        n.ok_or("bad \\u escape in JSON string")
+    }
+
+    fn number(&mut self) -> Result<Value, &'static str> {
+        let start = self.pos;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        self.text[start..self.pos]
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| "bad number in JSON")
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, &'static str> {
+        if !self.text[self.pos..].starts_with(word) {
+            return Err("unexpected word in JSON");
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let byte = self.peek();
+        self.pos += 1;
+        byte
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.peek() == Some(byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+}
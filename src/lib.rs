@@ -1,148 +1,6130 @@
+extern crate alloc;
+
+mod cache;
+mod completions;
+mod download;
+mod events;
+mod expand;
+mod feed;
+mod follow;
+mod git;
+#[cfg(feature = "hyperscan")]
+mod hyperscan;
+mod i18n;
+mod ignore;
+mod index;
+mod json;
+/// Checked to build as part of a `#![no_std]` crate, linking `std` only for
+/// the things a binary needs, so something from `std` creeping in fails to
+/// compile:
+///
+/// ```
+/// #![no_std]
+/// extern crate alloc;
+/// extern crate std as _;
+///
+/// #[path = "literal.rs"]
+/// mod literal;
+///
+/// fn main() {
+///     let literal = literal::Literal::new("RUST", true);
+///     assert_eq!(literal.find("Trust me."), Some(1..5));
+/// }
+/// ```
+mod literal;
+mod log;
+mod matcher;
+mod output;
+mod pager;
+#[cfg(feature = "parallel")]
+mod parallel;
+#[cfg(feature = "pcre2")]
+mod pcre2;
+mod progress;
+mod regex;
+mod repl;
+#[cfg(feature = "json")]
+mod serve;
+mod sniff;
+mod stats;
+mod structured;
+mod synonyms;
+mod tui;
+mod vfs;
+mod walk;
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::env;
 use std::error::Error;
-use std::fs;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::iter::Peekable;
+use std::mem;
+use std::ops::{Range, RangeInclusive};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+pub use completions::Shell;
+use download::Download;
+use events::Emit;
+pub use events::{ContextLine, Event, FileStats, Handler, MatchData, Summary};
+pub use feed::ChunkSearcher;
+use follow::Tail;
+use git::Revision;
+pub use i18n::Lang;
+use ignore::Ignores;
+use index::Index;
+pub use literal::Literal;
+pub use log::Verbosity;
+use log::{Log, Timings};
+use matcher::Matcher;
+pub use matcher::{Engine, Group};
+pub use output::Theme;
+use output::{Context, Headed, Printer};
+use pager::{LineEnds, Output};
+use progress::Progress;
+use stats::Stats;
+use structured::Selector;
+use synonyms::Synonyms;
+use vfs::Disk;
+pub use vfs::{Entry, FileKind, FileSystem, MemoryFs, Metadata, Source};
+
+/// Everything a run of minigrep was asked to do, as read from the command
+/// line. What to match, what to read and how to print it are kept apart,
+/// so each part can be built and passed around on its own.
+pub struct Config {
+    pub search: SearchOptions,
+    pub input: InputOptions,
+    pub output: OutputOptions,
+    /// Whether to ignore the environment variables that would change what
+    /// is found or how it's printed, for reproducible results.
+    pub no_env: bool,
+    /// The language messages are printed in.
+    pub lang: Lang,
+    pub error_format: ErrorFormat,
+    pub timeout: Option<Duration>,
+    pub verbosity: Verbosity,
+    pub explain_skips: bool,
+    pub command: Command,
+    /// For `replace`, what each match is replaced with.
+    pub replacement: Option<String>,
+    /// For `--preserve-case`, give each replacement the case of the match
+    /// it replaces.
+    pub preserve_case: bool,
+    pub in_place: bool,
+    /// For `--in-place`, the original of each file is kept under its name
+    /// with this added...
+    pub backup_suffix: Option<String>,
+    /// ...and in this directory, at the path the file was found at.
+    pub backup_dir: Option<PathBuf>,
+    pub tui: bool,
+    pub interactive: bool,
+    /// Where to listen for search requests instead of searching.
+    pub serve: Option<String>,
+    pub completions: Option<Shell>,
+}
+
+/// What a search looks for, and how the queries are read.
+pub struct SearchOptions {
+    /// What to search for; a line matches if any of these does.
+    pub queries: Vec<String>,
+    pub ignore_case: bool,
+    /// How the query is interpreted.
+    pub engine: Engine,
+    /// For `-o`, print only what this group of a regex query captured.
+    pub group: Option<Group>,
+    /// For `--overlapping`, report matches that overlap each other with
+    /// `-o` and `--count-matches`, rather than only those that don't.
+    pub overlapping: bool,
+    /// For `--synonyms`, the file of alternatives each query was expanded
+    /// with; see [`Config::build`].
+    pub synonyms: Option<PathBuf>,
+}
+
+/// What a search reads: where, which files, and which parts of them.
+pub struct InputOptions {
+    pub file_path: PathBuf,
+    pub max_memory: Option<usize>,
+    /// For `--buffer-size`, read files this many bytes at a time rather
+    /// than in reads sized to suit each file.
+    pub buffer_size: Option<usize>,
+    pub recursive: bool,
+    pub max_filesize: Option<u64>,
+    pub newer_than: Option<SystemTime>,
+    pub older_than: Option<SystemTime>,
+    pub sort: Option<SortKey>,
+    pub sort_reverse: bool,
+    pub from_line: Option<usize>,
+    pub to_line: Option<usize>,
+    pub start_offset: Option<u64>,
+    pub end_offset: Option<u64>,
+    pub follow: bool,
+    /// Whether to search the values in JSON documents rather than lines.
+    pub json_input: bool,
+    /// Whether to search delimited files record by record.
+    pub csv: bool,
+    /// For `--csv`, which `--field` implies, the columns to search, by
+    /// header name or number.
+    pub fields: Vec<String>,
+    pub delimiter: char,
+    /// Whether to search only the text in HTML or XML, not the markup.
+    pub html: bool,
+    /// For `--html`, which `--selector` implies, the elements to search in.
+    pub selector: Option<String>,
+    /// The git revision to search instead of the files on disk.
+    pub git_rev: Option<String>,
+    /// Whether to search only files git sees as changed or untracked.
+    pub changed: bool,
+    /// For `--changed`, which `--changed-since` implies, the revision to
+    /// compare with instead of the last commit.
+    pub changed_since: Option<String>,
+    /// Whether to ignore no files at all; implies the two below.
+    pub no_ignore: bool,
+    /// Whether to search what `.gitignore` files exclude, and `.git`.
+    pub no_ignore_vcs: bool,
+    /// Whether to leave out the user's own ignore file.
+    pub no_ignore_global: bool,
+    /// Whether a recursive search follows symlinks.
+    pub follow_links: bool,
+    /// Whether a recursive search also looks into files whose first bytes
+    /// mark them as images, audio, video or executables.
+    pub no_sniff: bool,
+    pub devices: Devices,
+    pub directories: Directories,
+    pub update_index: bool,
+}
+
+/// How what a search finds is printed and summed up.
+pub struct OutputOptions {
+    /// How many matching lines to print, across every file, before the
+    /// whole search stops.
+    pub max_total: Option<usize>,
+    /// Like `max_total`, but stopping quietly, for `--head`.
+    pub head: Option<usize>,
+    /// For `--tail`, print only the last this many matching lines.
+    pub tail: Option<usize>,
+    pub sort_lines: SortLines,
+    pub sort_scope: SortScope,
+    /// Whether to print line numbers: for `-n`, or by default when stdout
+    /// is a terminal.
+    pub line_number: bool,
+    /// For `--heading`, or by default when stdout is a terminal, print each
+    /// file's path once above its lines rather than at the start of each.
+    pub heading: bool,
+    /// For `--column`, print the column each match starts at after the
+    /// line number.
+    pub column: bool,
+    /// For `--vimgrep`, print a line for every match rather than every
+    /// matching line, as `path:line:column:text`.
+    pub vimgrep: bool,
+    /// For `--porcelain`, print lines as tab-separated records for scripts,
+    /// in a format that doesn't change between releases.
+    pub porcelain: bool,
+    /// For `--explain`, print under each matching line how each match in
+    /// it was found: by which query and engine, whether case was ignored,
+    /// and where it is.
+    pub explain: bool,
+    pub max_columns: Option<usize>,
+    pub tab_width: Option<usize>,
+    pub wrap: bool,
+    pub hyperlink_format: Option<String>,
+    pub color: ColorChoice,
+    pub theme: Theme,
+    pub pager: bool,
+    pub buffering: Buffering,
+    pub line_terminator: LineTerminator,
+    pub progress: bool,
+    pub stats: Option<StatsLevel>,
+    /// For `--stats-format`, how the statistics are printed.
+    pub stats_format: StatsFormat,
+    pub count: Option<Count>,
+    pub count_per_file: bool,
+    pub include_zero: bool,
+    /// For `--count-files`, print how many files matched instead of their
+    /// paths.
+    pub count_files: bool,
+    pub unique: bool,
+    pub unique_count: bool,
+    pub only_matching: bool,
+    pub top: Option<usize>,
+    pub histogram: Option<Histogram>,
+    /// For `-q`, print nothing and stop at the first matching line; the exit
+    /// status says whether there was one.
+    pub quiet: bool,
+    /// Whether a search that finds nothing says so on stderr, rather than
+    /// leaving it to the exit status.
+    pub message_on_no_match: bool,
+    pub passthru: bool,
+    /// How many lines to print before and after each match.
+    pub before_context: usize,
+    pub after_context: usize,
+    /// Printed between blocks of context that aren't next to each other.
+    pub group_separator: String,
+    /// Whether to print the paragraph around each match, between blank lines.
+    pub block_context: bool,
+    /// Whether to head matches with the line their function starts on.
+    pub show_function: bool,
+    /// Text marking the lines that start a function, instead of guessing.
+    pub function_start: Option<String>,
+}
+
+/// What minigrep has been asked to do, named by the first argument.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Print the matching lines; also what a bare `minigrep QUERY PATH` does.
+    Search,
+    /// Print the matching lines with each match replaced, or rewrite the
+    /// files that way with `--in-place`.
+    Replace,
+    /// Print how many lines match, as `-c` does.
+    Count,
+    /// Print the path of each file with a match.
+    Files,
+    /// Build or update the index of a directory instead of searching it.
+    Index,
+}
+
+impl Command {
+    fn parse(name: &str) -> Option<Command> {
+        match name {
+            "search" => Some(Command::Search),
+            "replace" => Some(Command::Replace),
+            "count" => Some(Command::Count),
+            "files" => Some(Command::Files),
+            "index" => Some(Command::Index),
+            _ => None,
+        }
+    }
+}
+
+/// What to do with named pipes, sockets and devices.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Devices {
+    /// Leave them alone, since reading one can wait forever.
+    #[default]
+    Skip,
+    /// Read them as a stream, a line at a time.
+    Read,
+}
+
+impl Devices {
+    fn parse(action: &str) -> Option<Devices> {
+        match action {
+            "skip" => Some(Devices::Skip),
+            "read" => Some(Devices::Read),
+            _ => None,
+        }
+    }
+}
+
+/// What to do when the path given is a directory and `-r` isn't.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Directories {
+    /// Stop with an error suggesting `-r`.
+    #[default]
+    Error,
+    Skip,
+    /// Search it as if `-r` had been given.
+    Recurse,
+}
+
+impl Directories {
+    fn parse(action: &str) -> Option<Directories> {
+        match action {
+            "error" => Some(Directories::Error),
+            "skip" => Some(Directories::Skip),
+            "recurse" => Some(Directories::Recurse),
+            _ => None,
+        }
+    }
+}
+
+/// When what's printed is flushed to stdout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Buffering {
+    /// A line at a time to a terminal, or with `--follow`, and a block at a
+    /// time otherwise.
+    #[default]
+    Auto,
+    /// After every line, for `--line-buffered`, so a pipeline watching a
+    /// slow stream sees each match as it's found.
+    Line,
+    /// Only when the buffer is full, for `--block-buffered`, which is
+    /// quicker when printing a lot.
+    Block,
+}
+
+/// What ends each line minigrep prints.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineTerminator {
+    #[default]
+    Lf,
+    /// `\r\n`, for `--crlf`, as Windows programs expect.
+    Crlf,
+    /// A NUL byte, for `--null`, so output can go to `xargs -0`.
+    Nul,
+}
+
+/// How `--histogram` groups matches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Histogram {
+    /// By the top-level directory (or file) under the searched path.
+    Directory,
+    File,
+}
+
+/// What to count instead of printing matching lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Count {
+    /// Lines with at least one match.
+    Lines,
+    /// Every match, including several on the same line.
+    Matches,
+}
+
+/// How much `--stats` reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsLevel {
+    /// Matches, files and elapsed time.
+    Basic,
+    /// Also bytes read, buffer memory, thread use and skipped files.
+    Full,
+}
+
+/// How `--stats` is printed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// A line for each number, set off from the matches by a blank line.
+    #[default]
+    Text,
+    /// One JSON object on the last line, for dashboards and scripts.
+    Json,
+}
+
+impl StatsFormat {
+    fn parse(format: &str) -> Option<StatsFormat> {
+        match format {
+            "text" => Some(StatsFormat::Text),
+            "json" => Some(StatsFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Whether output is coloured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only when stdout is a terminal and `NO_COLOR` isn't set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn parse(when: &str) -> Option<ColorChoice> {
+        match when {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+}
+
+/// How errors are reported on stderr.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// A line of prose, in the language of [`Config::lang`].
+    #[default]
+    Text,
+    /// A JSON object per error, always in English, for tools that wrap
+    /// minigrep; see [`error_json`].
+    Json,
+}
+
+impl ErrorFormat {
+    fn parse(format: &str) -> Option<ErrorFormat> {
+        match format {
+            "text" => Some(ErrorFormat::Text),
+            "json" => Some(ErrorFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// The format asked for by `--error-format` in `args`, for reporting a
+    /// command line that couldn't be read into a [`Config`].
+    pub fn from_args(args: &[OsString]) -> ErrorFormat {
+        let mut format = ErrorFormat::default();
+        let mut args = args.iter().map(|arg| arg.to_str().unwrap_or_default());
+        while let Some(arg) = args.next() {
+            let value = match arg.strip_prefix("--error-format=") {
+                Some(value) => Some(value),
+                None if arg == "--error-format" => args.next(),
+                None => continue,
+            };
+            format = value.and_then(ErrorFormat::parse).unwrap_or(format);
+        }
+        format
+    }
+}
+
+/// Describes `e` as a JSON object with a `code` that tools can match on, the
+/// `path` it happened to if known, and the `message`. A bad query also gets
+/// the `position` of the problem in it. Errors without a code of their own
+/// get `fallback`.
+pub fn error_json(e: &(dyn Error + 'static), path: Option<&str>, fallback: &str) -> String {
+    let mut message = e.to_string();
+    let mut position = None;
+    let code = if let Some(e) = e.downcast_ref::<PatternError>() {
+        message = e.message.to_string();
+        position = e.position;
+        "bad-pattern"
+    } else if let Some(e) = e.downcast_ref::<io::Error>() {
+        match e.kind() {
+            io::ErrorKind::NotFound => "not-found",
+            io::ErrorKind::PermissionDenied => "permission-denied",
+            io::ErrorKind::IsADirectory => "is-a-directory",
+            io::ErrorKind::InvalidData => "invalid-data",
+            _ => "io",
+        }
+    } else {
+        fallback
+    };
+
+    let mut json = format!("{{\"code\":{}", json::string(code));
+    if let Some(path) = path {
+        json += &format!(",\"path\":{}", json::string(path));
+    }
+    json += &format!(",\"message\":{}", json::string(&message));
+    if let Some(position) = position {
+        json += &format!(",\"position\":{position}");
+    }
+    json + "}"
+}
+
+/// The order in which files found by a recursive search are searched and
+/// reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    Path,
+    Modified,
+    Size,
+    /// Small files first, and of files about the same size the most
+    /// recently modified first, so the first results come sooner. This is
+    /// the order when stdout is a terminal and no other is asked for.
+    Priority,
+}
+
+impl SortKey {
+    fn parse(key: &str) -> Option<SortKey> {
+        match key {
+            "path" => Some(SortKey::Path),
+            "modified" => Some(SortKey::Modified),
+            "size" => Some(SortKey::Size),
+            "priority" => Some(SortKey::Priority),
+            _ => None,
+        }
+    }
+}
+
+/// How `--sort-lines` orders the matching lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortLines {
+    /// In the order they're found.
+    #[default]
+    None,
+    /// By their text.
+    Alpha,
+    /// Shortest first.
+    Length,
+}
+
+impl SortLines {
+    fn parse(order: &str) -> Option<SortLines> {
+        match order {
+            "alpha" => Some(SortLines::Alpha),
+            "length" => Some(SortLines::Length),
+            "none" => Some(SortLines::None),
+            _ => None,
+        }
+    }
+
+    /// Sorts what each line printed by the line's text, keeping lines that
+    /// compare the same in the order they were found.
+    fn sort(self, lines: &mut [(String, Vec<u8>)]) {
+        match self {
+            SortLines::None => {}
+            SortLines::Alpha => lines.sort_by(|a, b| a.0.cmp(&b.0)),
+            SortLines::Length => lines.sort_by_key(|(text, _)| text.chars().count()),
+        }
+    }
+}
+
+/// Whether `--sort-lines` sorts each file's lines or all of them together.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortScope {
+    #[default]
+    File,
+    All,
+}
+
+impl SortScope {
+    fn parse(scope: &str) -> Option<SortScope> {
+        match scope {
+            "file" => Some(SortScope::File),
+            "all" => Some(SortScope::All),
+            _ => None,
+        }
+    }
+}
+
+/// Flags that can be given alone or with `=VALUE`.
+const OPTIONAL_VALUE_FLAGS: [&str; 2] = ["--stats", "--histogram"];
+
+/// Short flags that take a value, which can be written straight after
+/// them, as in `-C2`.
+const SHORT_VALUE_FLAGS: [char; 4] = ['A', 'B', 'C', 'M'];
+
+/// The command line as [`Config::parse`] reads it: flags and their values
+/// as text, and everything else as it was given, since a path needn't be
+/// UTF-8.
+struct Args<I: Iterator<Item = OsString>> {
+    args: Peekable<I>,
+}
+
+impl<I: Iterator<Item = OsString>> Args<I> {
+    /// The next argument, as it was given.
+    fn next_os(&mut self) -> Option<OsString> {
+        self.args.next()
+    }
+
+    /// The next argument as a flag's value. One that isn't UTF-8 can't be
+    /// valid for any flag, so replacing its bad bytes is enough for the
+    /// flag to reject it.
+    fn next(&mut self) -> Option<String> {
+        let value = self.args.next()?;
+        Some(
+            value
+                .into_string()
+                .unwrap_or_else(|value| value.to_string_lossy().into_owned()),
+        )
+    }
+}
+
+/// A query that can't be searched for, shown with a caret under the
+/// problem when where it is is known.
+#[derive(Debug)]
+pub struct PatternError {
+    query: String,
+    message: &'static str,
+    /// The byte offset in the query of the problem.
+    position: Option<usize>,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(position) = self.position {
+            let column = self.query[..position].chars().count();
+            write!(f, "\n    {}\n    {}^", self.query, " ".repeat(column))?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for PatternError {}
+
+impl Config {
+    /// Reads the command line, and checks that the query can be searched
+    /// for, so a bad pattern is reported before anything is read. With
+    /// `--synonyms`, the file is read here and the queries expanded.
+    /// The defaults are the plain ones for output read by another program.
+    pub fn build(
+        args: impl Iterator<Item = impl Into<OsString>>,
+    ) -> Result<Config, Box<dyn Error>> {
+        Config::build_with(args, &mut || None, false)
+    }
+
+    /// Like [`Config::build`], but for the command line: when only a path is
+    /// given and stdin is a terminal, asks for the query instead of failing,
+    /// and when stdout is a terminal, shows line numbers and puts each path
+    /// above its file's lines unless told not to.
+    pub fn build_with_prompt(
+        args: impl Iterator<Item = impl Into<OsString>>,
+    ) -> Result<Config, Box<dyn Error>> {
+        Config::build_with(args, &mut prompt_for_query, io::stdout().is_terminal())
+    }
+
+    fn build_with(
+        args: impl Iterator<Item = impl Into<OsString>>,
+        prompt: &mut dyn FnMut() -> Option<String>,
+        terminal: bool,
+    ) -> Result<Config, Box<dyn Error>> {
+        let mut config = Config::parse_with(args, prompt, terminal)?;
+        if let Some(path) = &config.search.synonyms {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("can't read {}: {e}", path.display()))?;
+            let synonyms = Synonyms::parse(&text)?;
+            config.search.queries =
+                synonyms.expand(&config.search.queries, config.search.ignore_case);
+        }
+        // Indexing, serving, completions and the interactive modes can be
+        // started without a query.
+        let queryless = config.command == Command::Index
+            || config.tui
+            || config.interactive
+            || config.serve.is_some()
+            || config.completions.is_some();
+        if !(queryless && config.search.queries.iter().all(String::is_empty)) {
+            config.check_query()?;
+        }
+        Ok(config)
+    }
+
+    /// Checks that no query is empty, and that each compiles for its
+    /// engine.
+    pub(crate) fn check_query(&self) -> Result<(), PatternError> {
+        for query in &self.search.queries {
+            let error = |message, position| PatternError {
+                query: query.clone(),
+                message,
+                position,
+            };
+            if query.is_empty() {
+                return Err(error(
+                    "the query is empty, and would match every line",
+                    None,
+                ));
+            }
+            let matcher = Matcher::build(query, self.search.ignore_case, self.search.engine)
+                .map_err(|e| error(e.message, e.position))?;
+            if let Some(group) = &self.search.group {
+                if self.search.engine.resolve(query) != Engine::Regex {
+                    return Err(error("--group needs a regex, like --engine regex", None));
+                }
+                if !matcher.has_group(group) {
+                    return Err(error("--group names a group the regex doesn't have", None));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Where `--in-place` keeps the original of the file at `path` before
+    /// rewriting it, if anywhere. A backup directory mirrors the file's
+    /// full path, so backups of files with the same name don't collide, and
+    /// neither do `../a/x` and `a/x`.
+    fn backup_path(&self, path: &Path) -> Option<PathBuf> {
+        let backup = match &self.backup_dir {
+            Some(dir) => dir.join(
+                fs::canonicalize(path)
+                    .unwrap_or_else(|_| path.to_path_buf())
+                    .components()
+                    .filter(|component| matches!(component, Component::Normal(_)))
+                    .collect::<PathBuf>(),
+            ),
+            None => path.to_path_buf(),
+        };
+        let mut backup = backup.into_os_string();
+        match &self.backup_suffix {
+            Some(suffix) => backup.push(suffix),
+            None if self.backup_dir.is_none() => return None,
+            None => {}
+        }
+        Some(PathBuf::from(backup))
+    }
+
+    /// Reports an error with the file at `path` that doesn't end the search.
+    fn report(&self, path: &str, e: &(dyn Error + 'static)) {
+        match self.error_format {
+            ErrorFormat::Text => eprintln!("minigrep: {path}: {}", self.lang.describe(e)),
+            ErrorFormat::Json => eprintln!("{}", error_json(e, Some(path), "error")),
+        }
+    }
+
+    /// Reads the command line without checking the query.
+    #[cfg(feature = "json")]
+    pub(crate) fn parse(
+        args: impl Iterator<Item = impl Into<OsString>>,
+    ) -> Result<Config, &'static str> {
+        Config::parse_with(args, &mut || None, false)
+    }
+
+    /// Reads the command line, calling `prompt` for the query if only a
+    /// path was given. `terminal` says whether what's printed is read by a
+    /// person, which decides what's shown when no flag says.
+    fn parse_with(
+        args: impl Iterator<Item = impl Into<OsString>>,
+        prompt: &mut dyn FnMut() -> Option<String>,
+        terminal: bool,
+    ) -> Result<Config, &'static str> {
+        // Accept `--flag=value` as well as `--flag value`, except for flags
+        // whose value is optional and so can only be given with `=`, and
+        // `-C2` as well as `-C 2`. Nothing after `--` is a flag.
+        let mut ended = false;
+        let mut args = args.map(Into::into).flat_map(move |arg: OsString| {
+            if ended {
+                return vec![arg];
+            }
+            ended = arg == "--";
+            let Some(text) = arg.to_str() else {
+                return vec![arg];
+            };
+            let mut chars = text.chars();
+            if let (Some('-'), Some(short)) = (chars.next(), chars.next())
+                && SHORT_VALUE_FLAGS.contains(&short)
+                && !chars.as_str().is_empty()
+            {
+                return vec![
+                    OsString::from(format!("-{short}")),
+                    OsString::from(chars.as_str()),
+                ];
+            }
+            match text.split_once('=') {
+                Some((flag, value))
+                    if flag.starts_with("--") && !OPTIONAL_VALUE_FLAGS.contains(&flag) =>
+                {
+                    vec![OsString::from(flag), OsString::from(value)]
+                }
+                _ => vec![arg],
+            }
+        });
+        args.next(); // We don't need the program name
+        let mut args = Args {
+            args: args.peekable(),
+        };
+        let mut command = args
+            .args
+            .next_if(|arg| arg.to_str().and_then(Command::parse).is_some())
+            .and_then(|arg| arg.to_str().and_then(Command::parse))
+            .unwrap_or(Command::Search);
+
+        // Left unset unless -i or -s is given, so IGNORE_CASE can decide.
+        let mut ignore_case = None;
+        let mut engine = Engine::default();
+        let mut no_env = false;
+        let mut lang = None;
+        let mut timeout = None;
+        let mut max_total = None;
+        let mut head = None;
+        let mut tail = None;
+        let mut quiet = false;
+        let mut message_on_no_match = false;
+        let mut max_memory = None;
+        let mut buffer_size = None;
+        let mut recursive = false;
+        let mut max_filesize = None;
+        let mut newer_than = None;
+        let mut older_than = None;
+        let mut sort = None;
+        let mut sort_reverse = false;
+        let mut sort_lines = SortLines::None;
+        let mut sort_scope = SortScope::File;
+        let mut line_number = None;
+        let mut heading = None;
+        let mut column = false;
+        let mut vimgrep = false;
+        let mut porcelain = false;
+        let mut explain = false;
+        let mut from_line = None;
+        let mut to_line = None;
+        let mut start_offset = None;
+        let mut end_offset = None;
+        let mut max_columns = None;
+        let mut tab_width = None;
+        let mut wrap = false;
+        let mut hyperlink_format = None;
+        let mut color = ColorChoice::Auto;
+        let mut error_format = ErrorFormat::default();
+        let mut theme = Theme::default();
+        let mut pager = false;
+        let mut buffering = Buffering::Auto;
+        let mut line_terminator = LineTerminator::Lf;
+        let mut progress = true;
+        let mut verbosity = Verbosity::Quiet;
+        let mut explain_skips = false;
+        let mut stats = None;
+        let mut stats_format = None;
+        let mut count = None;
+        let mut count_per_file = false;
+        let mut include_zero = false;
+        let mut count_files = false;
+        let mut unique = false;
+        let mut unique_count = false;
+        let mut only_matching = false;
+        let mut group = None;
+        let mut overlapping = false;
+        let mut escapes = false;
+        let mut synonyms = None;
+        let mut top = None;
+        let mut histogram = None;
+        let mut follow = false;
+        let mut serve = None;
+        let mut update_index = false;
+        let mut in_place = false;
+        let mut preserve_case = false;
+        let mut backup_suffix = None;
+        let mut backup_dir = None;
+        let mut passthru = false;
+        let mut before_context = 0;
+        let mut after_context = 0;
+        let mut group_separator = String::from("--");
+        let mut block_context = false;
+        let mut show_function = false;
+        let mut function_start = None;
+        let mut json_input = false;
+        let mut csv = false;
+        let mut fields = Vec::new();
+        let mut delimiter = ',';
+        let mut html = false;
+        let mut selector = None;
+        let mut git_rev = None;
+        let mut changed = false;
+        let mut changed_since = None;
+        let mut no_ignore = false;
+        let mut no_ignore_vcs = false;
+        let mut no_ignore_global = false;
+        let mut follow_links = false;
+        let mut no_sniff = false;
+        let mut devices = Devices::default();
+        let mut directories = Directories::default();
+        let mut tui = false;
+        let mut interactive = false;
+        let mut completions = None;
+        let mut positional = Vec::new();
+
+        while let Some(raw) = args.next_os() {
+            let Some(arg) = raw.to_str() else {
+                positional.push(raw);
+                continue;
+            };
+            match arg {
+                "-i" => ignore_case = Some(true),
+                "-s" | "--case-sensitive" => ignore_case = Some(false),
+                "--no-env" => no_env = true,
+                "--lang" => {
+                    let code = args.next().and_then(|code| Lang::parse(&code));
+                    lang = Some(code.ok_or("--lang needs one of: en, de, es, fr")?);
+                }
+                "--glob-pattern" => engine = Engine::Glob,
+                "--pcre2" => engine = Engine::Pcre2,
+                "--engine" => {
+                    let name = args.next().and_then(|name| Engine::parse(&name));
+                    engine = name.ok_or(
+                        "--engine needs one of: literal, regex, glob, auto, pcre2, hyperscan",
+                    )?;
+                }
+                "-r" | "--recursive" => recursive = true,
+                "-n" | "--line-number" => line_number = Some(true),
+                "-N" | "--no-line-number" => line_number = Some(false),
+                "--heading" => heading = Some(true),
+                "--no-heading" => heading = Some(false),
+                "--column" => column = true,
+                "--vimgrep" => vimgrep = true,
+                "--porcelain" => porcelain = true,
+                "--explain" => explain = true,
+                "-F" | "--follow" => follow = true,
+                "--update-index" => update_index = true,
+                "--in-place" => in_place = true,
+                "--preserve-case" => preserve_case = true,
+                "--backup-suffix" => {
+                    let suffix = args.next().filter(|suffix| !suffix.is_empty());
+                    backup_suffix = Some(suffix.ok_or("--backup-suffix needs a suffix")?);
+                }
+                "--backup-dir" => {
+                    let dir = args.next_os().filter(|dir| !dir.is_empty());
+                    backup_dir = Some(PathBuf::from(dir.ok_or("--backup-dir needs a directory")?));
+                }
+                "--no-backup" => {
+                    backup_suffix = None;
+                    backup_dir = None;
+                }
+                "--passthru" => passthru = true,
+                "--tui" => tui = true,
+                "-I" | "--interactive" => interactive = true,
+                "--wrap" => wrap = true,
+                "--pager" => pager = true,
+                "--line-buffered" => buffering = Buffering::Line,
+                "--block-buffered" => buffering = Buffering::Block,
+                "--crlf" => line_terminator = LineTerminator::Crlf,
+                "--null" => line_terminator = LineTerminator::Nul,
+                "--no-progress" => progress = false,
+                "-v" | "--verbose" => verbosity = verbosity.max(Verbosity::Verbose),
+                "--debug" => verbosity = Verbosity::Debug,
+                "--explain-skips" => explain_skips = true,
+                "-c" | "--count" => count = Some(Count::Lines),
+                "--count-matches" => count = Some(Count::Matches),
+                "--count-per-file" => {
+                    count = count.or(Some(Count::Lines));
+                    count_per_file = true;
+                }
+                "--include-zero" => include_zero = true,
+                "-o" | "--only-matching" => only_matching = true,
+                "--group" => {
+                    let name = args.next().filter(|name| !name.is_empty());
+                    group = Some(Group::parse(
+                        &name.ok_or("--group needs a group number or name")?,
+                    ));
+                    only_matching = true;
+                }
+                "--overlapping" => overlapping = true,
+                "--escapes" => escapes = true,
+                "--synonyms" => {
+                    synonyms = Some(PathBuf::from(
+                        args.next()
+                            .ok_or("--synonyms needs a file of alternatives")?,
+                    ));
+                }
+                "--top" => {
+                    let n = args.next().and_then(|n| n.parse::<usize>().ok());
+                    top = Some(n.ok_or("--top needs a number of matches to show")?);
+                }
+                "--histogram" | "--histogram=dir" => histogram = Some(Histogram::Directory),
+                "--histogram=file" => histogram = Some(Histogram::File),
+                "--unique" => unique = true,
+                "--unique-count" => {
+                    unique = true;
+                    unique_count = true;
+                }
+                "--stats" | "--stats=basic" => stats = Some(StatsLevel::Basic),
+                "--stats=full" => stats = Some(StatsLevel::Full),
+                "-q" | "--quiet" => quiet = true,
+                "--message-on-no-match" => message_on_no_match = true,
+                "--quiet-no-match" => message_on_no_match = false,
+                "-l" | "--files-with-matches" => command = Command::Files,
+                "--count-files" => {
+                    command = Command::Files;
+                    count_files = true;
+                }
+                "--max-total" => {
+                    let n = args.next().and_then(|n| n.parse::<usize>().ok());
+                    let n = n.filter(|&n| n > 0);
+                    max_total = Some(n.ok_or("--max-total needs a number of matches")?);
+                }
+                "--head" => {
+                    let n = args.next().and_then(|n| n.parse::<usize>().ok());
+                    head = Some(
+                        n.filter(|&n| n > 0)
+                            .ok_or("--head needs a number of lines")?,
+                    );
+                }
+                "--tail" => {
+                    let n = args.next().and_then(|n| n.parse::<usize>().ok());
+                    tail = Some(
+                        n.filter(|&n| n > 0)
+                            .ok_or("--tail needs a number of lines")?,
+                    );
+                }
+                "--timeout" => {
+                    let duration = args
+                        .next()
+                        .and_then(|secs| secs.parse::<f64>().ok())
+                        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                        .ok_or("--timeout needs a number of seconds")?;
+                    timeout = Some(duration);
+                }
+                "--max-memory" => {
+                    let bytes = args
+                        .next()
+                        .and_then(|bytes| parse_size(&bytes))
+                        .filter(|bytes| *bytes > 0)
+                        .ok_or("--max-memory needs a size in bytes, like 64K or 16M")?;
+                    max_memory = Some(bytes);
+                }
+                "--buffer-size" => {
+                    let bytes = args
+                        .next()
+                        .and_then(|bytes| parse_size(&bytes))
+                        .filter(|bytes| *bytes > 0)
+                        .ok_or("--buffer-size needs a size in bytes, like 64K or 1M")?;
+                    buffer_size = Some(bytes);
+                }
+                "--max-filesize" => {
+                    let bytes = args
+                        .next()
+                        .and_then(|bytes| parse_size(&bytes))
+                        .ok_or("--max-filesize needs a size in bytes, like 512K or 10M")?;
+                    max_filesize = Some(bytes as u64);
+                }
+                "--newer-than" => {
+                    let time = args.next().and_then(|time| parse_time(&time));
+                    newer_than = Some(time.ok_or(
+                        "--newer-than needs an age like 2d or 12h, or a date like 2024-01-01",
+                    )?);
+                }
+                "--older-than" => {
+                    let time = args.next().and_then(|time| parse_time(&time));
+                    older_than = Some(time.ok_or(
+                        "--older-than needs an age like 2d or 12h, or a date like 2024-01-01",
+                    )?);
+                }
+                "--sort-lines" => {
+                    let order = args.next().and_then(|order| SortLines::parse(&order));
+                    sort_lines = order.ok_or("--sort-lines needs one of: alpha, length, none")?;
+                }
+                "--sort-scope" => {
+                    let scope = args.next().and_then(|scope| SortScope::parse(&scope));
+                    sort_scope = scope.ok_or("--sort-scope needs one of: file, all")?;
+                }
+                "--sort" | "--sortr" => {
+                    sort_reverse = arg == "--sortr";
+                    let key = args.next().and_then(|key| SortKey::parse(&key));
+                    sort = Some(key.ok_or("--sort needs one of: path, modified, size, priority")?);
+                }
+                "--from-line" => {
+                    let n = args.next().and_then(|n| n.parse::<usize>().ok());
+                    from_line = Some(
+                        n.filter(|n| *n > 0)
+                            .ok_or("--from-line needs a line number")?,
+                    );
+                }
+                "--to-line" => {
+                    let n = args.next().and_then(|n| n.parse::<usize>().ok());
+                    to_line = Some(
+                        n.filter(|n| *n > 0)
+                            .ok_or("--to-line needs a line number")?,
+                    );
+                }
+                "--start-offset" => {
+                    let offset = args.next().and_then(|offset| parse_size(&offset));
+                    start_offset = Some(offset.ok_or("--start-offset needs a byte offset")? as u64);
+                }
+                "--end-offset" => {
+                    let offset = args.next().and_then(|offset| parse_size(&offset));
+                    end_offset = Some(offset.ok_or("--end-offset needs a byte offset")? as u64);
+                }
+                "-A" | "--after-context" | "-B" | "--before-context" | "-C" | "--context" => {
+                    let n = args.next().and_then(|n| n.parse::<usize>().ok());
+                    let n = n.ok_or("-A, -B and -C need a number of lines")?;
+                    if !matches!(arg, "-B" | "--before-context") {
+                        after_context = n;
+                    }
+                    if !matches!(arg, "-A" | "--after-context") {
+                        before_context = n;
+                    }
+                }
+                "--block-context" => block_context = true,
+                "--show-function" => show_function = true,
+                "--json-input" => json_input = true,
+                "--csv" => csv = true,
+                "--html" => html = true,
+                "--selector" => {
+                    html = true;
+                    let text = args.next().filter(|text| Selector::parse(text).is_some());
+                    selector =
+                        Some(text.ok_or("--selector needs a selector like div.note or #main p")?);
+                }
+                "--field" => {
+                    csv = true;
+                    fields.push(args.next().ok_or("--field needs a column name or number")?);
+                }
+                "--delimiter" => {
+                    let chars: Vec<char> = args.next().unwrap_or_default().chars().collect();
+                    delimiter = match chars[..] {
+                        [c] if c != '"' && c != '\n' => c,
+                        ['\\', 't'] => '\t',
+                        _ => return Err("--delimiter needs a single character, like ; or \\t"),
+                    };
+                }
+                "--function-start" => {
+                    show_function = true;
+                    function_start = Some(
+                        args.next()
+                            .ok_or("--function-start needs the text that starts a function")?,
+                    );
+                }
+                "--group-separator" => {
+                    group_separator = args
+                        .next()
+                        .ok_or("--group-separator needs the text to print between blocks")?;
+                }
+                "-M" | "--max-columns" => {
+                    let n = args.next().and_then(|n| n.parse::<usize>().ok());
+                    max_columns = Some(
+                        n.filter(|n| *n > 0)
+                            .ok_or("--max-columns needs a number of columns")?,
+                    );
+                }
+                "--tabs" => {
+                    let n = args.next().and_then(|n| n.parse::<usize>().ok());
+                    tab_width = Some(
+                        n.filter(|n| *n > 0)
+                            .ok_or("--tabs needs a tab width in columns")?,
+                    );
+                }
+                "--hyperlink-format" => {
+                    hyperlink_format = Some(
+                        args.next()
+                            .ok_or("--hyperlink-format needs a URL template, like file://{path}")?,
+                    );
+                }
+                "--color" => {
+                    let when = args.next().and_then(|when| ColorChoice::parse(&when));
+                    color = when.ok_or("--color needs one of: auto, always, never")?;
+                }
+                "--stats-format" => {
+                    let format = args.next().and_then(|format| StatsFormat::parse(&format));
+                    stats_format = Some(format.ok_or("--stats-format needs one of: text, json")?);
+                }
+                "--error-format" => {
+                    let format = args.next().and_then(|format| ErrorFormat::parse(&format));
+                    error_format = format.ok_or("--error-format needs one of: text, json")?;
+                }
+                "--git-rev" => {
+                    // Passed to git, which would take a leading `-` as an
+                    // option.
+                    git_rev = Some(
+                        args.next()
+                            .filter(|rev| !rev.starts_with('-'))
+                            .ok_or("--git-rev needs a commit, branch or tag")?,
+                    );
+                }
+                "--changed" => changed = true,
+                "--no-ignore" => {
+                    no_ignore = true;
+                    no_ignore_vcs = true;
+                    no_ignore_global = true;
+                }
+                "--no-ignore-vcs" => no_ignore_vcs = true,
+                "-L" | "--follow-links" => follow_links = true,
+                "--no-sniff" => no_sniff = true,
+                "--devices" => {
+                    let action = args.next().and_then(|action| Devices::parse(&action));
+                    devices = action.ok_or("--devices needs one of: skip, read")?;
+                }
+                "--directories" => {
+                    let action = args.next().and_then(|action| Directories::parse(&action));
+                    directories =
+                        action.ok_or("--directories needs one of: error, skip, recurse")?;
+                }
+                "--no-ignore-global" => no_ignore_global = true,
+                "--changed-since" => {
+                    changed = true;
+                    changed_since = Some(
+                        args.next()
+                            .filter(|rev| !rev.starts_with('-'))
+                            .ok_or("--changed-since needs a commit, branch or tag")?,
+                    );
+                }
+                "--serve" => {
+                    serve = Some(
+                        args.next()
+                            .ok_or("--serve needs a socket path or a localhost:PORT address")?,
+                    );
+                }
+                "--completions" => {
+                    let shell = args.next().and_then(|shell| Shell::parse(&shell));
+                    completions = Some(
+                        shell.ok_or("--completions needs one of: bash, zsh, fish, powershell")?,
+                    );
+                }
+                "--colors" => {
+                    let spec = args
+                        .next()
+                        .ok_or("--colors needs a spec like match:fg:yellow")?;
+                    theme.apply(&spec)?;
+                }
+                // Everything after `--` is a query or a path, even if it
+                // starts with `-`.
+                "--" => positional.extend(args.args.by_ref()),
+                // `-` alone is stdin.
+                _ if arg.starts_with('-') && arg != "-" => {
+                    return Err("unknown flag; put -- before a query or path that starts with -");
+                }
+                _ => positional.push(raw),
+            }
+        }
+
+        #[cfg(not(feature = "json"))]
+        if serve.is_some() {
+            return Err("--serve needs minigrep built with the json feature");
+        }
+        if !cfg!(feature = "color") && color == ColorChoice::Always {
+            return Err("--color always needs minigrep built with the color feature");
+        }
+        // A server takes its queries and paths from each request, and a
+        // completion script needs neither.
+        if (serve.is_some() || completions.is_some()) && positional.is_empty() {
+            positional = vec![OsString::new(), OsString::new()];
+        }
+        // Indexing has no query, only a directory,
+        // and the interactive modes only need somewhere to search.
+        if command == Command::Index || ((tui || interactive) && positional.len() == 1) {
+            positional.insert(0, OsString::new());
+        }
+        // Any number of queries, then the replacement for `replace`, and
+        // the path last.
+        let needed = match command {
+            Command::Replace => 3,
+            _ => 2,
+        };
+        if positional.len() == 1
+            && needed == 2
+            && let Some(query) = prompt()
+        {
+            positional.insert(0, query.into());
+        }
+        match positional.len() {
+            0 => return Err("Didn't get a query string"),
+            1 if command == Command::Replace => return Err("Didn't get a replacement"),
+            n if n < needed => return Err("Didn't get a file path"),
+            _ => {}
+        }
+        let file_path = PathBuf::from(positional.pop().unwrap_or_default());
+        let replacement = match command {
+            Command::Replace => match positional.pop().map(OsString::into_string) {
+                Some(Ok(arg)) => Some(arg),
+                _ => return Err("the replacement isn't valid UTF-8"),
+            },
+            _ => None,
+        };
+        let mut queries = positional
+            .into_iter()
+            .map(OsString::into_string)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| "the query isn't valid UTF-8")?;
+        if escapes {
+            queries = queries
+                .iter()
+                .map(|query| unescape(query))
+                .collect::<Result<_, _>>()?;
+        }
+        if directories == Directories::Recurse && file_path.is_dir() {
+            recursive = true;
+        }
+
+        if let (Some(from_line), Some(to_line)) = (from_line, to_line)
+            && from_line > to_line
+        {
+            return Err("--from-line can't be after --to-line");
+        }
+
+        if let (Some(start_offset), Some(end_offset)) = (start_offset, end_offset)
+            && start_offset > end_offset
+        {
+            return Err("--start-offset can't be after --end-offset");
+        }
+
+        if file_path.to_str().is_some_and(download::is_url)
+            && (recursive
+                || follow
+                || in_place
+                || json_input
+                || csv
+                || html
+                || git_rev.is_some()
+                || start_offset.is_some()
+                || end_offset.is_some())
+        {
+            return Err(
+                "a URL is searched as it downloads, so can't be used with -r, --follow, --in-place, --json-input, --csv, --html, --git-rev or byte offsets",
+            );
+        }
+        if follow && recursive {
+            return Err("--follow needs a single file, not -r");
+        }
+        if follow && (to_line.is_some() || end_offset.is_some()) {
+            return Err("--follow can't stop at --to-line or --end-offset");
+        }
+
+        if preserve_case && command != Command::Replace {
+            return Err("--preserve-case only works with replace");
+        }
+        if in_place && command != Command::Replace {
+            return Err("--in-place only works with replace");
+        }
+        if !in_place && (backup_suffix.is_some() || backup_dir.is_some()) {
+            return Err("--backup-suffix and --backup-dir only work with --in-place");
+        }
+        // The buffer comes out of the memory budget, and the line being
+        // matched needs some of it too.
+        if let (Some(size), Some(limit)) = (buffer_size, max_memory)
+            && size >= limit
+        {
+            return Err("--buffer-size has to be smaller than --max-memory");
+        }
+        if in_place
+            && (max_memory.is_some() || start_offset.is_some() || end_offset.is_some() || follow)
+        {
+            return Err(
+                "--in-place rewrites whole files, so can't be used with --max-memory, byte offsets or --follow",
+            );
+        }
+        if command == Command::Count {
+            count = count.or(Some(Count::Lines));
+        }
+        // These print something other than the matching lines, so there's
+        // nothing to put the lines around them next to.
+        let summarising = count.is_some()
+            || only_matching
+            || unique
+            || histogram.is_some()
+            || in_place
+            || command == Command::Files;
+        if passthru && summarising {
+            return Err(
+                "--passthru prints every line, so can't be used with counts, -o, --unique, --histogram, --in-place or files",
+            );
+        }
+        // Asking how the statistics are printed asks for them too.
+        if stats_format.is_some() {
+            stats = stats.or(Some(StatsLevel::Basic));
+        }
+        let stats_format = stats_format.unwrap_or_default();
+        // `--porcelain` only fixes how lines are printed; summaries keep
+        // their own formats.
+        if porcelain
+            && (count.is_some()
+                || unique
+                || top.is_some()
+                || histogram.is_some()
+                || stats.is_some()
+                || vimgrep
+                || command == Command::Files)
+        {
+            return Err(
+                "--porcelain prints lines, so can't be used with counts, files, --unique, --top, --histogram, --stats or --vimgrep",
+            );
+        }
+        if explain && (only_matching || vimgrep || porcelain) {
+            return Err(
+                "--explain annotates whole lines, so can't be used with -o, --vimgrep or --porcelain",
+            );
+        }
+        // Headings go above a file's lines as they're printed, so they're
+        // only for when the lines are printed file by file.
+        let grouped = count.is_none()
+            && !unique
+            && top.is_none()
+            && histogram.is_none()
+            && !vimgrep
+            && !porcelain
+            && tail.is_none()
+            && sort_lines == SortLines::None
+            && !in_place
+            && command != Command::Files;
+        if heading == Some(true) && !grouped {
+            return Err(
+                "--heading groups lines by file, so can't be used with counts, files, --unique, --top, --histogram, --vimgrep, --porcelain, --tail, --sort-lines or --in-place",
+            );
+        }
+        let heading = heading.unwrap_or(terminal && grouped);
+        // Someone watching the terminal would rather see some results soon
+        // than all of them a little sooner.
+        if terminal {
+            sort = sort.or(Some(SortKey::Priority));
+        }
+        let mut line_number =
+            line_number.unwrap_or(terminal && start_offset.is_none_or(|offset| offset == 0));
+        // `--tail` and `--sort-lines` hold on to what each matching line
+        // printed, which is nothing for a summary and only part of the story
+        // with context.
+        if (tail.is_some() || sort_lines != SortLines::None)
+            && (passthru
+                || follow
+                || before_context > 0
+                || after_context > 0
+                || block_context
+                || show_function
+                || (summarising && !only_matching))
+        {
+            return Err(
+                "--tail and --sort-lines keep matching lines, so can't be used with context, --passthru, --follow, counts, --unique, --histogram, --in-place or files",
+            );
+        }
+        if (before_context > 0 || after_context > 0 || block_context || show_function)
+            && summarising
+        {
+            return Err(
+                "context and --show-function can't be used with counts, -o, --unique, --histogram, --in-place or files",
+            );
+        }
+        if [json_input, csv, html].iter().filter(|mode| **mode).count() > 1 {
+            return Err("only one of --json-input, --csv and --html can be used");
+        }
+        // Rows are reported by number, as lines usually are with -n.
+        line_number = line_number || csv || vimgrep;
+        column = column || vimgrep;
+        // Counting lines would mean reading everything before the offset.
+        if start_offset.is_some_and(|offset| offset > 0)
+            && (line_number || from_line.is_some() || to_line.is_some())
+        {
+            return Err("line numbers aren't known when starting from --start-offset");
+        }
+        if (json_input || csv || html)
+            && (before_context > 0
+                || after_context > 0
+                || block_context
+                || show_function
+                || passthru
+                || follow
+                || in_place
+                || max_memory.is_some()
+                || start_offset.is_some()
+                || end_offset.is_some())
+        {
+            return Err(
+                "--json-input, --csv and --html read whole files, so can't be used with context, --passthru, --follow, --in-place, --max-memory or byte offsets",
+            );
+        }
+        if git_rev.is_some()
+            && (follow
+                || in_place
+                || update_index
+                || max_memory.is_some()
+                || start_offset.is_some()
+                || end_offset.is_some())
+        {
+            return Err(
+                "--git-rev reads files from git, so can't be used with --follow, --in-place, --update-index, --max-memory or byte offsets",
+            );
+        }
+        // Changes are found across a tree, so that's what's searched.
+        recursive = recursive || changed;
+        if changed && git_rev.is_some() {
+            return Err("--changed can't be used with --git-rev");
+        }
+        if block_context && (before_context > 0 || after_context > 0) {
+            return Err("--block-context can't be used with -A, -B or -C");
+        }
+
+        if overlapping && group.is_some() {
+            return Err("--overlapping can't be used with --group");
+        }
+        if top.is_some() && !only_matching {
+            return Err("--top only works with -o");
+        }
+
+        let ignore_case = ignore_case.unwrap_or_else(|| {
+            !no_env && env::var("IGNORE_CASE").is_ok_and(|value| is_truthy(&value))
+        });
+        Ok(Config {
+            search: SearchOptions {
+                queries,
+                ignore_case,
+                engine,
+                group,
+                overlapping,
+                synonyms,
+            },
+            input: InputOptions {
+                file_path,
+                max_memory,
+                buffer_size,
+                recursive,
+                max_filesize,
+                newer_than,
+                older_than,
+                sort,
+                sort_reverse,
+                from_line,
+                to_line,
+                start_offset,
+                end_offset,
+                follow,
+                json_input,
+                csv,
+                fields,
+                delimiter,
+                html,
+                selector,
+                git_rev,
+                changed,
+                changed_since,
+                no_ignore,
+                no_ignore_vcs,
+                no_ignore_global,
+                follow_links,
+                no_sniff,
+                devices,
+                directories,
+                update_index,
+            },
+            output: OutputOptions {
+                max_total,
+                head,
+                tail,
+                sort_lines,
+                sort_scope,
+                line_number,
+                heading,
+                column,
+                vimgrep,
+                porcelain,
+                explain,
+                max_columns,
+                tab_width,
+                wrap,
+                hyperlink_format,
+                color,
+                theme,
+                pager,
+                buffering,
+                line_terminator,
+                progress,
+                stats,
+                stats_format,
+                count,
+                count_per_file,
+                include_zero,
+                count_files,
+                unique,
+                unique_count,
+                only_matching,
+                top,
+                histogram,
+                quiet,
+                message_on_no_match,
+                passthru,
+                before_context,
+                after_context,
+                group_separator,
+                block_context,
+                show_function,
+                function_start,
+            },
+            no_env,
+            lang: lang.unwrap_or_else(|| match no_env {
+                true => Lang::English,
+                false => Lang::from_env(),
+            }),
+            error_format,
+            timeout,
+            verbosity,
+            explain_skips,
+            command,
+            replacement,
+            preserve_case,
+            in_place,
+            backup_suffix,
+            backup_dir,
+            tui,
+            interactive,
+            serve,
+            completions,
+        })
+    }
+
+    /// Decides from a file's metadata alone whether it should be searched,
+    /// returning the rule that rules it out if not.
+    fn skip_reason(&self, metadata: &Metadata) -> Option<&'static str> {
+        if metadata.is_symlink() {
+            return Some("symlink, not followed");
+        }
+        if !metadata.is_file() {
+            match metadata.special_kind() {
+                Some(_) if self.input.devices == Devices::Read => {}
+                Some(kind) => return Some(kind),
+                None => return Some("not a regular file"),
+            }
+        }
+
+        if self
+            .input
+            .max_filesize
+            .is_some_and(|max_filesize| metadata.len() > max_filesize)
+        {
+            return Some("larger than --max-filesize");
+        }
+
+        if self.input.newer_than.is_some() || self.input.older_than.is_some() {
+            let Ok(modified) = metadata.modified() else {
+                return Some("modification time unknown");
+            };
+            if self
+                .input
+                .newer_than
+                .is_some_and(|newer_than| modified < newer_than)
+            {
+                return Some("older than --newer-than");
+            }
+            if self
+                .input
+                .older_than
+                .is_some_and(|older_than| modified > older_than)
+            {
+                return Some("newer than --older-than");
+            }
+        }
+
+        None
+    }
+
+    /// Why a recursive search skips the file at `path` for what its first
+    /// bytes say it is. Only checked once the metadata has let it through.
+    fn sniff_reason(&self, fs: &dyn FileSystem, path: &Path) -> Option<&'static str> {
+        match self.input.no_sniff {
+            true => None,
+            false => sniff::skip_reason(fs, path),
+        }
+    }
+}
+
+/// Asks on the terminal for a query that wasn't given, for people who'd
+/// rather be asked than read an error. Only a terminal is asked, so a
+/// script still gets the error.
+fn prompt_for_query() -> Option<String> {
+    if !io::stdin().is_terminal() {
+        return None;
+    }
+    eprint!("query: ");
+    let mut query = String::new();
+    io::stdin().read_line(&mut query).ok()?;
+    let query = query.trim_end_matches(['\n', '\r']);
+    (!query.is_empty()).then(|| query.to_string())
+}
+
+/// Whether an environment variable's value turns its setting on. `0`,
+/// `false`, `no`, `off` and the empty string don't; anything else does.
+fn is_truthy(value: &str) -> bool {
+    let value = value.trim().to_ascii_lowercase();
+    !matches!(value.as_str(), "" | "0" | "false" | "no" | "off")
+}
+
+/// Parses a byte count with an optional `K`, `M` or `G` suffix (powers of
+/// 1024), e.g. `512`, `64K` or `10M`.
+fn parse_size(size: &str) -> Option<usize> {
+    let (digits, multiplier) = match size.char_indices().last()? {
+        (i, 'k' | 'K') => (&size[..i], 1 << 10),
+        (i, 'm' | 'M') => (&size[..i], 1 << 20),
+        (i, 'g' | 'G') => (&size[..i], 1 << 30),
+        _ => (size, 1),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+/// Interprets the escapes `--escapes` allows in a query: `\t`, `\n`,
+/// `\r`, `\0`, `\xNN` for an ASCII character by its hex code, and `\\`
+/// for a backslash. A backslash before anything else is kept, so a regex
+/// query can still use `\d` and the like.
+fn unescape(query: &str) -> Result<String, &'static str> {
+    let mut unescaped = String::with_capacity(query.len());
+    let mut chars = query.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('0') => unescaped.push('\0'),
+            Some('\\') => unescaped.push('\\'),
+            Some('x') => {
+                let code = chars
+                    .as_str()
+                    .get(..2)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match code {
+                    Some(code) if code.is_ascii() => unescaped.push(char::from(code)),
+                    _ => return Err("\\x in a query needs two hex digits, from 00 to 7F"),
+                }
+                chars.nth(1);
+            }
+            Some(c) => {
+                unescaped.push('\\');
+                unescaped.push(c);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    Ok(unescaped)
+}
+
+/// Parses either an age relative to now (`90s`, `30m`, `12h`, `2d`, `1w`) or
+/// a `YYYY-MM-DD` date, taken as midnight UTC.
+fn parse_time(time: &str) -> Option<SystemTime> {
+    if let Some((i, unit)) = time.char_indices().last()
+        && unit.is_ascii_alphabetic()
+    {
+        let seconds = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        let age = time[..i].parse::<u64>().ok()?.checked_mul(seconds)?;
+        return SystemTime::now().checked_sub(Duration::from_secs(age));
+    }
+
+    let mut parts = time.splitn(3, '-').map(|part| part.parse::<u32>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_length = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if !(1..=12).contains(&month) || !(1..=month_length).contains(&day) || year < 1970 {
+        return None;
+    }
+
+    // Days since 1970-01-01 in the proleptic Gregorian calendar, counting
+    // years from March so the leap day falls at the end.
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146_097 + day_of_era) as u64 - 719_468;
+
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(days * 24 * 60 * 60))
+}
+
+/// A handle for aborting a search that is in progress.
+///
+/// Clones share the same flag, so one copy can be handed to the search while
+/// another is kept around to call [`CancellationToken::cancel`].
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    /// Whether anything has found the token cancelled since it was.
+    noticed: Arc<AtomicBool>,
+    /// The token this one was made from by [`CancellationToken::child`].
+    parent: Option<Box<CancellationToken>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        let cancelled = self.cancelled.load(Ordering::Relaxed)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.is_cancelled());
+        if cancelled {
+            self.noticed.store(true, Ordering::Relaxed);
+        }
+        cancelled
+    }
+
+    /// Whether a search found the token cancelled and so stopped before it
+    /// was done. Cancelling it once the search has finished doesn't count.
+    pub fn cut_short(&self) -> bool {
+        self.noticed.load(Ordering::Relaxed)
+    }
+
+    /// A token that is cancelled along with this one, but can also be
+    /// cancelled by itself without affecting this one.
+    fn child(&self) -> CancellationToken {
+        CancellationToken {
+            cancelled: Arc::default(),
+            noticed: Arc::default(),
+            parent: Some(Box::new(self.clone())),
+        }
+    }
+
+    /// Cancels the token from a background thread once `timeout` has passed.
+    pub fn cancel_after(&self, timeout: Duration) {
+        let token = self.clone();
+        thread::spawn(move || {
+            thread::sleep(timeout);
+            token.cancel();
+        });
+    }
+}
+
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    run_with_cancel(config, &CancellationToken::new()).map(|_| ())
+}
+
+/// Searches the way `-q` does, printing nothing and stopping at the first
+/// matching line. Returns whether there was one.
+pub fn any_match(config: &Config, token: &CancellationToken) -> Result<bool, Box<dyn Error>> {
+    Searcher::new(config, token).search_all(&mut io::sink())
+}
+
+/// Runs the search `config` describes, handing what it finds to `handler`
+/// as it goes instead of printing it. Returns whether anything matched.
+pub fn search_events(
+    config: &Config,
+    token: &CancellationToken,
+    handler: &mut dyn Handler,
+) -> Result<bool, Box<dyn Error>> {
+    search_events_in(&Disk, config, token, handler)
+}
+
+/// Like [`search_events`], but finds and reads the files in `fs` rather
+/// than on disk.
+pub fn search_events_in(
+    fs: &dyn FileSystem,
+    config: &Config,
+    token: &CancellationToken,
+    handler: &mut dyn Handler,
+) -> Result<bool, Box<dyn Error>> {
+    let handler = RefCell::new(handler);
+    let emit = |event: Event| handler.borrow_mut().handle(event);
+    let mut searcher = Searcher::new(config, token);
+    searcher.events = Some(&emit);
+    searcher.fs = fs;
+    searcher.search_all(&mut io::sink())
+}
+
+/// Like [`run`], but stops early once `token` is cancelled and prints the
+/// lines found up to that point. Returns whether anything matched; the
+/// modes that don't search count as having matched.
+pub fn run_with_cancel(config: Config, token: &CancellationToken) -> Result<bool, Box<dyn Error>> {
+    if let Some(shell) = config.completions {
+        io::stdout().write_all(completions::script(shell).as_bytes())?;
+        return Ok(true);
+    }
+    #[cfg(feature = "json")]
+    if let Some(address) = &config.serve {
+        return serve::serve(address).map(|()| true);
+    }
+    if config.tui {
+        return tui::run(&config).map(|()| true);
+    }
+    if config.interactive {
+        return repl::run(&config, &mut io::stdout()).map(|()| true);
+    }
+    if config.command == Command::Index {
+        let root = Path::new(&config.input.file_path);
+        let mut index = Index::load(&Disk, root)
+            .unwrap_or_else(|e| {
+                let path = root.join(index::INDEX_FILE);
+                eprintln!("minigrep: {}: {e}, rebuilding it", path.display());
+                None
+            })
+            .unwrap_or_default();
+        let changes = index.update(&Disk, root);
+        let path = index.save(root)?;
+        println!(
+            "indexed {} files into {} ({changes})",
+            index.len(),
+            path.display()
+        );
+        return Ok(true);
+    }
+
+    // A pager would hold back followed lines until it had a screenful.
+    let buffering = match config.output.buffering {
+        Buffering::Auto if config.input.follow => Buffering::Line,
+        buffering => buffering,
+    };
+    let mut out = Output::open(config.output.pager && !config.input.follow, buffering);
+    let mut ends = LineEnds::new(&mut out, config.output.line_terminator);
+    let result = Searcher::new(&config, token).search_all(&mut ends);
+    let finished = out.finish();
+
+    match result.and_then(|matched| finished.map(|()| matched).map_err(Into::into)) {
+        // The reader went away, e.g. the pager was quit or `head` had enough,
+        // so there's nobody left to tell.
+        Err(e) if is_broken_pipe(e.as_ref()) => Ok(true),
+        result => result,
+    }
+}
+
+fn is_broken_pipe(e: &(dyn Error + 'static)) -> bool {
+    e.downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+}
+
+/// Replaces the file at `path` with `contents` so that it's never left
+/// half-written: they go to a temporary file beside it, which is synced
+/// and then renamed over it. The new file gets the old one's permissions
+/// and, where allowed, its owner. A symlink is followed, so the file it
+/// points at is the one replaced; a hard link stops being one.
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let path = match fs::symlink_metadata(path)?.is_symlink() {
+        true => fs::canonicalize(path)?,
+        false => path.to_path_buf(),
+    };
+    let metadata = fs::metadata(&path)?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let temp = dir.join(temp_name(&path));
+
+    let written = (|| {
+        let mut file = File::create_new(&temp)?;
+        file.write_all(contents)?;
+        file.set_permissions(metadata.permissions())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            // Only root can give a file away, so this is allowed to fail.
+            let _ = std::os::unix::fs::fchown(&file, Some(metadata.uid()), Some(metadata.gid()));
+        }
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written?;
+    // The rename is only durable once the directory is synced too, which
+    // Windows has no way to do.
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// The name of the file [`write_atomically`] writes before renaming it
+/// to `path`: hidden, and marked as minigrep's.
+fn temp_name(path: &Path) -> OsString {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".minigrep-{}.tmp", std::process::id()));
+    name
+}
+
+/// Prints each file in `fs` a search would consider, and whether it would be
+/// searched or which rule would skip it, without searching anything.
+fn explain_skips(
+    config: &Config,
+    fs: &dyn FileSystem,
+    root: &Path,
+    out: &mut dyn Write,
+) -> Result<(), Box<dyn Error>> {
+    // Shared with the report of what ignore rules left out, so everything
+    // is listed in the order the walk came to it.
+    let verdicts = RefCell::new(Vec::new());
+    let explain = |path: &Path, metadata: &Metadata| {
+        let reason = config
+            .skip_reason(metadata)
+            .or_else(|| config.sniff_reason(fs, path));
+        let verdict = match reason {
+            Some(reason) => format!("skipped, {reason}"),
+            None => "searched".to_string(),
+        };
+        verdicts
+            .borrow_mut()
+            .push(format!("{}: {verdict}", path.display()));
+        reason.is_none()
+    };
+
+    if config.input.recursive {
+        walk::walk_noting_ignored(
+            fs,
+            root,
+            &Ignores::new(config),
+            config.input.follow_links,
+            explain,
+            |path, rule| {
+                let verdict = format!("{}: skipped, ignored by {rule}", path.display());
+                verdicts.borrow_mut().push(verdict);
+            },
+        );
+    } else {
+        explain(root, &vfs::followed(fs, root)?);
+    }
+
+    for verdict in verdicts.into_inner() {
+        writeln!(out, "{verdict}")?;
+    }
+    Ok(())
+}
+
+/// The state of one run over the inputs a [`Config`] names.
+struct Searcher<'a> {
+    config: &'a Config,
+    /// Cancelled with the caller's token, or by the search itself once
+    /// `--max-total` is reached.
+    token: CancellationToken,
+    log: Log,
+    timings: Timings,
+    stats: Stats,
+    started: Instant,
+    /// For `--unique`, the text of each line printed so far, mapped to its
+    /// place in `tallies`.
+    seen: HashMap<String, usize>,
+    /// For `--unique-count`, each distinct line as it will be printed and
+    /// how many times it matched, in the order they were first seen.
+    tallies: Vec<(String, usize)>,
+    /// For `--top`, how many times each matched string was found.
+    frequencies: HashMap<String, usize>,
+    /// For `--histogram`, the number of matches in each group.
+    buckets: HashMap<String, usize>,
+    context: Context,
+    /// For `--git-rev`, where files are read from.
+    revision: Option<Revision>,
+    /// How many lines have matched in the files searched so far.
+    total: usize,
+    /// For `--tail`, what the latest matching lines printed, oldest first.
+    tail: VecDeque<Vec<u8>>,
+    /// For `--sort-lines`, each matching line, or match with `-o`, and what
+    /// it printed, waiting to be sorted.
+    sorted: Vec<(String, Vec<u8>)>,
+    /// For [`search_events`], where what's found goes instead of being
+    /// printed.
+    events: Option<Emit<'a>>,
+    /// Where files are found and read.
+    fs: &'a dyn FileSystem,
+    /// For `--heading`, whether a file's heading has been printed yet.
+    headed: Rc<Cell<bool>>,
+}
+
+impl<'a> Searcher<'a> {
+    fn new(config: &'a Config, token: &'a CancellationToken) -> Searcher<'a> {
+        Searcher {
+            config,
+            token: token.child(),
+            log: Log::new(config.verbosity),
+            timings: Timings::default(),
+            stats: Stats::default(),
+            started: Instant::now(),
+            seen: HashMap::new(),
+            tallies: Vec::new(),
+            frequencies: HashMap::new(),
+            buckets: HashMap::new(),
+            context: Context::new(config),
+            revision: None,
+            total: 0,
+            tail: VecDeque::new(),
+            sorted: Vec::new(),
+            events: None,
+            fs: &Disk,
+            headed: Rc::default(),
+        }
+    }
+
+    /// Searches everything and prints the summaries asked for. Returns
+    /// whether any line matched.
+    fn search_all(&mut self, out: &mut dyn Write) -> Result<bool, Box<dyn Error>> {
+        self.search_root(out)?;
+        self.log.debug(format_args!("timings: {}", self.timings));
+
+        self.print_sorted(out)?;
+        for printed in &self.tail {
+            out.write_all(printed)?;
+        }
+        if self.config.output.count_files {
+            writeln!(out, "{}", self.stats.files_matched)?;
+        }
+        for (line, count) in &self.tallies {
+            writeln!(out, "{count:>7} {line}")?;
+        }
+        if let Some(n) = self.config.output.top {
+            let mut frequencies: Vec<(&String, &usize)> = self.frequencies.iter().collect();
+            frequencies.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+            for (text, count) in frequencies.into_iter().take(n) {
+                writeln!(out, "{count:>7} {text}")?;
+            }
+        }
+        if self.config.output.histogram.is_some() {
+            let mut buckets: Vec<(&str, usize)> = self
+                .buckets
+                .iter()
+                .map(|(label, count)| (label.as_str(), *count))
+                .collect();
+            buckets.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            out.write_all(output::histogram(&buckets).as_bytes())?;
+        }
+
+        if let Some(level) = self.config.output.stats {
+            let timings = &self.timings;
+            self.stats.elapsed = self.started.elapsed();
+            let working = timings.reading + timings.matching + timings.printing;
+            self.stats.busy += working.saturating_sub(self.stats.waited);
+            match self.config.output.stats_format {
+                StatsFormat::Text => self.stats.write(out, level)?,
+                StatsFormat::Json => writeln!(out, "{}", self.stats.json(level))?,
+            }
+        }
+        if let Some(max_total) = self.config.output.max_total
+            && self.total >= max_total
+        {
+            out.flush()?;
+            let lang = self.config.lang;
+            let message = lang.format("stopped after {} matches (--max-total)", max_total);
+            eprintln!("minigrep: {message}");
+        }
+        if let Some(emit) = self.events {
+            emit(Event::SearchEnd(Summary {
+                files_searched: self.stats.files_searched,
+                files_matched: self.stats.files_matched,
+                matched_lines: self.stats.matched_lines,
+                elapsed: self.started.elapsed(),
+            }));
+        }
+        // Explaining skips searches nothing, so there's nothing to miss.
+        let matched = self.total > 0 || self.config.explain_skips;
+        if !matched && self.config.output.message_on_no_match {
+            out.flush()?;
+            let lang = self.config.lang;
+            let queries = self.config.search.queries.join("\", \"");
+            let message = lang.format("no matches found for \"{}\"", queries);
+            eprintln!("minigrep: {message}");
+        }
+        Ok(matched)
+    }
+
+    fn search_root(&mut self, out: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+        let config = self.config;
+        let root = Path::new(&config.input.file_path);
+
+        if config.explain_skips {
+            return explain_skips(config, self.fs, root, out);
+        }
+        // Built once for every file, which for Hyperscan means compiling
+        // its database once.
+        let matcher = Matcher::any(
+            &config.search.queries,
+            config.search.ignore_case,
+            config.search.engine,
+        )?;
+        if let Some(rev) = &config.input.git_rev {
+            return self.search_revision(rev, root, &matcher, out);
+        }
+        if let Some(paths) = expand::expand(self.fs, root) {
+            return self.search_expanded(root, &paths, &matcher, out);
+        }
+
+        if !config.input.recursive {
+            let metadata = vfs::followed(self.fs, root).ok();
+            if metadata.as_ref().is_some_and(Metadata::is_dir)
+                && config.input.directories == Directories::Error
+            {
+                let root = root.display();
+                return Err(format!("{root} is a directory; use -r to search it").into());
+            }
+            if let Some(reason) = metadata
+                .as_ref()
+                .and_then(|metadata| config.skip_reason(metadata))
+            {
+                // Only a special file is worth a warning; it's the one that
+                // can be read when asked for.
+                if metadata.as_ref().and_then(Metadata::special_kind).is_some() {
+                    eprintln!(
+                        "minigrep: {}: skipped, {reason}; --devices read reads it",
+                        root.display()
+                    );
+                }
+                self.log
+                    .verbose(format_args!("skipping {}: {reason}", root.display()));
+                self.stats.skip(reason);
+                return Ok(());
+            }
+            let show_path = config.output.count_per_file
+                || config.output.vimgrep
+                || config.command == Command::Files;
+            self.search_file(root, show_path, &matcher, out)?;
+            return Ok(());
+        }
+
+        let started = Instant::now();
+        let log = self.log;
+        let stats = &mut self.stats;
+        let mut index = Index::load(self.fs, root).unwrap_or_else(|e| {
+            eprintln!("minigrep: {}: {e}", root.join(index::INDEX_FILE).display());
+            None
+        });
+        if let Some(index) = &mut index
+            && config.input.update_index
+        {
+            let changes = index.update(self.fs, root);
+            self.log.verbose(format_args!("updated index: {changes}"));
+            if changes != index::Changes::default()
+                && let Err(e) = index.save(root)
+            {
+                eprintln!("minigrep: {}: {e}", root.join(index::INDEX_FILE).display());
+            }
+        }
+        // The index only knows which files hold a given piece of text.
+        let narrowing = index
+            .as_ref()
+            .and_then(|index| match &config.search.queries[..] {
+                [query] if config.search.engine.resolve(query) == Engine::Literal => {
+                    index.narrow(query)
+                }
+                _ => None,
+            });
+        let changed = match config.input.changed {
+            true => Some(git::changed(root, config.input.changed_since.as_deref())?),
+            false => None,
+        };
+        // A file reached by more than one path, through hard links or
+        // followed symlinks, is searched once, under the first path found.
+        let mut seen = HashSet::new();
+        let ignores = Ignores::new(config);
+        let fs = self.fs;
+        let mut files = walk::walk(
+            fs,
+            root,
+            &ignores,
+            config.input.follow_links,
+            |path, metadata| {
+                let relative = path.strip_prefix(root).unwrap_or(path);
+                if relative == Path::new(index::INDEX_FILE) {
+                    return false;
+                }
+                let ruled_out = narrowing
+                    .as_ref()
+                    .is_some_and(|narrowing| narrowing.rules_out(relative, metadata));
+                let unchanged = changed
+                    .as_ref()
+                    .is_some_and(|changed| metadata.is_file() && !changed.contains(relative));
+                let reason = config
+                    .skip_reason(metadata)
+                    .or(ruled_out.then_some("ruled out by the index"))
+                    .or(unchanged.then_some("unchanged in git"))
+                    .or_else(|| config.sniff_reason(fs, path))
+                    .or_else(|| {
+                        let linked = metadata.identity.is_some_and(|id| !seen.insert(id));
+                        linked.then_some("same file as one already searched")
+                    });
+                match reason {
+                    Some(reason) => {
+                        log.verbose(format_args!("skipping {}: {reason}", path.display()));
+                        stats.skip(reason);
+                        false
+                    }
+                    None => true,
+                }
+            },
+        );
+        if let Some(key) = config.input.sort {
+            walk::sort(fs, &mut files, key, config.input.sort_reverse);
+        }
+        self.timings.walking = started.elapsed();
+        self.log.debug(format_args!(
+            "found {} files in {:?}",
+            files.len(),
+            self.timings.walking
+        ));
+
+        // Log lines would tear through the progress line, and say more anyway.
+        let mut progress = Progress::new(config.output.progress && self.log.is_quiet());
+        let mut matches = 0;
+        for (searched, path) in files.iter().enumerate() {
+            if self.token.is_cancelled() {
+                break;
+            }
+            progress.update(path, searched, matches);
+            // A single unreadable or non-UTF-8 file shouldn't end a recursive
+            // search, so report it and move on.
+            match self.search_file(path, true, &matcher, &mut progress.clearing(out)) {
+                Ok(found) => matches += found,
+                Err(e) if is_broken_pipe(e.as_ref()) => return Err(e),
+                Err(e) => {
+                    progress.clear();
+                    self.config.report(&path.display().to_string(), e.as_ref());
+                    self.stats.skip("unreadable");
+                }
+            }
+        }
+        progress.clear();
+
+        Ok(())
+    }
+
+    /// Searches each of `paths`, which wildcards in `pattern` expanded to,
+    /// naming the file on each line as a search of several files does.
+    fn search_expanded(
+        &mut self,
+        pattern: &Path,
+        paths: &[PathBuf],
+        matcher: &Matcher,
+        out: &mut dyn Write,
+    ) -> Result<(), Box<dyn Error>> {
+        if paths.is_empty() {
+            return Err(format!("no files match {}", pattern.display()).into());
+        }
+        if self.config.input.recursive {
+            return Err("-r can't be used with wildcards in the path".into());
+        }
+        for path in paths {
+            if self.token.is_cancelled() {
+                break;
+            }
+            let reason = vfs::followed(self.fs, path)
+                .ok()
+                .and_then(|metadata| self.config.skip_reason(&metadata));
+            if let Some(reason) = reason {
+                self.log
+                    .verbose(format_args!("skipping {}: {reason}", path.display()));
+                self.stats.skip(reason);
+                continue;
+            }
+            match self.search_file(path, true, matcher, out) {
+                Ok(_) => {}
+                Err(e) if is_broken_pipe(e.as_ref()) => return Err(e),
+                Err(e) => {
+                    self.config.report(&path.display().to_string(), e.as_ref());
+                    self.stats.skip("unreadable");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Searches `root` as it was at the git revision `rev`, naming each file
+    /// as `rev:path`.
+    fn search_revision(
+        &mut self,
+        rev: &str,
+        root: &Path,
+        matcher: &Matcher,
+        out: &mut dyn Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let revision = Revision::open(rev, root)?;
+        let files = revision.files(root)?;
+        if files.is_empty() {
+            return Err(format!("{} isn't in {rev}", root.display()).into());
+        }
+        if files.len() > 1 && !self.config.input.recursive {
+            return Err(format!("{} is a directory in {rev}; use -r", root.display()).into());
+        }
+        self.revision = Some(revision);
+        let show_path = self.config.input.recursive
+            || self.config.output.count_per_file
+            || self.config.output.vimgrep
+            || self.config.command == Command::Files;
+        for path in &files {
+            if self.token.is_cancelled() {
+                break;
+            }
+            match self.search_file(path, show_path, matcher, out) {
+                Ok(_) => {}
+                Err(e) if is_broken_pipe(e.as_ref()) => return Err(e),
+                Err(e) => {
+                    self.config
+                        .report(&format!("{rev}:{}", path.display()), e.as_ref());
+                    self.stats.skip("unreadable");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Searches one file for what `matcher` matches and prints its matching
+    /// lines, prefixed with the file's path when `show_path` is set. Returns
+    /// the number of lines that matched.
+    fn search_file(
+        &mut self,
+        path: &Path,
+        show_path: bool,
+        matcher: &Matcher,
+        out: &mut dyn Write,
+    ) -> Result<usize, Box<dyn Error>> {
+        let config = self.config;
+        self.log.verbose(format_args!("opening {}", path.display()));
+        let started = Instant::now();
+
+        // With `--passthru` or context every line is wanted, matching or not.
+        self.context.start_file();
+        let everything = Matcher::new("", false);
+        let every_line = config.output.passthru || self.context.wanted();
+        let wanted = if every_line { &everything } else { matcher };
+        let lines =
+            config.input.from_line.unwrap_or(1)..=config.input.to_line.unwrap_or(usize::MAX);
+        let shown = match &self.revision {
+            Some(revision) => PathBuf::from(format!("{}:{}", revision.name, path.display())),
+            None => path.to_path_buf(),
+        };
+        let show_path = show_path || config.output.porcelain;
+        // With `--heading` the path is printed once, above the file's lines.
+        let heading = show_path && config.output.heading;
+        let printer = Printer::new(
+            config,
+            matcher,
+            (show_path && !heading).then_some(shown.as_path()),
+        )
+        .reporting_to(self.events, &shown);
+        let mut headed = Headed::new(
+            out,
+            heading.then(|| printer.heading(&shown)),
+            Rc::clone(&self.headed),
+        );
+        let out: &mut dyn Write = &mut headed;
+        if let Some(emit) = self.events {
+            emit(Event::FileStart(&shown));
+        }
+        // Cancelled once reading further can't change what's printed: with
+        // `files` after the first match, and with `-q` for the whole search.
+        let finished = self.token.child();
+        let first_is_enough = config.command == Command::Files
+            && config.output.count.is_none()
+            && config.output.histogram.is_none()
+            && !config.in_place;
+        let mut matches = 0;
+        let mut occurrences = 0;
+        let mut printing = Duration::ZERO;
+        let spans = |line: &str| match &config.search.group {
+            Some(group) => matcher.group_spans(line, group),
+            None if config.search.overlapping => matcher.find_overlapping(line),
+            None => matcher.find_all(line),
+        };
+        let mut print = |line_number: usize, line: &str| {
+            if config.output.passthru && !matcher.is_match(line) {
+                return printer.print_context(out, line_number, line);
+            }
+            if self.context.wanted() {
+                if !matcher.is_match(line) {
+                    return self.context.other(&printer, out, line_number, line);
+                }
+                self.context.matched(&printer, out, line_number, line)?;
+            }
+            matches += 1;
+            if config.output.quiet
+                || config
+                    .output
+                    .max_total
+                    .into_iter()
+                    .chain(config.output.head)
+                    .any(|limit| self.total + matches >= limit)
+            {
+                self.token.cancel();
+            } else if first_is_enough {
+                finished.cancel();
+            }
+            if let Some(emit) = self.events {
+                emit(Event::Match(MatchData {
+                    path: &shown,
+                    line_number,
+                    line,
+                    spans: spans(line),
+                }));
+                return Ok(());
+            }
+            if config.output.histogram.is_some()
+                || config.in_place
+                || config.command == Command::Files
+            {
+                return Ok(());
+            }
+            match config.output.count {
+                Some(Count::Lines) => return Ok(()),
+                Some(Count::Matches) => {
+                    occurrences += spans(line).len();
+                    return Ok(());
+                }
+                None => {}
+            }
+            if config.output.top.is_some() {
+                for span in spans(line) {
+                    *self.frequencies.entry(line[span].to_string()).or_default() += 1;
+                }
+                return Ok(());
+            }
+            if config.output.unique {
+                if let Some(&i) = self.seen.get(line) {
+                    if let Some((_, count)) = self.tallies.get_mut(i) {
+                        *count += 1;
+                    }
+                    return Ok(());
+                }
+                self.seen.insert(line.to_string(), self.tallies.len());
+                if config.output.unique_count {
+                    let text = printer.format_match(line_number, line);
+                    self.tallies.push((text, 1));
+                    return Ok(());
+                }
+            }
+            let started = Instant::now();
+            // What to print: each match and the column it starts at for -o
+            // and --vimgrep, or else the line.
+            let mut texts: Vec<(Option<usize>, &str)> = Vec::new();
+            if config.output.only_matching || config.output.vimgrep {
+                for span in spans(line) {
+                    let text = if config.output.only_matching {
+                        &line[span.clone()]
+                    } else {
+                        line
+                    };
+                    texts.push((Some(span.start + 1), text));
+                }
+            }
+            if texts.is_empty() && !config.output.only_matching {
+                texts.push((None, line));
+            }
+            let print_one = |sink: &mut dyn Write, (column, text): (Option<usize>, &str)| {
+                match column {
+                    Some(column) => printer.print_match_at(sink, line_number, column, text)?,
+                    None => printer.print_match(sink, line_number, text)?,
+                }
+                if config.output.explain {
+                    printer.print_explanation(sink, line)?;
+                }
+                Ok(())
+            };
+            let result = if config.output.sort_lines != SortLines::None {
+                texts.into_iter().try_for_each(|(column, text)| {
+                    let mut printed = Vec::new();
+                    print_one(&mut printed, (column, text))?;
+                    self.sorted.push((text.to_string(), printed));
+                    Ok(())
+                })
+            } else if let Some(n) = config.output.tail {
+                let mut printed = Vec::new();
+                let result = texts
+                    .into_iter()
+                    .try_for_each(|text| print_one(&mut printed, text));
+                keep_last(&mut self.tail, n, printed);
+                result
+            } else {
+                texts.into_iter().try_for_each(|text| print_one(out, text))
+            };
+            printing += started.elapsed();
+            result
+        };
+
+        let mut reading = Duration::ZERO;
+        // Where the search stopped, as a byte offset and a line number, for
+        // `--follow` to carry on from.
+        let mut end = (0, 0);
+        let url = path.to_str().filter(|path| download::is_url(path));
+        let metadata = match (&self.revision, url) {
+            (None, None) => vfs::followed(self.fs, path).ok(),
+            _ => None,
+        };
+        // A pipe or device has no end to read up to, so it's streamed.
+        let special = metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.special_kind().is_some());
+        if url.is_some()
+            || special
+            || config.input.max_memory.is_some()
+            || config.input.start_offset.is_some()
+            || config.input.end_offset.is_some()
+        {
+            let len = metadata
+                .as_ref()
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len());
+            let size = config
+                .input
+                .buffer_size
+                .unwrap_or_else(|| buffer_size_for(len));
+            // Give the read buffer a slice of the memory budget and leave the
+            // rest for the line currently being matched.
+            let (capacity, max_line) = match config.input.max_memory {
+                Some(limit) => {
+                    let capacity = match config.input.buffer_size {
+                        Some(size) => size,
+                        None => (limit / 4).clamp(1, size),
+                    };
+                    (capacity, limit - capacity)
+                }
+                None => (size, usize::MAX),
+            };
+
+            let start = config.input.start_offset.unwrap_or(0);
+            let source: Box<dyn Read + '_> = match url {
+                Some(url) => Box::new(Download::start(url)?),
+                None => {
+                    let mut file = self.fs.open(path)?;
+                    // Look at the byte just before the start so a line that
+                    // began earlier is left to whoever searched the previous
+                    // range. A pipe can't seek, but then it starts at 0.
+                    if start > 0 {
+                        file.seek(SeekFrom::Start(start - 1))?;
+                    }
+                    Box::new(file)
+                }
+            };
+            let mut reader = BufReader::with_capacity(capacity, source);
+            let mut skipped = 0;
+            if start > 0 {
+                let mut previous = [0];
+                if reader.read(&mut previous)? == 0 {
+                    return Ok(0);
+                }
+                if previous[0] != b'\n' {
+                    skipped = skip_line(&mut reader)?;
+                }
+            }
+
+            let limits = Limits {
+                max_line,
+                lines,
+                bytes: config
+                    .input
+                    .end_offset
+                    .map_or(u64::MAX, |end| end.saturating_sub(start + skipped)),
+            };
+            let consumed = search_reader(wanted, reader, &limits, &finished, &mut print)?;
+            end = (start + skipped + consumed.bytes, consumed.lines);
+            self.stats.bytes_read += consumed.bytes;
+            self.stats.peak_buffer = self.stats.peak_buffer.max(capacity + consumed.buffer);
+        } else {
+            let contents = match &mut self.revision {
+                Some(revision) => String::from_utf8(revision.read(path)?)?,
+                None => match config.input.buffer_size {
+                    Some(size) => vfs::read_to_string_by(self.fs, path, size)?,
+                    None => vfs::read_to_string(self.fs, path)?,
+                },
+            };
+            reading = started.elapsed();
+            self.stats.bytes_read += contents.len() as u64;
+            self.stats.peak_buffer = self.stats.peak_buffer.max(contents.capacity());
+
+            let hits = if config.input.json_input {
+                Some(structured::json_hits(&contents, matcher)?)
+            } else if config.input.csv {
+                let delimiter = config.input.delimiter;
+                Some(structured::csv_hits(
+                    &contents,
+                    matcher,
+                    delimiter,
+                    &config.input.fields,
+                )?)
+            } else if config.input.html {
+                let selector = config.input.selector.as_deref().and_then(Selector::parse);
+                Some(structured::html_hits(&contents, matcher, selector.as_ref()))
+            } else {
+                None
+            };
+            if let Some(hits) = hits {
+                for (line_number, hit) in hits {
+                    if lines.contains(&line_number) {
+                        print(line_number, &hit)?;
+                    }
+                }
+                return Ok(matches);
+            }
+            // Splitting a file up only pays when there's a lot of it, and
+            // every line of it will be looked at.
+            #[cfg(feature = "parallel")]
+            let threads = thread::available_parallelism().map_or(1, usize::from);
+            #[cfg(feature = "parallel")]
+            let split = threads > 1
+                && contents.len() >= parallel::MIN_SIZE
+                && !every_line
+                && !first_is_enough
+                && !config.output.quiet;
+            #[cfg(not(feature = "parallel"))]
+            let split = false;
+            if split {
+                #[cfg(feature = "parallel")]
+                let work = parallel::search(
+                    &contents,
+                    config,
+                    threads,
+                    &finished,
+                    |line_number, line| {
+                        if line_number > *lines.end() || finished.is_cancelled() {
+                            return Ok::<_, Box<dyn Error>>(false);
+                        }
+                        if line_number >= *lines.start() {
+                            print(line_number, line)?;
+                        }
+                        Ok(true)
+                    },
+                )?;
+                #[cfg(feature = "parallel")]
+                {
+                    self.stats.threads = self.stats.threads.max(work.threads);
+                    self.stats.busy += work.busy;
+                    self.stats.waited += work.waited;
+                }
+            } else {
+                for (line_number, line) in (1..).zip(contents.lines()).skip(lines.start() - 1) {
+                    if line_number > *lines.end() || finished.is_cancelled() {
+                        break;
+                    }
+                    if wanted.is_match(line) {
+                        print(line_number, line)?;
+                    }
+                }
+            }
+            if config.input.follow {
+                end = (contents.len() as u64, contents.lines().count());
+            }
+            if let Some(replacement) = &config.replacement
+                && config.in_place
+            {
+                // Each line keeps the ending it had, and the last keeps
+                // having none if it had none, so only the replaced lines
+                // show up in a diff.
+                let mut replaced = String::with_capacity(contents.len());
+                let mut changed = 0;
+                for (line_number, raw) in (1..).zip(contents.split_inclusive('\n')) {
+                    let line = raw
+                        .strip_suffix('\n')
+                        .map_or(raw, |line| line.strip_suffix('\r').unwrap_or(line));
+                    if lines.contains(&line_number) && matcher.is_match(line) {
+                        replaced.push_str(&output::substitute(
+                            line,
+                            matcher,
+                            Some(replacement),
+                            config.preserve_case,
+                            "",
+                        ));
+                        changed += 1;
+                    } else {
+                        replaced.push_str(line);
+                    }
+                    replaced.push_str(&raw[line.len()..]);
+                }
+                if changed > 0 {
+                    if let Some(backup) = config.backup_path(path) {
+                        if let Some(dir) = backup.parent() {
+                            fs::create_dir_all(dir)?;
+                        }
+                        fs::copy(path, &backup)?;
+                    }
+                    write_atomically(path, replaced.as_bytes())?;
+                    self.log.verbose(format_args!(
+                        "replaced {changed} lines in {}",
+                        path.display()
+                    ));
+                }
+            }
+        }
+
+        if config.input.follow {
+            let mut tail = Tail::new(path, end.0, end.1)?;
+            while !finished.is_cancelled() {
+                match tail.next_line()? {
+                    Some((line_number, line)) if wanted.is_match(line) => {
+                        print(line_number, line)?;
+                    }
+                    Some(_) => {}
+                    None => thread::sleep(follow::POLL_INTERVAL),
+                }
+            }
+        }
+
+        let elapsed = started.elapsed();
+        self.timings.reading += reading;
+        self.timings.printing += printing;
+        self.timings.matching += elapsed - reading - printing;
+        if config.output.sort_scope == SortScope::File {
+            self.print_sorted(out)?;
+        }
+        if let Some(emit) = self.events {
+            emit(Event::FileEnd(FileStats {
+                path: &shown,
+                matched_lines: matches,
+            }));
+        }
+        self.total += matches;
+        self.stats.files_searched += 1;
+        self.stats.files_matched += usize::from(matches > 0);
+        self.stats.matched_lines += matches;
+        if let Some(histogram) = config.output.histogram
+            && matches > 0
+        {
+            let root = Path::new(&config.input.file_path);
+            let relative = path.strip_prefix(root).unwrap_or(path);
+            let label = match (histogram, relative.components().next()) {
+                (Histogram::Directory, Some(top)) => top.as_os_str().to_string_lossy().into_owned(),
+                _ => path.display().to_string(),
+            };
+            *self.buckets.entry(label).or_default() += matches;
+        }
+        if config.command == Command::Files && matches > 0 && !config.output.count_files {
+            printer.print_path(out)?;
+        }
+        if let Some(count) = config.output.count
+            && (matches > 0 || config.output.include_zero || !show_path)
+        {
+            let count = match count {
+                Count::Lines => matches,
+                Count::Matches => occurrences,
+            };
+            printer.print_count(out, count)?;
+        }
+        self.log.debug(format_args!(
+            "searched {} in {elapsed:?}: {matches} matches",
+            path.display()
+        ));
+
+        Ok(matches)
+    }
+
+    /// Prints the lines `--sort-lines` has been holding back, in order.
+    fn print_sorted(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let mut sorted = mem::take(&mut self.sorted);
+        self.config.output.sort_lines.sort(&mut sorted);
+        for (_, printed) in sorted {
+            match self.config.output.tail {
+                Some(n) => keep_last(&mut self.tail, n, printed),
+                None => out.write_all(&printed)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Adds `printed` to the `n` kept for `--tail`, dropping the oldest.
+fn keep_last(tail: &mut VecDeque<Vec<u8>>, n: usize, printed: Vec<u8>) {
+    if tail.len() == n {
+        tail.pop_front();
+    }
+    tail.push_back(printed);
+}
+
+const DEFAULT_BUF_SIZE: usize = 8 * 1024;
+
+/// The largest read buffer chosen without `--buffer-size`.
+const MAX_BUF_SIZE: usize = 1024 * 1024;
+
+/// The read buffer for streaming a file of `len` bytes: the default for a
+/// small file or one whose size isn't known, and larger for larger files,
+/// so fewer reads are needed where each one is slow to come back.
+fn buffer_size_for(len: Option<u64>) -> usize {
+    match len {
+        Some(len) => usize::try_from(len / 64)
+            .unwrap_or(MAX_BUF_SIZE)
+            .clamp(DEFAULT_BUF_SIZE, MAX_BUF_SIZE),
+        None => DEFAULT_BUF_SIZE,
+    }
+}
+
+/// How much of its input [`search_reader`] may look at.
+struct Limits {
+    /// The longest line that may be held in memory.
+    max_line: usize,
+    /// The line numbers to search; others are read past without matching.
+    lines: RangeInclusive<usize>,
+    /// Lines starting this many bytes or more into the reader are not read.
+    bytes: u64,
+}
+
+/// What [`search_reader`] went through to find its matches.
+struct Consumed {
+    bytes: u64,
+    /// The size the line buffer grew to.
+    buffer: usize,
+    /// How many lines were read.
+    lines: usize,
+}
+
+/// Reads `reader` one line at a time and hands each matching line to `emit`
+/// as soon as it is found, so only a single line is held in memory.
+fn search_reader<R: BufRead>(
+    matcher: &Matcher,
+    mut reader: R,
+    limits: &Limits,
+    token: &CancellationToken,
+    mut emit: impl FnMut(usize, &str) -> io::Result<()>,
+) -> io::Result<Consumed> {
+    let mut line = String::new();
+    let mut line_number = 0;
+    let mut consumed = 0;
+
+    while !token.is_cancelled() && line_number < *limits.lines.end() && consumed < limits.bytes {
+        line.clear();
+        let read = (&mut reader)
+            .take((limits.max_line as u64).saturating_add(1))
+            .read_line(&mut line)?;
+        if read == 0 {
+            break;
+        }
+        if read > limits.max_line && !line.ends_with('\n') {
+            return Err(io::Error::other("line is longer than --max-memory allows"));
+        }
+        consumed += read as u64;
+        line_number += 1;
+        if line_number < *limits.lines.start() {
+            continue;
+        }
+
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if matcher.is_match(line) {
+            emit(line_number, line)?;
+        }
+    }
+
+    Ok(Consumed {
+        bytes: consumed,
+        buffer: line.capacity(),
+        lines: line_number,
+    })
+}
+
+/// Consumes the rest of the current line without keeping it, returning the
+/// number of bytes skipped.
+fn skip_line(reader: &mut impl BufRead) -> io::Result<u64> {
+    let mut skipped = 0;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(skipped);
+        }
+        match buf.iter().position(|&b| b == b'\n') {
+            Some(i) => {
+                reader.consume(i + 1);
+                return Ok(skipped + i as u64 + 1);
+            }
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+                skipped += len as u64;
+            }
+        }
+    }
+}
+
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    contents
+        .lines()
+        .filter(|line| line.contains(query))
+        .collect()
+}
+
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    let query = query.to_lowercase();
+    contents
+        .lines()
+        .filter(|line| line.to_lowercase().contains(&query))
+        .collect()
+}
+
+/// A match found by [`search_bytes`], as byte offsets into the contents
+/// searched.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ByteMatch {
+    pub line_number: usize,
+    /// The line the match is on, without its line ending.
+    pub line: Range<usize>,
+    pub span: Range<usize>,
+}
+
+/// Finds every occurrence of `query` in `contents`, which needn't be UTF-8.
+/// Lines end at `\n`, or `\r\n`, as [`str::lines`] has them, and matches
+/// don't overlap or cross from one line to the next. An empty query matches
+/// once at the start of each line.
+pub fn search_bytes(query: &[u8], contents: &[u8]) -> Vec<ByteMatch> {
+    let mut matches = Vec::new();
+    let mut start = 0;
+    for (line_number, line) in (1..).zip(contents.split_inclusive(|&b| b == b'\n')) {
+        let text = line.strip_suffix(b"\n").unwrap_or(line);
+        let text = text.strip_suffix(b"\r").unwrap_or(text);
+        let whole = start..start + text.len();
+        let mut at = 0;
+        while at + query.len() <= text.len() {
+            if !text[at..].starts_with(query) {
+                at += 1;
+                continue;
+            }
+            matches.push(ByteMatch {
+                line_number,
+                line: whole.clone(),
+                span: start + at..start + at + query.len(),
+            });
+            if query.is_empty() {
+                break;
+            }
+            at += query.len();
+        }
+        start += line.len();
+    }
+    matches
+}
+
+/// Searches `contents` line by line, checking `token` before each line.
+///
+/// When the token is cancelled the lines matched so far are returned.
+pub fn search_cancellable<'a>(
+    query: &str,
+    contents: &'a str,
+    ignore_case: bool,
+    token: &CancellationToken,
+) -> Vec<&'a str> {
+    let matcher = Matcher::new(query, ignore_case);
+    contents
+        .lines()
+        .take_while(|_| !token.is_cancelled())
+        .filter(|line| matcher.is_match(line))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::Regex;
+
+    /// Runs minigrep with `args` (after the program name) and returns what
+    /// it would have printed.
+    fn output_of(args: &[&str]) -> String {
+        output_in(&Disk, args)
+    }
+
+    /// Like [`output_of`], but searching the files in `fs`.
+    fn output_in(fs: &dyn FileSystem, args: &[&str]) -> String {
+        search_in(fs, args).unwrap()
+    }
+
+    /// Like [`output_in`], but returning the error if the search fails.
+    fn search_in(fs: &dyn FileSystem, args: &[&str]) -> Result<String, Box<dyn Error>> {
+        let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+        let config = Config::build(args).unwrap();
+        let token = CancellationToken::new();
+        let mut searcher = Searcher::new(&config, &token);
+        searcher.fs = fs;
+        let mut out = Vec::new();
+        searcher.search_all(&mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    /// A directory of the test's own under the system's temporary one, for
+    /// the tests that need a real disk. It's removed when dropped, so also
+    /// when the test fails.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = env::temp_dir().join(format!("minigrep-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl std::ops::Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn case_sensitive() {
+        let query = "duct";
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+    }
+
+    #[test]
+    fn bytes_that_arent_utf8_can_be_searched() {
+        let contents = b"ab\xffab\r\n\xfe\nxab";
+        let match_at = |line_number, line: Range<usize>, span| ByteMatch {
+            line_number,
+            line,
+            span,
+        };
+        assert_eq!(
+            search_bytes(b"ab", contents),
+            [
+                match_at(1, 0..5, 0..2),
+                match_at(1, 0..5, 3..5),
+                match_at(3, 9..12, 10..12),
+            ]
+        );
+        assert_eq!(search_bytes(b"\xff", contents), [match_at(1, 0..5, 2..3)]);
+        assert_eq!(search_bytes(b"", b"a\n").len(), 1);
+        assert!(search_bytes(b"b\r", contents).is_empty());
+    }
+
+    #[test]
+    fn lines_split_across_chunks_are_put_back_together() {
+        let config =
+            Config::build(["minigrep", "fish", "-"].into_iter().map(String::from)).unwrap();
+        let mut searcher = ChunkSearcher::new(&config.search).unwrap();
+        let mut found = Vec::new();
+        let mut record =
+            |line_number: usize, line: &str| found.push((line_number, line.to_string()));
+        for chunk in ["one fi", "sh\r\ntwo", " birds\n", "", "red\nblue f", "ish"] {
+            searcher.feed(chunk.as_bytes(), &mut record);
+        }
+        searcher.finish(&mut record);
+        assert_eq!(
+            found,
+            [(1, "one fish".to_string()), (4, "blue fish".to_string())]
+        );
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let query = "rUsT";
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        assert_eq!(
+            vec!["Rust:", "Trust me."],
+            search_case_insensitive(query, contents)
+        );
+    }
+
+    #[test]
+    fn literal_matches_by_byte_range() {
+        let literal = Literal::new("RUST", true);
+        assert!(literal.is_match("Trust me."));
+        assert_eq!(literal.find("İ rust"), Some(3..7));
+        assert_eq!(literal.find_all("rust, Rust", false), [0..4, 6..10]);
+        assert_eq!(
+            Literal::new("aa", false).find_all("aaa", true),
+            [0..2, 1..3]
+        );
+        assert_eq!(Literal::new("", false).find("x"), Some(0..0));
+    }
+
+    #[test]
+    fn cancelled_search_stops_early() {
+        let token = CancellationToken::new();
+        assert_eq!(
+            vec!["Rust:", "Trust me."],
+            search_cancellable("rust", "Rust:\nTrust me.", true, &token)
+        );
+
+        token.cancel();
+        assert!(search_cancellable("rust", "Rust:\nTrust me.", true, &token).is_empty());
+        assert!(token.cut_short());
+
+        // A timeout that fires once the search is over cut nothing short.
+        let config = Config::build(
+            ["minigrep", "frog", "poem.txt"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+        let token = CancellationToken::new();
+        assert!(any_match(&config, &token).unwrap());
+        token.cancel();
+        assert!(!token.cut_short());
+        assert!(!any_match(&config, &token).unwrap());
+        assert!(token.cut_short());
+    }
+
+    #[test]
+    fn reader_search_with_bounded_lines() {
+        let contents = "Rust:\r\nsafe, fast, productive.\nTrust me.";
+        let token = CancellationToken::new();
+
+        let matcher = Matcher::new("rust", true);
+
+        let mut found = Vec::new();
+        let limits = Limits {
+            max_line: 64,
+            lines: 1..=usize::MAX,
+            bytes: u64::MAX,
+        };
+        search_reader(&matcher, contents.as_bytes(), &limits, &token, |n, line| {
+            found.push((n, line.to_string()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            found,
+            vec![(1, "Rust:".to_string()), (3, "Trust me.".to_string())]
+        );
+
+        let mut found = Vec::new();
+        let limits = Limits {
+            lines: 2..=3,
+            ..limits
+        };
+        search_reader(&matcher, contents.as_bytes(), &limits, &token, |n, _| {
+            found.push(n);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(found, vec![3]);
+
+        let mut found = Vec::new();
+        let limits = Limits {
+            lines: 1..=usize::MAX,
+            bytes: 8,
+            ..limits
+        };
+        search_reader(&matcher, contents.as_bytes(), &limits, &token, |n, _| {
+            found.push(n);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(found, vec![1]);
+
+        let limits = Limits {
+            max_line: 8,
+            bytes: u64::MAX,
+            ..limits
+        };
+        let result = search_reader(
+            &matcher,
+            contents.as_bytes(),
+            &limits,
+            &token,
+            |_, _| Ok(()),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn skip_partial_line() {
+        let mut reader = "ust:\nTrust me.".as_bytes();
+        assert_eq!(skip_line(&mut reader).unwrap(), 5);
+        assert_eq!(reader, b"Trust me.");
+        assert_eq!(skip_line(&mut reader).unwrap(), 9);
+    }
+
+    #[test]
+    fn buffer_size_suits_the_file_unless_given() {
+        assert_eq!(buffer_size_for(None), DEFAULT_BUF_SIZE);
+        assert_eq!(buffer_size_for(Some(100)), DEFAULT_BUF_SIZE);
+        assert_eq!(buffer_size_for(Some(16 * 1024 * 1024)), 256 * 1024);
+        assert_eq!(buffer_size_for(Some(u64::MAX)), MAX_BUF_SIZE);
+
+        let whole = output_of(&["-n", "to", "poem.txt"]);
+        assert_eq!(
+            output_of(&["--buffer-size", "3", "-n", "to", "poem.txt"]),
+            whole
+        );
+        assert_eq!(
+            output_of(&[
+                "--buffer-size",
+                "3",
+                "--max-memory",
+                "1K",
+                "-n",
+                "to",
+                "poem.txt"
+            ]),
+            whole
+        );
+        assert!(
+            Config::build(
+                [
+                    "minigrep",
+                    "--buffer-size",
+                    "1K",
+                    "--max-memory",
+                    "1K",
+                    "to",
+                    "poem.txt"
+                ]
+                .into_iter()
+                .map(String::from)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn escapes_in_queries() {
+        assert_eq!(unescape(r"a\tb\x41\\n\d").unwrap(), "a\tbA\\n\\d");
+        assert_eq!(unescape(r"\r\n\0\").unwrap(), "\r\n\0\\");
+        assert!(unescape(r"\x8").is_err());
+        assert!(unescape(r"\xFF").is_err());
+
+        let mut fs = MemoryFs::new();
+        fs.insert("ages.tsv", "name\tage\nfrog\t3\nbig\\tfrog\n");
+        assert_eq!(
+            output_in(&fs, &["--escapes", r"g\t", "ages.tsv"]),
+            "frog\t3\n"
+        );
+        assert_eq!(output_in(&fs, &[r"g\t", "ages.tsv"]), "big\\tfrog\n");
+    }
+
+    #[test]
+    fn sizes_with_suffixes() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("64K"), Some(64 * 1024));
+        assert_eq!(parse_size("10m"), Some(10 * 1024 * 1024));
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("lots"), None);
+    }
+
+    #[test]
+    fn times_from_ages_and_dates() {
+        assert_eq!(
+            parse_time("2024-01-01"),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_067_200))
+        );
+        assert_eq!(
+            parse_time("2000-03-01"),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(951_868_800))
+        );
+
+        let two_days_ago = parse_time("2d").unwrap();
+        let age = SystemTime::now().duration_since(two_days_ago).unwrap();
+        assert!(age >= Duration::from_secs(2 * 24 * 60 * 60));
+
+        assert_eq!(parse_time("2024-13-01"), None);
+        assert_eq!(parse_time("2024-02-31"), None);
+        assert_eq!(parse_time("2023-02-29"), None);
+        assert_eq!(parse_time("2024-04-31"), None);
+        assert!(parse_time("2024-02-29").is_some());
+        assert!(parse_time("2000-02-29").is_some());
+        assert_eq!(parse_time("2100-02-29"), None);
+        assert_eq!(parse_time("2y"), None);
+        assert_eq!(parse_time("yesterday"), None);
+    }
+
+    #[test]
+    fn config_error_too_few_arguments() {
+        let result = Config::build([String::from("foo")].into_iter());
+        match result {
+            Ok(_) => panic!("This should fail"),
+            Err(e) => assert!(e.to_string().to_lowercase().contains("query")),
+        }
+    }
+
+    #[test]
+    fn config_three_arguments() {
+        let result = Config::build(
+            [
+                String::from("minigrep"),
+                String::from("to"),
+                String::from("poem.txt"),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        assert_eq!(result.search.queries, ["to"]);
+        assert_eq!(result.input.file_path, Path::new("poem.txt"));
+    }
+
+    #[test]
+    fn config_can_set_case_insensitive() {
+        let result = Config::build(
+            [
+                String::from("minigrep"),
+                String::from("-i"),
+                String::from("to"),
+                String::from("poem.txt"),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        assert!(result.search.ignore_case)
+    }
+
+    #[test]
+    fn environment_values_can_be_false() {
+        for value in ["1", "true", "YES", "on", "anything"] {
+            assert!(is_truthy(value), "{value}");
+        }
+        for value in ["", "0", "false", "No", " off "] {
+            assert!(!is_truthy(value), "{value}");
+        }
+    }
+
+    #[test]
+    fn messages_are_translated() {
+        assert_eq!(Lang::parse("de_DE.UTF-8"), Some(Lang::German));
+        assert_eq!(Lang::parse("C"), Some(Lang::English));
+        assert_eq!(Lang::parse("pt_BR"), None);
+        let args = ["minigrep", "--lang=fr", "to"].map(OsString::from);
+        assert_eq!(Lang::from_args(&args), Lang::French);
+
+        let lang = |args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            Config::build(args).map(|config| config.lang)
+        };
+        assert_eq!(
+            lang(&["--lang", "es", "to", "poem.txt"]).unwrap(),
+            Lang::Spanish
+        );
+        assert_eq!(
+            lang(&["--no-env", "to", "poem.txt"]).unwrap(),
+            Lang::English
+        );
+        assert!(lang(&["--lang", "xx", "to", "poem.txt"]).is_err());
+
+        assert_eq!(
+            Lang::German.translate("Didn't get a query string"),
+            "Kein Suchbegriff angegeben"
+        );
+        assert_eq!(
+            Lang::German.translate("not in the catalog"),
+            "not in the catalog"
+        );
+        assert_eq!(
+            Lang::Spanish.format("no matches found for \"{}\"", "frog"),
+            "no hay coincidencias para \"frog\""
+        );
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        assert_eq!(Lang::French.describe(&missing), "fichier introuvable");
+        assert_eq!(Lang::English.describe(&missing), missing.to_string());
+
+        // Flags and placeholders are the same in every language.
+        let words = |message: &'static str| {
+            let mut flags: Vec<&str> = message
+                .split([' ', ',', '(', ')'])
+                .filter(|word| word.starts_with('-'))
+                .collect();
+            flags.sort();
+            (flags, message.matches("{}").count())
+        };
+        for row in i18n::CATALOG {
+            for translation in &row[1..] {
+                assert_eq!(words(translation), words(row[0]), "{translation}");
+            }
+        }
+    }
+
+    #[test]
+    fn errors_can_be_reported_as_json() {
+        let args = ["minigrep", "--error-format", "json", "to"].map(OsString::from);
+        assert_eq!(ErrorFormat::from_args(&args), ErrorFormat::Json);
+        assert_eq!(ErrorFormat::from_args(&args[..2]), ErrorFormat::Text);
+
+        let build = |args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            Config::build(args)
+        };
+        let e = build(&["--engine", "regex", "fr(og", "poem.txt"])
+            .err()
+            .unwrap();
+        assert_eq!(
+            error_json(e.as_ref(), None, "bad-arguments"),
+            r#"{"code":"bad-pattern","message":"unclosed group in the regex","position":2}"#
+        );
+        let e = build(&["to"]).err().unwrap();
+        assert_eq!(
+            error_json(e.as_ref(), None, "bad-arguments"),
+            r#"{"code":"bad-arguments","message":"Didn't get a file path"}"#
+        );
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        assert!(
+            error_json(&missing, Some("a \"b\".txt"), "error")
+                .starts_with(r#"{"code":"not-found","path":"a \"b\".txt","message":"#)
+        );
+        assert!(build(&["--error-format", "xml", "to", "poem.txt"]).is_err());
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        let build = |args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            Config::build(args)
+        };
+        assert!(build(&["--frog", "to", "poem.txt"]).is_err());
+        assert!(build(&["-Z", "to", "poem.txt"]).is_err());
+        let config = build(&["-n", "--", "-12", "poem.txt"]).unwrap();
+        assert_eq!(config.search.queries, ["-12"]);
+        assert!(config.output.line_number);
+        assert_eq!(
+            output_of(&["-A1", "-M20", "dreary", "poem.txt"]),
+            output_of(&["-A", "1", "-M", "20", "dreary", "poem.txt"])
+        );
+        let config = build(&["to", "-"]).unwrap();
+        assert_eq!(config.input.file_path, Path::new("-"));
+    }
+
+    #[test]
+    fn last_case_flag_wins() {
+        let ignore_case = |flags: &[&str]| {
+            let args = ["minigrep"].iter().chain(flags).chain(&["to", "poem.txt"]);
+            Config::build(args.map(|arg| arg.to_string()))
+                .unwrap()
+                .search
+                .ignore_case
+        };
+        assert!(!ignore_case(&["-i", "-s"]));
+        assert!(ignore_case(&["--case-sensitive", "-i"]));
+        assert!(!ignore_case(&["-s"]));
+    }
+
+    #[test]
+    fn config_timeout() {
+        let result = Config::build(
+            ["minigrep", "--timeout", "1.5", "to", "poem.txt"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+
+        assert_eq!(result.timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(result.search.queries, ["to"]);
+
+        for secs in ["soon", "-1", "1e20", "inf", "NaN"] {
+            let result = Config::build(
+                ["minigrep", "--timeout", secs, "to", "poem.txt"]
+                    .into_iter()
+                    .map(String::from),
+            );
+            assert_eq!(
+                result.err().unwrap().to_string(),
+                "--timeout needs a number of seconds"
+            );
+        }
+    }
+
+    #[test]
+    fn recursive_search_skips_large_files() {
+        let mut fs = MemoryFs::new();
+        fs.insert("root/small.txt", "needle\n");
+        fs.insert("root/nested/big.txt", "needle\n".repeat(100));
+
+        let config = Config::build(
+            ["minigrep", "-r", "--max-filesize", "64", "needle", "root"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+        let root = Path::new("root");
+        let files: Vec<PathBuf> = walk::walk(&fs, root, &Ignores::all(), false, |_, metadata| {
+            config.skip_reason(metadata).is_none()
+        });
+
+        assert_eq!(files, vec![root.join("small.txt")]);
+    }
+
+    #[test]
+    fn sort_files_by_size() {
+        let mut fs = MemoryFs::new();
+        let (a, b, c) = (
+            PathBuf::from("root/a.txt"),
+            PathBuf::from("root/b.txt"),
+            PathBuf::from("root/c.txt"),
+        );
+        fs.insert(&a, "medium");
+        fs.insert(&b, "the largest");
+        fs.insert(&c, "tiny");
+
+        let mut files = vec![b.clone(), c.clone(), a.clone()];
+        walk::sort(&fs, &mut files, SortKey::Path, false);
+        assert_eq!(files, vec![a.clone(), b.clone(), c.clone()]);
+
+        walk::sort(&fs, &mut files, SortKey::Size, false);
+        assert_eq!(files, vec![c.clone(), a.clone(), b.clone()]);
+
+        walk::sort(&fs, &mut files, SortKey::Size, true);
+        assert_eq!(files, vec![b.clone(), a.clone(), c.clone()]);
+
+        // A small file changed long ago comes after one changed just now,
+        // but a large file comes after both however new it is.
+        let (old, large) = (
+            PathBuf::from("root/old.txt"),
+            PathBuf::from("root/large.txt"),
+        );
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        fs.insert(&old, "tiny");
+        fs.set_modified(&old, at(60));
+        fs.set_modified(&c, at(1_000));
+        fs.insert(&large, "x".repeat(100_000));
+        fs.set_modified(&large, at(2_000));
+        let mut files = vec![large.clone(), old.clone(), c.clone()];
+        walk::sort(&fs, &mut files, SortKey::Priority, false);
+        assert_eq!(files, vec![c, old, large]);
+    }
+
+    #[test]
+    fn config_line_range() {
+        let result = Config::build(
+            [
+                "minigrep",
+                "-n",
+                "--from-line",
+                "10",
+                "--to-line",
+                "20",
+                "to",
+                "poem.txt",
+            ]
+            .into_iter()
+            .map(String::from),
+        )
+        .unwrap();
+        assert!(result.output.line_number);
+        assert_eq!(
+            (result.input.from_line, result.input.to_line),
+            (Some(10), Some(20))
+        );
+
+        let result = Config::build(
+            [
+                "minigrep",
+                "--from-line",
+                "20",
+                "--to-line",
+                "10",
+                "to",
+                "poem.txt",
+            ]
+            .into_iter()
+            .map(String::from),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn find_case_insensitive_spans() {
+        let matcher = Matcher::new("rust", true);
+        assert_eq!(matcher.find("Trust me."), Some(1..5));
+        assert_eq!(matcher.find("İRUST"), Some(2..6));
+        assert_eq!(matcher.find("Pick three."), None);
+    }
+
+    #[test]
+    fn truncate_long_lines_around_match() {
+        let truncate = |line, span, max_columns| {
+            output::reshape(line, output::window(line, span, max_columns), None).0
+        };
+        let line = "aaaaaaaaaaneedleaaaaaaaaaa";
+        assert_eq!(truncate(line, 10..16, 30), line);
+        assert_eq!(truncate(line, 10..16, 10), "[...]aaneedleaa[...]");
+        assert_eq!(truncate(line, 0..1, 4), "aaaa[...]");
+        assert_eq!(truncate(line, 25..26, 4), "[...]aaaa");
+    }
+
+    #[test]
+    fn expand_tabs_to_tab_stops() {
+        assert_eq!(output::expand_tabs("a\tbc\td", 4), "a   bc  d");
+        assert_eq!(output::expand_tabs("\tabcd\t", 4), "    abcd    ");
+        assert_eq!(output::expand_tabs("no tabs", 8), "no tabs");
+    }
+
+    #[test]
+    fn wrap_with_hanging_indent() {
+        let line = "abcdefghijklmnopqrstuvwxyz0123456789";
+        assert_eq!(
+            output::wrap(line, 4, 24),
+            "abcdefghijklmnopqrst\n    uvwxyz0123456789"
+        );
+        assert_eq!(output::wrap(line, 4, 60), line);
+        assert_eq!(output::wrap(line, 30, 40), line);
+    }
+
+    #[test]
+    fn hyperlink_template() {
+        assert_eq!(
+            output::hyperlink_url(
+                "vscode://file/{path}:{line}:{column}",
+                "/src/my lib.rs",
+                12,
+                4
+            ),
+            "vscode://file//src/my%20lib.rs:12:4"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "color")]
+    fn config_color_choice() {
+        let result = Config::build(
+            ["minigrep", "--color=always", "to", "poem.txt"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+        assert_eq!(result.output.color, ColorChoice::Always);
+        assert_eq!(result.search.queries, ["to"]);
+
+        let result = Config::build(["minigrep", "to", "poem.txt"].into_iter().map(String::from));
+        assert_eq!(result.unwrap().output.color, ColorChoice::Auto);
+    }
+
+    #[test]
+    fn highlight_every_match() {
+        let matcher = Matcher::new("to", true);
+        let highlighted = output::highlight("To tell, too", &matcher, "\x1b[1;31m");
+        assert_eq!(
+            highlighted,
+            "\x1b[1;31mTo\x1b[0m tell, \x1b[1;31mto\x1b[0mo"
+        );
+        assert_eq!(output::wrap(&highlighted, 0, 20), highlighted);
+    }
+
+    #[test]
+    #[cfg(feature = "color")]
+    fn each_query_has_its_own_color() {
+        assert_eq!(
+            output_of(&["--color", "always", "frog", "public", "poem.txt"]),
+            "How \x1b[1;33mpublic\x1b[0m, like a \x1b[1;31mfrog\x1b[0m\n"
+        );
+        assert_eq!(
+            output_of(&["--color", "always", "frog", "poem.txt"]),
+            "How public, like a \x1b[1;31mfrog\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "color")]
+    fn matches_are_highlighted_where_the_line_was_reshaped() {
+        let mut fs = MemoryFs::new();
+        fs.insert("lines.txt", "a\tb\nxxxxxxxxxx needle yyyyyyyyyy frog\n");
+        let color = |args: &[&str]| {
+            output_in(
+                &fs,
+                &[&["--color", "always"], args, &["lines.txt"]].concat(),
+            )
+        };
+        let tabs = color(&["--engine", "regex", "--tabs", "4", r"a\tb"]);
+        let cut = color(&["-M", "3", "needle"]);
+        let both = color(&["-M", "12", "needle", "frog"]);
+
+        // A match with a tab in it is highlighted once the tab is spaces...
+        assert_eq!(tabs, "\x1b[1;31ma   b\x1b[0m\n");
+        // ...and one cut short by --max-columns as far as it's shown.
+        assert_eq!(cut, "[...]\x1b[1;31mnee\x1b[0m[...]\n");
+        assert_eq!(both, "[...]xx \x1b[1;31mneedle\x1b[0m yy[...]\n");
+    }
+
+    #[test]
+    #[cfg(feature = "color")]
+    fn forced_color_is_written_as_escapes() {
+        // Nothing here is a console, so `auto` stays plain and `always`
+        // writes the escapes through untouched.
+        assert_eq!(
+            output_of(&["--color", "always", "-n", "frog", "poem.txt"]),
+            "\x1b[32m7\x1b[0m:How public, like a \x1b[1;31mfrog\x1b[0m\n"
+        );
+        assert_eq!(
+            output_of(&["--color", "auto", "-n", "frog", "poem.txt"]),
+            "7:How public, like a frog\n"
+        );
+    }
+
+    #[test]
+    fn theme_from_color_specs() {
+        let mut theme = Theme::default();
+        theme.apply("match:fg:yellow").unwrap();
+        theme.apply("match:bg:0,128,255").unwrap();
+        theme.apply("match:style:nobold").unwrap();
+        theme.apply("path:none").unwrap();
+        theme.apply("line:fg:208").unwrap();
+
+        let mut expected = Theme::default();
+        for spec in ["path:none", "line:none", "line:fg:208"] {
+            expected.apply(spec).unwrap();
+        }
+        for spec in ["match:none", "match:fg:yellow", "match:bg:0,128,255"] {
+            expected.apply(spec).unwrap();
+        }
+        assert_eq!(theme, expected);
+
+        assert!(theme.apply("match:fg:mauve").is_err());
+        assert!(theme.apply("match:fg:1,2").is_err());
+        assert!(theme.apply("heading:fg:red").is_err());
+        assert!(theme.apply("match:style:blink").is_err());
+    }
+
+    #[test]
+    fn skip_reasons_name_the_rule() {
+        let config = Config::build(
+            [
+                "minigrep",
+                "--max-filesize",
+                "4",
+                "--newer-than",
+                "1d",
+                "to",
+                "poem.txt",
+            ]
+            .into_iter()
+            .map(String::from),
+        )
+        .unwrap();
+        let metadata = Metadata::from(fs::metadata("poem.txt").unwrap());
+        assert_eq!(
+            config.skip_reason(&metadata),
+            Some("larger than --max-filesize")
+        );
+
+        let config = Config {
+            input: InputOptions {
+                max_filesize: None,
+                ..config.input
+            },
+            ..config
+        };
+        let touched = metadata.modified().unwrap();
+        assert_eq!(
+            config.skip_reason(&metadata).is_some(),
+            touched < config.input.newer_than.unwrap()
+        );
+    }
+
+    #[test]
+    fn explain_skips_names_each_rule() {
+        let mut fs = MemoryFs::new();
+        fs.insert("root/small.txt", "needle\n");
+        fs.insert("root/big.txt", "needle\n".repeat(100));
+        fs.insert("root/.gitignore", "# built\n*.log\n");
+        fs.insert("root/debug.log", "needle\n");
+
+        let config = Config::build(
+            [
+                "minigrep",
+                "-r",
+                "--no-ignore-global",
+                "--max-filesize",
+                "64",
+                "needle",
+                "root",
+            ]
+            .into_iter()
+            .map(String::from),
+        )
+        .unwrap();
+        let root = Path::new("root");
+        let mut out = Vec::new();
+        explain_skips(&config, &fs, root, &mut out).unwrap();
+
+        let mut out: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        out.sort();
+        assert_eq!(
+            out,
+            vec![
+                format!("{}: searched", root.join(".gitignore").display()),
+                format!(
+                    "{}: skipped, larger than --max-filesize",
+                    root.join("big.txt").display()
+                ),
+                format!(
+                    "{}: skipped, ignored by {}:2",
+                    root.join("debug.log").display(),
+                    root.join(".gitignore").display()
+                ),
+                format!("{}: searched", root.join("small.txt").display()),
+            ]
+        );
+    }
+
+    #[test]
+    fn config_stats_levels() {
+        let build = |flag: &str| {
+            Config::build(
+                ["minigrep", flag, "to", "poem.txt"]
+                    .into_iter()
+                    .map(String::from),
+            )
+            .unwrap()
+        };
+        assert_eq!(build("--stats").output.stats, Some(StatsLevel::Basic));
+        assert_eq!(build("--stats=full").output.stats, Some(StatsLevel::Full));
+        assert_eq!(build("--stats").search.queries, ["to"]);
+        assert_eq!(build("--stats").output.stats_format, StatsFormat::Text);
+    }
+
+    #[test]
+    fn stats_can_be_printed_as_json() {
+        let output = output_of(&["--stats-format", "json", "to", "poem.txt"]);
+        let (lines, json) = output.trim_end().rsplit_once('\n').unwrap();
+        assert_eq!(lines, "Are you nobody, too?\nHow dreary to be somebody!");
+        assert!(json.starts_with(
+            r#"{"matched_lines":2,"files_matched":1,"files_searched":1,"elapsed_seconds":"#
+        ));
+        assert!(json.ends_with('}'));
+
+        let full = output_of(&[
+            "--stats=full",
+            "--stats-format",
+            "json",
+            "zebra",
+            "poem.txt",
+        ]);
+        assert!(full.contains(r#""bytes_read":221,"#), "{full}");
+        assert!(full.contains(r#""threads":1,"#), "{full}");
+        assert!(full.ends_with(",\"skipped\":{}}\n"), "{full}");
+    }
+
+    #[test]
+    fn count_lines_and_matches() {
+        assert_eq!(output_of(&["-c", "nobody", "poem.txt"]), "2\n");
+        assert_eq!(output_of(&["-c", "zebra", "poem.txt"]), "0\n");
+        assert_eq!(output_of(&["-c", "-i", "o", "poem.txt"]), "8\n");
+        assert_eq!(
+            output_of(&["--count-matches", "-i", "o", "poem.txt"]),
+            "24\n"
+        );
+    }
+
+    #[test]
+    fn find_every_occurrence() {
+        let matcher = Matcher::new("o", true);
+        assert_eq!(matcher.find_all("Oh, no"), vec![0..1, 5..6]);
+        assert_eq!(
+            Matcher::new("aa", false).find_all("aaaaa"),
+            vec![0..2, 2..4]
+        );
+        assert!(Matcher::new("", false).find_all("anything").is_empty());
+    }
+
+    #[test]
+    fn count_per_file() {
+        assert_eq!(
+            output_of(&["--count-per-file", "nobody", "poem.txt"]),
+            "poem.txt:2\n"
+        );
+        assert_eq!(output_of(&["--count-per-file", "zebra", "poem.txt"]), "");
+        assert_eq!(
+            output_of(&["--count-per-file", "--include-zero", "zebra", "poem.txt"]),
+            "poem.txt:0\n"
+        );
+    }
+
+    #[test]
+    fn count_files() {
+        let mut fs = MemoryFs::new();
+        fs.insert("dir/a.txt", "frog\nfrog\n");
+        fs.insert("dir/b.txt", "toad\n");
+        fs.insert("dir/c.txt", "a frog\n");
+
+        assert_eq!(
+            output_in(&fs, &["--count-files", "-r", "frog", "dir"]),
+            "2\n"
+        );
+        assert_eq!(
+            output_in(&fs, &["--count-files", "-r", "newt", "dir"]),
+            "0\n"
+        );
+        assert_eq!(output_of(&["--count-files", "frog", "poem.txt"]), "1\n");
+    }
+
+    #[test]
+    fn unique_lines() {
+        let mut fs = MemoryFs::new();
+        fs.insert(
+            "app.log",
+            "error: disk\nok\nerror: net\nerror: disk\nerror: disk\n",
+        );
+
+        assert_eq!(
+            output_in(&fs, &["--unique", "error", "app.log"]),
+            "error: disk\nerror: net\n"
+        );
+        assert_eq!(
+            output_in(&fs, &["--unique-count", "error", "app.log"]),
+            "      3 error: disk\n      1 error: net\n"
+        );
+    }
+
+    #[test]
+    fn only_matching_and_top() {
+        assert_eq!(
+            output_of(&["-o", "-n", "-i", "are", "poem.txt"]),
+            "1:are\n2:Are\n"
+        );
+        assert_eq!(
+            output_of(&["-o", "--top", "2", "-i", "i", "poem.txt"]),
+            "      7 i\n      1 I\n"
+        );
+        assert!(
+            Config::build(
+                ["minigrep", "--top", "2", "o", "poem.txt"]
+                    .into_iter()
+                    .map(String::from)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn a_terminal_gets_headings_and_line_numbers() {
+        let parse = |args: &[&str], terminal| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            let config = Config::parse_with(args, &mut || None, terminal).unwrap();
+            (config.output.heading, config.output.line_number)
+        };
+        assert_eq!(parse(&["to", "poem.txt"], true), (true, true));
+        let args = ["minigrep", "to", "poem.txt"].into_iter();
+        let config = Config::parse_with(args, &mut || None, true).unwrap();
+        assert_eq!(config.input.sort, Some(SortKey::Priority));
+        assert_eq!(parse(&["to", "poem.txt"], false), (false, false));
+        assert_eq!(
+            parse(&["-N", "--no-heading", "to", "poem.txt"], true),
+            (false, false)
+        );
+        assert_eq!(parse(&["-c", "to", "poem.txt"], true), (false, true));
+        assert!(
+            Config::build(
+                ["minigrep", "--heading", "-c", "to", "poem.txt"]
+                    .into_iter()
+                    .map(String::from)
+            )
+            .is_err()
+        );
+
+        let mut fs = MemoryFs::new();
+        fs.insert("dir/a.txt", "a frog\nno\n");
+        fs.insert("dir/b/c.txt", "no\nfrog c\n");
+        let output = output_in(
+            &fs,
+            &["-r", "--heading", "-n", "--sort", "path", "frog", "dir"],
+        );
+        let dir = Path::new("dir");
+        let a = dir.join("a.txt").display().to_string();
+        let c = dir.join("b").join("c.txt").display().to_string();
+        assert_eq!(output, format!("{a}\n1:a frog\n\n{c}\n2:frog c\n"));
+    }
+
+    #[test]
+    fn explain_says_how_each_match_was_found() {
+        assert_eq!(
+            output_of(&["--explain", "dreary", "poem.txt"]),
+            "How dreary to be somebody!\n  \
+             explain: bytes 4..10 \"dreary\" matched literal \"dreary\", case-sensitive\n"
+        );
+        assert_eq!(
+            output_of(&[
+                "--explain",
+                "--engine",
+                "auto",
+                "-i",
+                "FROG",
+                "p.b",
+                "poem.txt"
+            ]),
+            "How public, like a frog\n  \
+             explain: bytes 4..7 \"pub\" matched query 2 regex \"p.b\", ignoring case\n  \
+             explain: bytes 19..23 \"frog\" matched query 1 literal \"FROG\", ignoring case\n"
+        );
+        assert!(
+            Config::build(
+                ["minigrep", "--explain", "-o", "to", "poem.txt"]
+                    .into_iter()
+                    .map(String::from)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn progress_never_reaches_the_results() {
+        let mut fs = MemoryFs::new();
+        fs.insert("root/a.txt", "needle\n");
+        fs.insert("root/sub/b.txt", "hay\nneedle\n");
+
+        let with = output_in(&fs, &["-r", "-c", "needle", "root"]);
+        let without = output_in(&fs, &["-r", "-c", "--no-progress", "needle", "root"]);
+
+        assert_eq!(with, without);
+        assert_eq!(with.lines().count(), 2);
+        assert!(!with.contains('\x1b'));
+    }
+
+    #[test]
+    fn a_quit_pager_ends_the_search_quietly() {
+        // Away from a terminal there's no pager, just the usual output.
+        assert_eq!(
+            output_of(&["--pager", "-n", "frog", "poem.txt"]),
+            output_of(&["-n", "frog", "poem.txt"])
+        );
+
+        struct Quit;
+        impl Write for Quit {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let config = Config::build(
+            ["minigrep", "--pager", "frog", "poem.txt"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+        let token = CancellationToken::new();
+        let e = Searcher::new(&config, &token)
+            .search_all(&mut Quit)
+            .unwrap_err();
+        assert!(is_broken_pipe(e.as_ref()));
+    }
+
+    #[test]
+    fn porcelain_output_is_tab_separated_and_escaped() {
+        let frog = "match\tpoem.txt\t7\t20\tHow public, like a frog\n\
+                    context\tpoem.txt\t8\t\tTo tell your name the livelong day\n";
+        assert_eq!(
+            output_of(&["--porcelain", "-A", "1", "-n", "frog", "poem.txt"]),
+            frog
+        );
+        // Porcelain output is never colored.
+        #[cfg(feature = "color")]
+        assert_eq!(
+            output_of(&[
+                "--porcelain",
+                "-A1",
+                "--color",
+                "always",
+                "frog",
+                "poem.txt"
+            ]),
+            frog
+        );
+        assert_eq!(
+            output_of(&["--porcelain", "-o", "-i", "how", "poem.txt"]),
+            "match\tpoem.txt\t6\t1\tHow\nmatch\tpoem.txt\t7\t1\tHow\n"
+        );
+
+        let mut fs = MemoryFs::new();
+        fs.insert("odd.txt", "a\tb\\c\0d\r\n");
+        assert_eq!(
+            output_in(&fs, &["--porcelain", "b", "odd.txt"]),
+            "match\todd.txt\t1\t3\ta\\tb\\\\c\\0d\n"
+        );
+        assert!(
+            Config::build(
+                ["minigrep", "--porcelain", "-c", "to", "poem.txt"]
+                    .into_iter()
+                    .map(String::from)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn every_occurrence_gets_its_own_column() {
+        assert_eq!(
+            output_of(&["--vimgrep", "he", "poem.txt"]),
+            "poem.txt:3:2:Then there's a pair of us - don't tell!\n\
+             poem.txt:3:7:Then there's a pair of us - don't tell!\n\
+             poem.txt:4:2:They'd banish us, you know.\n\
+             poem.txt:8:20:To tell your name the livelong day\n"
+        );
+        assert_eq!(
+            output_of(&["--column", "-o", "-n", "he", "poem.txt"]),
+            "3:2:he\n3:7:he\n4:2:he\n8:20:he\n"
+        );
+        assert_eq!(
+            output_of(&["--column", "-n", "banish", "poem.txt"]),
+            "4:8:They'd banish us, you know.\n"
+        );
+        // --vimgrep numbers lines, which an offset would make wrong.
+        assert!(
+            Config::build(
+                [
+                    "minigrep",
+                    "--vimgrep",
+                    "--start-offset",
+                    "10",
+                    "he",
+                    "poem.txt"
+                ]
+                .into_iter()
+                .map(String::from)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn overlapping_matches_can_be_counted() {
+        let mut fs = MemoryFs::new();
+        fs.insert("dna.txt", "GATATATC\naaa\n");
+        let output = |args: &[&str]| output_in(&fs, &[args, &["dna.txt"]].concat());
+
+        let plain = output(&["--count-matches", "ATA"]);
+        let counted = output(&["--count-matches", "--overlapping", "ATA"]);
+        let folded = output(&["-o", "--overlapping", "-i", "AA"]);
+        let regex = output(&[
+            "-o",
+            "--column",
+            "--overlapping",
+            "--engine",
+            "regex",
+            "T.T",
+        ]);
+
+        assert_eq!(plain, "1\n");
+        assert_eq!(counted, "2\n");
+        assert_eq!(folded, "aa\naa\n");
+        assert_eq!(regex, "3:TAT\n5:TAT\n");
+    }
+
+    #[test]
+    fn histogram_bars_scale_to_the_largest() {
+        assert_eq!(
+            output::histogram(&[("src", 40), ("docs", 10), ("misc", 1)]),
+            concat!(
+                "src  40 ########################################\n",
+                "docs 10 ##########\n",
+                "misc  1 #\n",
+            )
+        );
+    }
+
+    #[test]
+    fn tail_follows_appends_truncation_and_rotation() {
+        let dir = TempDir::new("follow");
+        let path = dir.join("app.log");
+        let rotated = dir.join("app.log.1");
+        let append = |path: &Path, text: &str| {
+            let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        };
+        let next = |tail: &mut Tail| {
+            let line = tail.next_line().unwrap();
+            line.map(|(n, line)| (n, line.to_string()))
+        };
+
+        fs::write(&path, "one\ntwo\n").unwrap();
+        let mut tail = Tail::new(&path, 8, 2).unwrap();
+        assert_eq!(next(&mut tail), None);
+        append(&path, "thr");
+        assert_eq!(next(&mut tail), None);
+        append(&path, "ee\r\n");
+        assert_eq!(next(&mut tail), Some((3, "three".to_string())));
+
+        fs::write(&path, "new\n").unwrap();
+        assert_eq!(next(&mut tail), Some((1, "new".to_string())));
+
+        // Lines written to the old file after it's moved are still read.
+        fs::rename(&path, &rotated).unwrap();
+        append(&rotated, "old\n");
+        assert_eq!(next(&mut tail), Some((2, "old".to_string())));
+        fs::write(&path, "rotated\n").unwrap();
+        let after_rotation = next(&mut tail);
+        if cfg!(unix) {
+            assert_eq!(after_rotation, Some((1, "rotated".to_string())));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json_round_trip() {
+        use json::Value;
+
+        let value = json::parse(r#" {"a": [1, -2.5e1, true, null], "bé😀": "x\n"} "#);
+        assert_eq!(
+            value,
+            Ok(Value::Object(vec![
+                (
+                    "a".to_string(),
+                    Value::Array(vec![
+                        Value::Number(1.0),
+                        Value::Number(-25.0),
+                        Value::Bool(true),
+                        Value::Null,
+                    ])
+                ),
+                ("bé😀".to_string(), Value::String("x\n".to_string())),
+            ]))
+        );
+        assert!(json::parse(r#"{"a": }"#).is_err());
+        assert!(json::parse("[1] 2").is_err());
+        assert!(json::parse(&format!("{}{}", "[".repeat(512), "]".repeat(512))).is_ok());
+        assert_eq!(json::parse(&"[".repeat(100_000)), Err("nesting too deep"));
+        assert_eq!(json::string("a\t\"\u{1}\\"), r#""a\t\"\u0001\\""#);
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn serve_answers_a_request() {
+        let answer = |request: &str| {
+            let mut out = Vec::new();
+            serve::answer(request, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            answer(r#"{"query": "-x", "path": "poem.txt", "args": ["-n"]}"#),
+            "{\"done\":true}\n"
+        );
+        assert_eq!(
+            answer(r#"{"query": "nobody", "path": "poem.txt", "args": ["-n"]}"#),
+            concat!(
+                "{\"line\":\"1:I'm nobody! Who are you?\"}\n",
+                "{\"line\":\"2:Are you nobody, too?\"}\n",
+                "{\"done\":true}\n",
+            )
+        );
+        assert_eq!(
+            answer(r#"{"query": "x"}"#),
+            "{\"error\":\"request needs a \\\"path\\\" string\"}\n"
+        );
+        assert!(answer(r#"{"query": "x", "path": "missing.txt"}"#).starts_with("{\"error\":"));
+        assert!(
+            answer(r#"{"query": "(", "path": "poem.txt", "args": ["--engine", "regex"]}"#)
+                .starts_with("{\"error\":\"unclosed group in the regex")
+        );
+        // Values, attached or not, and read-only subcommands are fine.
+        assert_eq!(
+            answer(r#"{"query": "frog", "path": "poem.txt", "args": ["count", "--lang=en"]}"#),
+            "{\"line\":\"1\"}\n{\"done\":true}\n"
+        );
+        assert_eq!(
+            answer(r#"{"query": "frog", "path": "poem.txt", "args": ["-B1", "-n"]}"#),
+            concat!(
+                "{\"line\":\"6-How dreary to be somebody!\"}\n",
+                "{\"line\":\"7:How public, like a frog\"}\n",
+                "{\"done\":true}\n",
+            )
+        );
+        // Nothing that writes files or runs git gets through.
+        for args in [
+            r#"["replace"]"#,
+            r#"["--in-place"]"#,
+            r#"["--backup-dir", "/tmp"]"#,
+            r#"["--backup-suffix=~"]"#,
+            r#"["--git-rev", "HEAD"]"#,
+            r#"["--update-index"]"#,
+            r#"["-F"]"#,
+            r#"["extra.txt"]"#,
+        ] {
+            let request = format!(r#"{{"query": "to", "path": "poem.txt", "args": {args}}}"#);
+            assert!(answer(&request).starts_with("{\"error\":"), "{args}");
+        }
+        // The path is only ever read from here...
+        assert_eq!(
+            answer(r#"{"query": "to", "path": "https://example.com/poem.txt"}"#),
+            "{\"error\":\"a request can only search files, not URLs\"}\n"
+        );
+        // ...and is checked as it would be on the command line.
+        let recursed = answer(
+            r#"{"query": "fn answer", "path": "src", "args": ["--directories", "recurse", "-l"]}"#,
+        );
+        assert!(recursed.contains("serve.rs"), "{recursed}");
+        // Refused before anything is bound, so this returns at once.
+        assert!(serve::serve("0.0.0.0:0").is_err());
+    }
+
+    #[test]
+    fn index_rules_out_unchanged_files_without_the_query() {
+        // The index is saved beside what it covers, so this needs a disk.
+        let dir = TempDir::new("index");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.txt"), "The Quick brown fox\n").unwrap();
+        fs::write(dir.join("sub/b.txt"), "lazy dog\n").unwrap();
+        fs::write(dir.join("c.bin"), [0xff, 0xfe, 0x00]).unwrap();
+
+        let mut index = Index::default();
+        index.update(&Disk, &dir);
+        index.save(&dir).unwrap();
+        let index = Index::load(&Disk, &dir).unwrap().unwrap();
+        assert_eq!(index.len(), 3);
+        let rules_out = |query: &str, path: &str| {
+            let metadata = Metadata::from(fs::metadata(dir.join(path)).unwrap());
+            let narrowing = index.narrow(query).unwrap();
+            narrowing.rules_out(Path::new(path), &metadata)
+        };
+        assert!(!rules_out("quick", "a.txt"));
+        assert!(rules_out("quick", "sub/b.txt"));
+        assert!(!rules_out("quick", "c.bin"));
+        assert!(index.narrow("ox").is_none());
+
+        let dir_path = dir.to_str().unwrap();
+        assert_eq!(
+            output_of(&["-r", "dog", dir_path]),
+            format!("{}:lazy dog\n", dir.join("sub/b.txt").display())
+        );
+        fs::write(dir.join("sub/b.txt"), "a quick dog, now\n").unwrap();
+        assert!(!rules_out("quick", "sub/b.txt"));
+        assert_eq!(
+            output_of(&["-r", "quick", dir_path]),
+            format!("{}:a quick dog, now\n", dir.join("sub/b.txt").display())
+        );
+    }
+
+    #[test]
+    fn index_updates_only_what_changed() {
+        let dir = Path::new("dir");
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let mut before = MemoryFs::new();
+        for name in ["a", "b", "c"] {
+            before.insert(dir.join(name), format!("file {name}\n"));
+            before.set_modified(dir.join(name), at(100));
+        }
+        let mut index = Index::default();
+        let added = index.update(&before, dir);
+        assert_eq!((added.added, added.changed, added.removed), (3, 0, 0));
+
+        // a is rewritten, b is removed and d is added.
+        let mut fs = MemoryFs::new();
+        fs.insert(dir.join("a"), "now about cats\n");
+        fs.set_modified(dir.join("a"), at(200));
+        fs.insert(dir.join("c"), "file c\n");
+        fs.set_modified(dir.join("c"), at(100));
+        fs.insert(dir.join("d"), "file d, about dogs\n");
+        fs.set_modified(dir.join("d"), at(200));
+        let changes = index.update(&fs, dir);
+        assert_eq!((changes.added, changes.changed, changes.removed), (1, 1, 1));
+        assert_eq!(index.update(&fs, dir), index::Changes::default());
+        assert_eq!(index.len(), 3);
+
+        let rules_out = |query: &str, path: &str| {
+            let metadata = fs.metadata(&dir.join(path)).unwrap();
+            index
+                .narrow(query)
+                .unwrap()
+                .rules_out(Path::new(path), &metadata)
+        };
+        assert!(!rules_out("cats", "a"));
+        assert!(rules_out("file", "a"));
+        assert!(!rules_out("file", "c"));
+        assert!(rules_out("dogs", "c"));
+        assert!(!rules_out("dogs", "d"));
+    }
+
+    #[test]
+    fn tui_keys_from_raw_input() {
+        use tui::Key;
+
+        assert_eq!(
+            tui::keys("aé\x1b[A\x1b[B\x7f\x15\r".as_bytes()),
+            [
+                Key::Char('a'),
+                Key::Char('é'),
+                Key::Up,
+                Key::Down,
+                Key::Backspace,
+                Key::ClearQuery,
+                Key::Enter,
+            ]
+        );
+        assert_eq!(tui::keys(b"\x1b"), [Key::Quit]);
+        assert_eq!(tui::keys(b"\x1b[1;5Cx\x03"), [Key::Char('x'), Key::Quit]);
+    }
+
+    #[test]
+    fn repl_searches_cached_files() {
+        let config = Config::build(
+            ["minigrep", "-I", "-n", "poem.txt"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+        let files = cache::load(&config, &Disk);
+        let mut out = Vec::new();
+        assert_eq!(repl::search(&config, &files, "you", &mut out).unwrap(), 4);
+        assert_eq!(repl::search(&config, &files, "frog", &mut out).unwrap(), 1);
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .ends_with("\n7:How public, like a frog\n")
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "color", feature = "json"))]
+    fn completions_cover_flags_that_parse() {
+        let dir = TempDir::new("flags");
+        let file = dir.join("empty.txt");
+        fs::write(&file, "").unwrap();
+        let file = file.display().to_string();
+        for flag in completions::FLAGS {
+            let name = match (flag.long, flag.short) {
+                (Some(long), _) => format!("--{long}"),
+                (None, Some(short)) => format!("-{short}"),
+                (None, None) => unreachable!(),
+            };
+            let value = match (flag.value, flag.choices) {
+                (None, _) => None,
+                (Some(_), [first, ..]) => Some(*first),
+                (Some("TIME"), []) => Some("1d"),
+                (Some("SPEC"), []) => Some("match:none"),
+                (Some("N|NAME"), []) => Some("0"),
+                (Some("FILE"), []) => Some(file.as_str()),
+                (Some(_), []) => Some("1"),
+            };
+            // `replace` accepts every flag `search` does, and `--in-place`;
+            // `-o` lets `--top` parse, but some flags need whole lines.
+            let whole_lines = [
+                "--passthru",
+                "--after-context",
+                "--before-context",
+                "--context",
+                "--block-context",
+                "--show-function",
+                "--function-start",
+                "--explain",
+            ];
+            let only = Some("-o").filter(|_| !whole_lines.contains(&name.as_str()));
+            // `--group` needs a regex query, and the backup flags a file
+            // to rewrite.
+            let needs: &[&str] = match name.as_str() {
+                "--group" => &["--engine", "regex"],
+                "--backup-suffix" | "--backup-dir" => &["--in-place"],
+                _ => &[],
+            };
+            let args = ["minigrep", "replace"]
+                .into_iter()
+                .chain(only)
+                .chain([name.as_str()])
+                .chain(value)
+                .chain(needs.iter().copied())
+                .chain(["to", "from", "poem.txt"]);
+            let built = Config::build(args.map(String::from));
+            if name == "--pcre2" && !cfg!(feature = "pcre2") {
+                continue;
+            }
+            assert!(built.is_ok(), "{name}: {:?}", built.err());
+        }
+
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let script = completions::script(shell);
+            assert!(script.contains("max-columns"), "{shell:?}");
+            assert!(script.contains("always"), "{shell:?}");
+        }
+    }
+
+    #[test]
+    fn subcommands() {
+        assert_eq!(
+            output_of(&["replace", "nobody", "NOBODY", "poem.txt"]),
+            "I'm NOBODY! Who are you?\nAre you NOBODY, too?\n"
+        );
+        assert_eq!(output_of(&["count", "nobody", "poem.txt"]), "2\n");
+        assert_eq!(output_of(&["files", "frog", "poem.txt"]), "poem.txt\n");
+        assert_eq!(output_of(&["files", "zebra", "poem.txt"]), "");
+        assert_eq!(
+            output_of(&["search", "frog", "poem.txt"]),
+            "How public, like a frog\n"
+        );
+        // Only the first argument names a subcommand.
+        assert_eq!(output_of(&["-i", "count", "poem.txt"]), "");
+
+        let build = |args: &[&str]| Config::build(args.iter().map(|arg| arg.to_string()));
+        assert!(build(&["minigrep", "replace", "a", "poem.txt"]).is_err());
+        assert!(build(&["minigrep", "--in-place", "a", "poem.txt"]).is_err());
+    }
+
+    #[test]
+    fn replacements_can_keep_the_case_of_the_match() {
+        assert_eq!(output::match_case("colour", "COLOR"), "COLOUR");
+        assert_eq!(output::match_case("colour", "Color"), "Colour");
+        assert_eq!(output::match_case("Colour", "color"), "colour");
+        assert_eq!(output::match_case("colour", "C"), "Colour");
+        assert_eq!(output::match_case("colour", "cOlOr"), "colour");
+        assert_eq!(output::match_case("colour", "42"), "colour");
+        assert_eq!(
+            output_of(&["replace", "-i", "--preserve-case", "how", "why", "poem.txt"]),
+            "Why dreary to be somebody!\nWhy public, like a frog\n"
+        );
+        assert_eq!(
+            output_of(&[
+                "replace",
+                "-i",
+                "--preserve-case",
+                "NOBODY",
+                "someone",
+                "poem.txt"
+            ]),
+            "I'm someone! Who are you?\nAre you someone, too?\n"
+        );
+        assert!(
+            Config::build(
+                ["minigrep", "--preserve-case", "to", "poem.txt"]
+                    .into_iter()
+                    .map(String::from)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn replace_in_place() {
+        // Files are rewritten on disk, so this needs one.
+        let dir = TempDir::new("replace");
+        let path = dir.join("fish.txt");
+        fs::write(&path, "one fish\ntwo fish\nred\n").unwrap();
+        let file = path.to_str().unwrap();
+        assert_eq!(
+            output_of(&["replace", "--in-place", "fish", "cat", file]),
+            ""
+        );
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "one cat\ntwo cat\nred\n"
+        );
+        // Line endings are left as they were, down to a missing last one.
+        fs::write(&path, "one fish\r\nred\r\ntwo fish\nblue fish").unwrap();
+        output_of(&["replace", "--in-place", "fish", "cat", file]);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "one cat\r\nred\r\ntwo cat\nblue cat"
+        );
+
+        let backup = |args: &[&str]| {
+            let args = ["replace", "--in-place"].iter().chain(args);
+            Config::build(["minigrep"].iter().chain(args).map(|arg| arg.to_string()))
+                .unwrap()
+                .backup_path(Path::new("src/lib.rs"))
+        };
+        assert_eq!(backup(&["a", "b", "poem.txt"]), None);
+        assert_eq!(
+            backup(&["--backup-suffix", ".orig", "a", "b", "poem.txt"]),
+            Some(PathBuf::from("src/lib.rs.orig"))
+        );
+        let full = fs::canonicalize("src/lib.rs").unwrap();
+        let full = full
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)));
+        assert_eq!(
+            backup(&["--backup-dir", "bak", "a", "b", "poem.txt"]),
+            Some(Path::new("bak").join(full.collect::<PathBuf>()))
+        );
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::create_dir_all(dir.join("b/a")).unwrap();
+        fs::write(dir.join("a/x"), "").unwrap();
+        fs::write(dir.join("b/a/x"), "").unwrap();
+        let config = Config::build(
+            [
+                "minigrep",
+                "replace",
+                "--in-place",
+                "--backup-dir",
+                "bak",
+                "a",
+                "b",
+                "poem.txt",
+            ]
+            .into_iter()
+            .map(String::from),
+        )
+        .unwrap();
+        let outside = config.backup_path(&dir.join("b/../a/x"));
+        let inside = config.backup_path(&dir.join("b/a/x"));
+        assert_ne!(outside, inside);
+        assert_eq!(
+            backup(&["--backup-suffix", "~", "--no-backup", "a", "b", "poem.txt"]),
+            None
+        );
+        fs::write(&path, "one fish\n").unwrap();
+        output_of(&[
+            "replace",
+            "--in-place",
+            "--backup-suffix",
+            ".orig",
+            "fish",
+            "cat",
+            file,
+        ]);
+        let original = format!("{file}.orig");
+        assert_eq!(fs::read_to_string(&original).unwrap(), "one fish\n");
+
+        // The file is replaced whole, keeping its permissions and leaving
+        // no temporary file behind. Windows won't rename over a read-only
+        // file, so only the rest is checked there.
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(cfg!(unix));
+        fs::set_permissions(&path, permissions).unwrap();
+        output_of(&["replace", "--in-place", "cat", "dog", file]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one dog\n");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().readonly(),
+            cfg!(unix)
+        );
+        assert!(!dir.join(temp_name(&path)).exists());
+    }
+
+    #[test]
+    fn passthru_prints_every_line() {
+        let poem = fs::read_to_string("poem.txt").unwrap();
+        let output = output_of(&["--passthru", "-n", "frog", "poem.txt"]);
+        assert_eq!(output.lines().count(), poem.lines().count());
+        assert!(
+            output.contains("6-How dreary to be somebody!\n7:How public, like a frog\n8-To tell")
+        );
+        assert_eq!(
+            output_of(&["replace", "--passthru", "frog", "toad", "poem.txt"])
+                .lines()
+                .nth(6),
+            Some("How public, like a toad")
+        );
+        assert!(
+            Config::build(
+                ["minigrep", "--passthru", "-c", "a", "poem.txt"]
+                    .into_iter()
+                    .map(String::from)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn context_merges_overlapping_blocks() {
+        assert_eq!(
+            output_of(&["-n", "-A", "1", "tell", "poem.txt"]),
+            "3:Then there's a pair of us - don't tell!\n4-They'd banish us, you know.\n--\n\
+             8:To tell your name the livelong day\n9-To an admiring bog!\n"
+        );
+        // Line 3 comes after one match and before the next, but only once.
+        assert_eq!(
+            output_of(&["-n", "-C", "1", "you", "poem.txt"])
+                .lines()
+                .take(5)
+                .collect::<Vec<_>>(),
+            [
+                "1:I'm nobody! Who are you?",
+                "2:Are you nobody, too?",
+                "3-Then there's a pair of us - don't tell!",
+                "4:They'd banish us, you know.",
+                "5-",
+            ]
+        );
+        assert!(
+            output_of(&["-A", "1", "--group-separator", "~~", "tell", "poem.txt"])
+                .contains("\n~~\n")
+        );
+    }
+
+    #[test]
+    fn block_context_prints_the_paragraph() {
+        assert_eq!(
+            output_of(&["-n", "--block-context", "frog", "poem.txt"]),
+            "6-How dreary to be somebody!\n7:How public, like a frog\n\
+             8-To tell your name the livelong day\n9-To an admiring bog!\n"
+        );
+        let output = output_of(&["--block-context", "o", "poem.txt"]);
+        assert_eq!(output.lines().filter(|line| *line == "--").count(), 1);
+        assert_eq!(output.lines().count(), 9);
+    }
+
+    #[test]
+    fn show_function_heads_matches() {
+        let mut fs = MemoryFs::new();
+        fs.insert(
+            "thing.rs",
+            "pub fn first() {\n    print(1);\n}\n\nimpl Thing {\n    fn second() {\n        print(2);\n    }\n}\n",
+        );
+        let output = |args: &[&str]| output_in(&fs, &[args, &["print", "thing.rs"]].concat());
+
+        let guessed = output(&["-n", "--show-function"]);
+        let given = output(&["-n", "--function-start", "impl"]);
+        let context = output(&["-n", "-B", "1", "--show-function"]);
+
+        assert_eq!(
+            guessed,
+            "1=pub fn first() {\n2:    print(1);\n6=    fn second() {\n7:        print(2);\n"
+        );
+        assert_eq!(
+            given,
+            "2:    print(1);\n5=impl Thing {\n7:        print(2);\n"
+        );
+        // A function line that's already shown as context isn't repeated.
+        assert_eq!(
+            context,
+            "1-pub fn first() {\n2:    print(1);\n--\n6-    fn second() {\n7:        print(2);\n"
+        );
+        assert!(output::starts_function("int main(void)"));
+        assert!(!output::starts_function("  return f(x);"));
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json_input_reports_value_paths() {
+        let mut fs = MemoryFs::new();
+        let document = r#"{"config":{"users":[{"email":"ann@example.com"},{"email":"bob@example.org"}],
+            "odd key":"example","port":8080}}"#;
+        fs.insert("config.json", document);
+        fs.insert(
+            "lines.json",
+            "{\"id\":1,\"tag\":\"x\"}\n\n{\"id\":2,\"tag\":[\"y\",\"x\"]}\n",
+        );
+        fs.insert("broken.json", "not json");
+        let whole = output_in(&fs, &["--json-input", "example", "config.json"]);
+        let lines = output_in(&fs, &["--json-input", "-n", "x", "lines.json"]);
+        let numbers = output_in(&fs, &["--json-input", "2", "lines.json"]);
+        let broken = search_in(&fs, &["--json-input", "x", "broken.json"]);
+
+        assert_eq!(
+            whole,
+            "config.users[0].email: ann@example.com\nconfig.users[1].email: bob@example.org\n\
+             config[\"odd key\"]: example\n"
+        );
+        // Keys aren't searched, only values.
+        assert_eq!(lines, "1:tag: x\n3:tag[1]: x\n");
+        assert_eq!(numbers, "id: 2\n");
+        assert!(broken.is_err());
+    }
+
+    #[test]
+    fn csv_matches_chosen_fields() {
+        let mut fs = MemoryFs::new();
+        fs.insert(
+            "people.csv",
+            "name,city,note\r\nAnn,Paris,\"likes Paris, \"\"really\"\"\"\r\nBob,Rome,\"from\nParis\"\r\n",
+        );
+        let search =
+            |args: &[&str]| search_in(&fs, &[&["--csv"], args, &["Paris", "people.csv"]].concat());
+
+        let any = search(&[]).unwrap();
+        let city = search(&["--field", "city"]).unwrap();
+        let third = search(&["--field", "3"]).unwrap();
+        let missing = search(&["--field", "country"]);
+
+        assert_eq!(
+            any,
+            "2:Ann,Paris,\"likes Paris, \"\"really\"\"\"\n3:Bob,Rome,\"from\\nParis\"\n"
+        );
+        assert_eq!(
+            city.lines().collect::<Vec<_>>(),
+            [any.lines().next().unwrap()]
+        );
+        assert_eq!(third, any);
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn html_searches_text_not_markup() {
+        let mut fs = MemoryFs::new();
+        fs.insert(
+            "page.html",
+            r#"<!DOCTYPE html>
+<html><head><style>.note { note: 1 }</style>
+<script>if (a < b) { note() }</script></head>
+<body class="note">
+<!-- a note nobody reads -->
+<p class="intro">An <b>important</b> note &amp; more</p>
+<div id="main"><p>Second note</p>
+<p class="intro" title="note">Third</p></div>
+</body></html>
+"#,
+        );
+
+        let text = output_in(&fs, &["-n", "--html", "note", "page.html"]);
+        let nested = output_in(&fs, &["--selector", "#main p", "note", "page.html"]);
+        let classed = output_in(&fs, &["--selector", "p.intro", "-i", "an", "page.html"]);
+
+        assert_eq!(text, "6:An important note & more\n7:Second note\n");
+        assert_eq!(nested, "Second note\n");
+        assert_eq!(classed, "An important note & more\n");
+        assert!(Selector::parse("div.").is_none());
+    }
+
+    #[test]
+    fn git_rev_searches_old_contents() {
+        let dir = TempDir::new("git");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let git = |args: &[&str]| {
+            std::process::Command::new("git")
+                .arg("-C")
+                .arg(&*dir)
+                .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+                .args(args)
+                .output()
+        };
+        if git(&["init", "-q"]).is_err() {
+            return; // No git here.
+        }
+        fs::write(dir.join("sub/a.txt"), "old line\n").unwrap();
+        fs::write(dir.join("b.txt"), "old too\n").unwrap();
+        git(&["add", "."]).unwrap();
+        git(&["commit", "-q", "-m", "first"]).unwrap();
+        fs::write(dir.join("sub/a.txt"), "new line\n").unwrap();
+        let path = dir.display().to_string();
+
+        let old = output_of(&["--git-rev", "HEAD", "-r", "old", &path]);
+        let single = output_of(&["--git-rev", "HEAD", "line", &format!("{path}/sub/a.txt")]);
+
+        assert_eq!(
+            old,
+            format!("HEAD:{path}/b.txt:old too\nHEAD:{path}/sub/a.txt:old line\n")
+        );
+        // The working copy now says "new line".
+        assert_eq!(single, "old line\n");
+        assert!(
+            Config::build(
+                ["minigrep", "--git-rev", "--output=x", "old", "poem.txt"]
+                    .into_iter()
+                    .map(String::from)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn changed_limits_the_search_to_what_git_sees_changing() {
+        let dir = TempDir::new("changed");
+        let git = |args: &[&str]| {
+            std::process::Command::new("git")
+                .arg("-C")
+                .arg(&*dir)
+                .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+                .args(args)
+                .output()
+        };
+        if git(&["init", "-q"]).is_err() {
+            return; // No git here.
+        }
+        for name in ["kept.txt", "edited.txt", "later.txt"] {
+            fs::write(dir.join(name), "todo\n").unwrap();
+        }
+        fs::write(dir.join(".gitignore"), "ignored.txt\n").unwrap();
+        git(&["add", "kept.txt", "edited.txt", ".gitignore"]).unwrap();
+        git(&["commit", "-q", "-m", "first"]).unwrap();
+        git(&["add", "later.txt"]).unwrap();
+        git(&["commit", "-q", "-m", "second"]).unwrap();
+        fs::write(dir.join("edited.txt"), "todo: more\n").unwrap();
+        fs::write(dir.join("new.txt"), "todo\n").unwrap();
+        fs::write(dir.join("ignored.txt"), "todo\n").unwrap();
+        let path = dir.display().to_string();
+
+        let sorted = |output: String| {
+            let mut names: Vec<String> = output
+                .lines()
+                .map(|line| line.trim_start_matches(&format!("{path}/")).to_string())
+                .collect();
+            names.sort();
+            names
+        };
+        let changed = sorted(output_of(&["files", "--changed", "todo", &path]));
+        let since = sorted(output_of(&[
+            "files",
+            "-r",
+            "--changed-since",
+            "HEAD~1",
+            "todo",
+            &path,
+        ]));
+
+        assert_eq!(changed, ["edited.txt", "new.txt"]);
+        assert_eq!(since, ["edited.txt", "later.txt", "new.txt"]);
+    }
+
+    #[test]
+    fn searches_a_url() {
+        let curl = std::process::Command::new("curl")
+            .arg("--version")
+            .stdout(std::process::Stdio::null())
+            .status();
+        if !curl.is_ok_and(|status| status.success()) {
+            return; // No curl here.
+        }
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/poem.txt", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            for status in ["200 OK", "404 Not Found"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                while reader.read_line(&mut request).unwrap() > 2 {
+                    request.clear();
+                }
+                let body = fs::read_to_string("poem.txt").unwrap();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+        let args = |args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            Config::build(args).unwrap()
+        };
+        let token = CancellationToken::new();
+        let search = |config: &Config| {
+            let mut out = Vec::new();
+            let result = Searcher::new(config, &token).search_all(&mut out);
+            result.map(|_| String::from_utf8(out).unwrap())
+        };
+        let found = search(&args(&["-n", "frog", &url]));
+        let missing = search(&args(&["frog", &url]));
+        server.join().unwrap();
+
+        assert_eq!(found.unwrap(), "7:How public, like a frog\n");
+        assert!(missing.is_err());
+        assert!(
+            Config::build(
+                ["minigrep", "-r", "a", "https://example.com"]
+                    .into_iter()
+                    .map(String::from)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn ignore_files_are_followed() {
+        let mut fs = MemoryFs::new();
+        for file in [
+            "main.txt",
+            "debug.log",
+            "keep.log",
+            "build/out.txt",
+            "src/lib.txt",
+            "src/generated/gen.txt",
+            "docs/notes.txt",
+            "docs/draft.txt",
+        ] {
+            fs.insert(format!("root/{file}"), "needle\n");
+        }
+        fs.insert(
+            "root/.gitignore",
+            "# build output\n/build/\n*.log\n!keep.log\n",
+        );
+        fs.insert("root/src/.ignore", "generated\n");
+        fs.insert("root/docs/.rgignore", "dr?ft.*\n");
+        fs.insert("root/docs/.gitignore", "notes.txt\n");
+        fs.insert("root/.ignore", "!docs/notes.txt\n");
+
+        let files = |flags: &[&str]| {
+            let args = [
+                &["files", "-r", "--no-ignore-global"],
+                flags,
+                &["needle", "root"],
+            ]
+            .concat();
+            let mut found: Vec<String> = output_in(&fs, &args)
+                .lines()
+                .map(|line| line.trim_start_matches("root/").to_string())
+                .collect();
+            found.sort();
+            found
+        };
+        let found = files(&[]);
+        let everything = files(&["--no-ignore"]);
+        let outside_git = files(&["--no-ignore-vcs"]);
+
+        // The deeper docs/.gitignore wins over the top-level .ignore.
+        assert_eq!(found, ["keep.log", "main.txt", "src/lib.txt"]);
+        assert_eq!(everything.len(), 8);
+        assert_eq!(
+            outside_git,
+            [
+                "build/out.txt",
+                "debug.log",
+                "docs/notes.txt",
+                "keep.log",
+                "main.txt",
+                "src/lib.txt"
+            ]
+        );
+        assert!(ignore::glob(b"a/**/b", b"a/b"));
+        assert!(ignore::glob(b"a/**/b", b"a/x/y/b"));
+        assert!(!ignore::glob(b"*.txt", b"dir/a.txt"));
+        assert!(ignore::glob(b"[a-c]x[!0-9]", b"bxz"));
+    }
+
+    #[test]
+    fn ignored_directories_can_be_searched_anyway() {
+        let mut fs = MemoryFs::new();
+        fs.insert("root/main.txt", "needle\n");
+        fs.insert("root/target/out.txt", "needle\n");
+        fs.insert("root/notes.txt", "needle\n");
+        fs.insert("root/.gitignore", "target/\n");
+        fs.insert("root/.ignore", "notes.txt\n");
+
+        let files = |flags: &[&str]| {
+            let args = [&["files", "-r"], flags, &["needle", "root"]].concat();
+            let mut found: Vec<String> = output_in(&fs, &args)
+                .lines()
+                .map(|line| line.trim_start_matches("root/").to_string())
+                .collect();
+            found.sort();
+            found
+        };
+        let found = files(&[]);
+        let everything = files(&["--no-ignore"]);
+        let outside_git = files(&["--no-ignore-vcs"]);
+        let outside_global = files(&["--no-ignore-global"]);
+
+        assert_eq!(found, ["main.txt"]);
+        assert_eq!(everything, ["main.txt", "notes.txt", "target/out.txt"]);
+        assert_eq!(outside_git, ["main.txt", "target/out.txt"]);
+        assert_eq!(outside_global, found);
+    }
+
+    #[test]
+    fn global_ignore_file_applies_everywhere() {
+        let mut fs = MemoryFs::new();
+        for file in [
+            "vendor/lib.txt",
+            "web/app.js",
+            "web/app.min.js",
+            "web/keep.min.js",
+        ] {
+            fs.insert(format!("tree/{file}"), "needle\n");
+        }
+        // The tree's own rules come first.
+        fs.insert("tree/web/.ignore", "!keep.min.js\n");
+        // The user's own file is always read from disk.
+        let dir = TempDir::new("global-ignore");
+        fs::write(dir.join("ignore"), "vendor/\n*.min.js\n").unwrap();
+
+        let ignores = Ignores::reading(Some(&dir.join("ignore")));
+        let tree = Path::new("tree");
+        let mut found: Vec<PathBuf> = walk::walk(&fs, tree, &ignores, false, |_, _| true)
+            .into_iter()
+            .map(|path| path.strip_prefix(tree).unwrap().to_path_buf())
+            .collect();
+        found.sort();
+
+        let expected: Vec<PathBuf> = ["web/.ignore", "web/app.js", "web/keep.min.js"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_links_are_searched_once() {
+        let root = TempDir::new("hardlink");
+        fs::create_dir_all(root.join("farm")).unwrap();
+        fs::write(root.join("a.txt"), "needle\n").unwrap();
+        fs::hard_link(root.join("a.txt"), root.join("farm/b.txt")).unwrap();
+        fs::write(root.join("farm/c.txt"), "needle\n").unwrap();
+
+        let output = output_of(&["files", "-r", "needle", &root.display().to_string()]);
+
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.contains("c.txt"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn followed_symlinks_are_searched_once_by_canonical_path() {
+        use std::os::unix::fs::symlink;
+
+        let root = TempDir::new("symlinks");
+        fs::create_dir_all(root.join("tree/real")).unwrap();
+        fs::create_dir_all(root.join("elsewhere")).unwrap();
+        fs::write(root.join("elsewhere/target.txt"), "needle\n").unwrap();
+        fs::write(root.join("tree/real/own.txt"), "needle\n").unwrap();
+        symlink(root.join("elsewhere/target.txt"), root.join("tree/one.txt")).unwrap();
+        symlink(root.join("elsewhere/target.txt"), root.join("tree/two.txt")).unwrap();
+        symlink(root.join("tree/real"), root.join("tree/alias")).unwrap();
+        // A loop back to the top mustn't be walked forever.
+        symlink(root.join("tree"), root.join("tree/real/up")).unwrap();
+
+        let tree = root.join("tree").display().to_string();
+        let mut followed: Vec<String> = output_of(&["files", "-r", "-L", "needle", &tree])
+            .lines()
+            .map(String::from)
+            .collect();
+        followed.sort();
+        let unfollowed = output_of(&["files", "-r", "needle", &tree]);
+        let canonical = fs::canonicalize(&*root).unwrap();
+
+        let target = canonical.join("elsewhere/target.txt").display().to_string();
+        assert_eq!(followed.len(), 2, "{followed:?}");
+        assert!(followed.contains(&target), "{followed:?}");
+        assert_eq!(unfollowed, format!("{tree}/real/own.txt\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn paths_need_not_be_utf8() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let root = TempDir::new("os-paths");
+        let path = root.join(OsString::from_vec(b"caf\xe9.txt".to_vec()));
+        fs::write(&path, "needle\n").unwrap();
+
+        let args = [
+            OsString::from("minigrep"),
+            "needle".into(),
+            path.clone().into(),
+        ];
+        let config = Config::build(args.into_iter()).unwrap();
+        assert_eq!(config.input.file_path, path);
+        let mut out = Vec::new();
+        Searcher::new(&config, &CancellationToken::new())
+            .search_all(&mut out)
+            .unwrap();
+        let searched = output_of(&["files", "-r", "needle", &root.display().to_string()]);
+
+        assert_eq!(String::from_utf8(out).unwrap(), "needle\n");
+        assert_eq!(searched, format!("{}\n", path.display()));
+        let query = OsString::from_vec(b"\xff".to_vec());
+        let args = [OsString::from("minigrep"), query, "poem.txt".into()];
+        assert!(Config::build(args.into_iter()).is_err());
+    }
+
+    #[test]
+    fn trees_deeper_than_max_path_are_walked() {
+        let root = TempDir::new("deep");
+        // Well past the 260 characters Windows allows without `\\?\`.
+        let deep = (0..12).fold(root.to_path_buf(), |dir, i| {
+            dir.join(format!("node_modules-{i:0>20}"))
+        });
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(deep.join("index.js"), "needle\n").unwrap();
+
+        let found = output_of(&["files", "-r", "needle", &root.display().to_string()]);
+
+        assert!(deep.as_os_str().len() > 300);
+        assert_eq!(found, format!("{}\n", deep.join("index.js").display()));
+    }
+
+    #[test]
+    fn directories_without_r_suggest_it() {
+        let search = |args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            let config = Config::build(args).unwrap();
+            let mut out = Vec::new();
+            Searcher::new(&config, &CancellationToken::new())
+                .search_all(&mut out)
+                .map(|_| String::from_utf8(out).unwrap())
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(
+            search(&["frog", "."]),
+            Err(". is a directory; use -r to search it".to_string())
+        );
+        assert_eq!(
+            search(&["--directories", "skip", "frog", "."]),
+            Ok(String::new())
+        );
+        let recursed = search(&["--directories", "recurse", "frog", "."]).unwrap();
+        assert!(
+            recursed.contains("poem.txt:How public, like a frog\n"),
+            "{recursed}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn named_pipes_are_skipped_unless_asked_for() {
+        let root = TempDir::new("fifo");
+        let fifo = root.join("pipe");
+        let made = std::process::Command::new("mkfifo").arg(&fifo).status();
+        if !made.is_ok_and(|status| status.success()) {
+            return; // No mkfifo here.
+        }
+        let pipe = fifo.display().to_string();
+        let dir = root.display().to_string();
+
+        // Neither of these would return if the pipe were opened.
+        let skipped = output_of(&["needle", &pipe]);
+        let walked = output_of(&["-r", "needle", &dir]);
+        let writer = thread::spawn({
+            let fifo = fifo.clone();
+            move || fs::write(fifo, "hay\nneedle\n").unwrap()
+        });
+        let read = output_of(&["--devices", "read", "-n", "needle", &pipe]);
+        writer.join().unwrap();
+
+        assert_eq!(skipped, "");
+        assert_eq!(walked, "");
+        assert_eq!(read, "2:needle\n");
+    }
+
+    #[test]
+    fn wildcards_in_the_path_are_expanded() {
+        let mut fs = MemoryFs::new();
+        for name in ["a.log", "b.log", ".hidden.log", "notes.txt", "[x].log"] {
+            fs.insert(format!("logs/{name}"), "needle\n");
+        }
+        let search = |pattern: &str| search_in(&fs, &["needle", &format!("logs/{pattern}")]);
+
+        assert_eq!(
+            search("*.log").unwrap(),
+            "logs/[x].log:needle\nlogs/a.log:needle\nlogs/b.log:needle\n"
+        );
+        assert_eq!(search(".*").unwrap(), "logs/.hidden.log:needle\n");
+        // A name that exists as it is isn't taken for a pattern.
+        assert_eq!(search("[x].log").unwrap(), "needle\n");
+        assert_eq!(
+            search("*.none").unwrap_err().to_string(),
+            "no files match logs/*.none"
+        );
+    }
+
+    #[test]
+    fn media_and_executables_are_skipped_by_their_first_bytes() {
+        let mut fs = MemoryFs::new();
+        fs.insert("root/logo.png", b"\x89PNG\r\n\x1a\n needle\n");
+        fs.insert("root/tool", b"\x7fELF needle\n");
+        fs.insert("root/song.mp3", b"ID3\x04\0 needle\n");
+        // Text that merely starts like a signature is still searched.
+        fs.insert("root/notes.txt", "ID3 tags: needle\nMZ needle\n");
 
-pub struct Config {
-    pub query: String,
-    pub file_path: String,
-    pub ignore_case: bool,
-}
+        let sniffed = output_in(&fs, &["files", "-r", "needle", "root"]);
+        let config = Config::build(
+            ["minigrep", "-r", "needle", "root"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+        let mut explained = Vec::new();
+        explain_skips(&config, &fs, Path::new("root"), &mut explained).unwrap();
+        let everything = output_in(&fs, &["files", "-r", "--no-sniff", "needle", "root"]);
 
-impl Config {
-    pub fn build(args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
-        let mut ignore_case = false;
+        assert_eq!(sniffed, "root/notes.txt\n");
+        let explained = String::from_utf8(explained).unwrap();
+        assert!(
+            explained.contains("logo.png: skipped, looks like an image"),
+            "{explained}"
+        );
+        assert!(explained.contains("tool: skipped, looks like an executable"));
+        assert!(explained.contains("song.mp3: skipped, looks like audio"));
+        // The PNG still isn't UTF-8, and is reported as such.
+        assert_eq!(everything.lines().count(), 3, "{everything}");
+    }
 
-        let mut filtered_args = args.filter(|arg| match arg.as_str() {
-            "-i" => {
-                ignore_case = true;
-                false
-            }
-            _ => true,
-        });
+    #[test]
+    fn max_total_stops_the_whole_search() {
+        let mut fs = MemoryFs::new();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs.insert(format!("root/{name}"), "needle\nneedle\n");
+        }
+
+        let printed = output_in(&fs, &["-r", "--max-total", "3", "needle", "root"]);
+        let counted = output_in(&fs, &["-c", "-r", "--max-total", "3", "needle", "root"]);
+
+        assert_eq!(printed.lines().count(), 3, "{printed}");
+        let counts: Vec<&str> = counted
+            .lines()
+            .map(|line| &line[line.len() - 1..])
+            .collect();
+        assert_eq!(counts, ["2", "1"], "{counted}");
+        assert!(
+            Config::build(
+                ["minigrep", "--max-total", "0", "to", "poem.txt"]
+                    .into_iter()
+                    .map(String::from)
+            )
+            .is_err()
+        );
+    }
 
-        filtered_args.next(); // We don't need the program name
+    #[test]
+    fn head_and_tail_limit_the_lines_printed() {
+        assert_eq!(
+            output_of(&["--head", "2", "-n", "you", "poem.txt"]),
+            "1:I'm nobody! Who are you?\n2:Are you nobody, too?\n"
+        );
+        assert_eq!(
+            output_of(&["--tail", "2", "-n", "-i", "to", "poem.txt"]),
+            "8:To tell your name the livelong day\n9:To an admiring bog!\n"
+        );
+        // With -o, everything one line printed is kept together.
+        assert_eq!(
+            output_of(&["--tail", "1", "-o", "-i", "to", "poem.txt"]),
+            "To\n"
+        );
+        assert_eq!(
+            output_of(&["--head", "1", "--tail", "5", "-i", "frog", "poem.txt"]),
+            "How public, like a frog\n"
+        );
+        let e = Config::build(
+            ["minigrep", "--tail", "1", "-c", "to", "poem.txt"]
+                .into_iter()
+                .map(String::from),
+        );
+        assert!(e.is_err());
+    }
 
-        let query = match filtered_args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a query string"),
+    #[test]
+    fn buffering_can_be_chosen() {
+        let buffering = |args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            Config::build(args).unwrap().output.buffering
         };
+        assert_eq!(buffering(&["to", "poem.txt"]), Buffering::Auto);
+        assert_eq!(
+            buffering(&["--line-buffered", "to", "poem.txt"]),
+            Buffering::Line
+        );
+        // The last one given wins, so an alias can be overridden.
+        assert_eq!(
+            buffering(&["--line-buffered", "--block-buffered", "to", "poem.txt"]),
+            Buffering::Block
+        );
+    }
 
-        let file_path = match filtered_args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a file path"),
+    #[test]
+    fn lines_can_end_with_crlf_or_nul() {
+        let output = |fs: &dyn FileSystem, args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            let config = Config::build(args).unwrap();
+            let token = CancellationToken::new();
+            let mut searcher = Searcher::new(&config, &token);
+            searcher.fs = fs;
+            let mut out = Vec::new();
+            let mut ends = LineEnds::new(&mut out, config.output.line_terminator);
+            searcher.search_all(&mut ends).unwrap();
+            out
         };
+        assert_eq!(
+            output(&Disk, &["--crlf", "-n", "bog", "poem.txt"]),
+            b"9:To an admiring bog!\r\n"
+        );
+        assert_eq!(output(&Disk, &["--null", "-c", "you", "poem.txt"]), b"4\0");
 
-        ignore_case = ignore_case || env::var("IGNORE_CASE").is_ok();
+        // Lines that already end in \r aren't given a second one.
+        let mut fs = MemoryFs::new();
+        fs.insert("crlf.txt", "one\r\ntwo\r\n");
+        assert_eq!(output(&fs, &["--crlf", "o", "crlf.txt"]), b"one\r\ntwo\r\n");
+    }
 
-        Ok(Config {
-            query,
-            file_path,
-            ignore_case,
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn a_big_file_is_searched_in_chunks() {
+        let poem = fs::read_to_string("poem.txt").unwrap().repeat(50);
+        for n in [1, 3, 7, 1000] {
+            let chunks = parallel::chunks(&poem, n);
+            assert!(chunks.len() <= n);
+            assert_eq!(chunks.first().unwrap().start, 0);
+            assert_eq!(chunks.last().unwrap().end, poem.len());
+            for pair in chunks.windows(2) {
+                assert_eq!(pair[0].end, pair[1].start);
+                assert_eq!(poem.as_bytes()[pair[0].end - 1], b'\n');
+            }
+        }
+
+        let config = Config::build(
+            ["minigrep", "-i", "you", "poem.txt"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+        let matcher = Matcher::any(&config.search.queries, true, Engine::Literal).unwrap();
+        let one: Vec<(usize, &str)> = (1..)
+            .zip(poem.lines())
+            .filter(|(_, line)| matcher.is_match(line))
+            .collect();
+        let token = CancellationToken::new();
+        let mut many = Vec::new();
+        let work = parallel::search(&poem, &config, 4, &token, |line_number, line| {
+            many.push((line_number, line));
+            Ok::<_, regex::Error>(true)
+        })
+        .unwrap();
+        assert_eq!(many, one);
+        assert_eq!(work.threads, 4);
+        assert!(!work.busy.is_zero());
+        // Stopping early stops the threads too.
+        let mut first = None;
+        parallel::search(&poem, &config, 4, &token, |line_number, _| {
+            first = Some(line_number);
+            Ok::<_, regex::Error>(false)
+        })
+        .unwrap();
+        assert_eq!(first, Some(1));
+        // So does cancelling the search.
+        let token = CancellationToken::new();
+        token.cancel();
+        let mut called = false;
+        parallel::search(&poem, &config, 4, &token, |_, _| {
+            called = true;
+            Ok::<_, regex::Error>(true)
         })
+        .unwrap();
+        assert!(!called);
+        assert_eq!(parallel::chunks("", 4), []);
     }
-}
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.file_path)?;
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn a_big_file_prints_in_order_whatever_thread_found_it() {
+        let hay = "hay ".repeat(15) + "\n";
+        let lines = parallel::MIN_SIZE / hay.len() + 1000;
+        let needles = [1, lines / 3, lines / 2, lines];
+        let mut contents = String::with_capacity(lines * hay.len());
+        for line_number in 1..=lines {
+            match needles.contains(&line_number) {
+                true => contents.push_str(&format!("needle {line_number}\n")),
+                false => contents.push_str(&hay),
+            }
+        }
+        let mut fs = MemoryFs::new();
+        fs.insert("big.txt", contents);
 
-    let results = if config.ignore_case {
-        search_case_insensitive(&config.query, &contents)
-    } else {
-        search(&config.query, &contents)
-    };
+        let all = output_in(&fs, &["-n", "needle", "big.txt"]);
+        let to = (lines / 2).to_string();
+        let some = output_in(&fs, &["-n", "--to-line", &to, "needle", "big.txt"]);
+        let stats = output_in(
+            &fs,
+            &[
+                "--stats=full",
+                "--stats-format",
+                "json",
+                "needle",
+                "big.txt",
+            ],
+        );
 
-    for line in results {
-        println!("{line}");
+        let printed = |needles: &[usize]| -> String {
+            needles
+                .iter()
+                .map(|n| format!("{n}:needle {n}\n"))
+                .collect()
+        };
+        assert_eq!(all, printed(&needles));
+        assert_eq!(some, printed(&needles[..3]));
+        // The stats count every thread that searched the file.
+        let threads = thread::available_parallelism().map_or(1, usize::from);
+        assert!(
+            stats.contains(&format!("\"threads\":{threads},")),
+            "{stats}"
+        );
     }
 
-    Ok(())
-}
+    #[test]
+    fn a_search_can_be_followed_through_events() {
+        struct Recorder(Vec<String>);
+        impl Handler for Recorder {
+            fn handle(&mut self, event: Event<'_>) {
+                self.0.push(match event {
+                    Event::FileStart(path) => format!("start {}", path.display()),
+                    Event::Match(m) => format!("match {} {} {:?}", m.line_number, m.line, m.spans),
+                    Event::ContextLine(c) => format!("context {} {}", c.line_number, c.line),
+                    Event::FileEnd(stats) => format!("end {}", stats.matched_lines),
+                    Event::SearchEnd(summary) => format!(
+                        "done {} {} {}",
+                        summary.files_searched, summary.files_matched, summary.matched_lines
+                    ),
+                });
+            }
+        }
 
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    contents
-        .lines()
-        .filter(|line| line.contains(query))
-        .collect()
-}
+        let config = Config::build(
+            ["minigrep", "-C", "1", "frog", "poem.txt"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+        let mut recorder = Recorder(Vec::new());
+        assert!(search_events(&config, &CancellationToken::new(), &mut recorder).unwrap());
+        assert_eq!(
+            recorder.0,
+            [
+                "start poem.txt",
+                "context 6 How dreary to be somebody!",
+                "match 7 How public, like a frog [19..23]",
+                "context 8 To tell your name the livelong day",
+                "end 1",
+                "done 1 1 1",
+            ]
+        );
+    }
 
-pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    contents
-        .lines()
-        .filter(|line| line.to_lowercase().contains(&query))
-        .collect()
-}
+    #[test]
+    fn files_can_be_searched_in_memory() {
+        struct Matches(Vec<String>);
+        impl Handler for Matches {
+            fn handle(&mut self, event: Event<'_>) {
+                if let Event::Match(m) = event {
+                    self.0.push(format!("{}:{}", m.path.display(), m.line));
+                }
+            }
+        }
+        let search = |fs: &MemoryFs, args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            let config = Config::build(args).unwrap();
+            let mut matches = Matches(Vec::new());
+            search_events_in(fs, &config, &CancellationToken::new(), &mut matches).unwrap();
+            matches.0
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let mut fs = MemoryFs::new();
+        fs.insert("tree/a.txt", "one fish\ntwo fish\n");
+        fs.insert("tree/sub/b.txt", "red fish\n");
+        fs.insert("tree/sub/skip.log", "blue fish\n");
+        fs.insert("tree/.ignore", "*.log\n");
+        let path = |path: &str| PathBuf::from(path).display().to_string();
+        assert_eq!(
+            search(&fs, &["-r", "fish", "tree"]),
+            [
+                format!("{}:one fish", path("tree/a.txt")),
+                format!("{}:two fish", path("tree/a.txt")),
+                format!("{}:red fish", path("tree/sub/b.txt")),
+            ]
+        );
+        assert_eq!(search(&fs, &["two", "tree/a.txt"]), ["tree/a.txt:two fish"]);
+        assert!(fs.metadata(Path::new("tree/sub")).unwrap().is_dir());
+        assert!(fs.open(Path::new("tree/sub")).is_err());
+
+        // Sorting goes by what the file system says, not the disk.
+        let mut fs = MemoryFs::new();
+        fs.insert("tree/new.txt", "fish\n");
+        fs.insert("tree/old.txt", "big fish, bigger fish\n");
+        fs.set_modified(
+            "tree/new.txt",
+            SystemTime::UNIX_EPOCH + Duration::from_secs(200),
+        );
+        fs.set_modified(
+            "tree/old.txt",
+            SystemTime::UNIX_EPOCH + Duration::from_secs(100),
+        );
+        let (new, old) = (path("tree/new.txt"), path("tree/old.txt"));
+        let sorted = |args: &[&str]| {
+            let args = [&["-r", "--no-ignore-global"], args, &["fish", "tree"]].concat();
+            search(&fs, &args)
+        };
+        assert_eq!(
+            sorted(&["--sort", "modified"]),
+            [
+                format!("{old}:big fish, bigger fish"),
+                format!("{new}:fish")
+            ]
+        );
+        assert_eq!(
+            sorted(&["--sortr", "modified"]),
+            [
+                format!("{new}:fish"),
+                format!("{old}:big fish, bigger fish")
+            ]
+        );
+        assert_eq!(
+            sorted(&["--sortr", "size"]),
+            [
+                format!("{old}:big fish, bigger fish"),
+                format!("{new}:fish")
+            ]
+        );
+    }
 
     #[test]
-    fn case_sensitive() {
-        let query = "duct";
-        let contents = "\
-Rust:
-safe, fast, productive.
-Pick three.
-Duct tape.";
+    fn small_recent_files_are_searched_first() {
+        let mut fs = MemoryFs::new();
+        fs.insert("tree/a_big.txt", "fish\n".repeat(10_000));
+        fs.insert("tree/b_old.txt", "old fish\n");
+        fs.insert("tree/c_new.txt", "new fish\n");
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        fs.set_modified("tree/a_big.txt", at(300));
+        fs.set_modified("tree/b_old.txt", at(100));
+        fs.set_modified("tree/c_new.txt", at(200));
 
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+        let files = |args: &[&str], terminal| {
+            let args = [
+                &["minigrep", "-r", "--no-ignore-global"],
+                args,
+                &["fish", "tree"],
+            ]
+            .concat();
+            let args = args.into_iter().map(String::from);
+            let config = Config::parse_with(args, &mut || None, terminal).unwrap();
+            struct Files(Vec<String>);
+            impl Handler for Files {
+                fn handle(&mut self, event: Event<'_>) {
+                    if let Event::FileStart(path) = event {
+                        let name = path.file_name().unwrap();
+                        self.0.push(name.to_string_lossy().into_owned());
+                    }
+                }
+            }
+            let mut files = Files(Vec::new());
+            search_events_in(&fs, &config, &CancellationToken::new(), &mut files).unwrap();
+            files.0
+        };
+        let first_results_first = ["c_new.txt", "b_old.txt", "a_big.txt"];
+        assert_eq!(files(&["--sort", "priority"], false), first_results_first);
+        assert_eq!(files(&[], true), first_results_first);
+        // Piped output keeps the walk order.
+        assert_eq!(files(&[], false), ["a_big.txt", "b_old.txt", "c_new.txt"]);
     }
 
     #[test]
-    fn case_insensitive() {
-        let query = "rUsT";
-        let contents = "\
-Rust:
-safe, fast, productive.
-Pick three.
-Trust me.";
+    fn matching_lines_can_be_sorted() {
+        assert_eq!(
+            output_of(&["--sort-lines", "alpha", "-n", "you", "poem.txt"]),
+            "2:Are you nobody, too?\n\
+             1:I'm nobody! Who are you?\n\
+             4:They'd banish us, you know.\n\
+             8:To tell your name the livelong day\n"
+        );
+        assert_eq!(
+            output_of(&["--sort-lines", "length", "-i", "how", "poem.txt"]),
+            "How public, like a frog\nHow dreary to be somebody!\n"
+        );
+
+        let mut fs = MemoryFs::new();
+        fs.insert("dir/a.txt", "b\nd\n");
+        fs.insert("dir/b.txt", "c\na\n");
+        let strip = |output: String| {
+            output
+                .lines()
+                .map(|line| line.rsplit(':').next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        let each = strip(output_in(
+            &fs,
+            &[
+                "-r",
+                "--sort",
+                "path",
+                "--sort-lines",
+                "alpha",
+                "-o",
+                ".",
+                "--engine",
+                "regex",
+                "dir",
+            ],
+        ));
+        let all = strip(output_in(
+            &fs,
+            &[
+                "-r",
+                "--sort",
+                "path",
+                "--sort-lines",
+                "alpha",
+                "--sort-scope",
+                "all",
+                "-o",
+                ".",
+                "--engine",
+                "regex",
+                "dir",
+            ],
+        ));
+
+        assert_eq!(each, ["b", "d", "a", "c"]);
+        assert_eq!(all, ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn existence_checks_stop_at_the_first_match() {
+        let config = Config::build(
+            ["minigrep", "-q", "to", "poem.txt"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+        let token = CancellationToken::new();
+        let mut searcher = Searcher::new(&config, &token);
+        searcher.search_all(&mut io::sink()).unwrap();
+        assert_eq!(searcher.total, 1);
+        assert!(!token.is_cancelled(), "the caller's token is left alone");
+        let config = Config {
+            search: SearchOptions {
+                queries: vec!["zebra".to_string()],
+                ..config.search
+            },
+            ..config
+        };
+        assert!(!any_match(&config, &token).unwrap());
 
+        let config = Config::build(
+            ["minigrep", "-l", "--stats", "to", "poem.txt"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        let mut searcher = Searcher::new(&config, &token);
+        searcher.search_all(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("poem.txt\n"), "{out}");
+        assert!(out.contains("\n1 matched lines\n"), "{out}");
+    }
+
+    #[test]
+    fn searches_say_whether_anything_matched() {
+        let search = |args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            let config = Config::build(args).unwrap();
+            let matched = Searcher::new(&config, &CancellationToken::new())
+                .search_all(&mut Vec::new())
+                .unwrap();
+            (matched, config.output.message_on_no_match)
+        };
+        assert_eq!(search(&["frog", "poem.txt"]), (true, false));
+        assert_eq!(search(&["-c", "zebra", "poem.txt"]), (false, false));
         assert_eq!(
-            vec!["Rust:", "Trust me."],
-            search_case_insensitive(query, contents)
+            search(&["--message-on-no-match", "zebra", "poem.txt"]),
+            (false, true)
         );
+        assert_eq!(
+            search(&[
+                "--message-on-no-match",
+                "--quiet-no-match",
+                "zebra",
+                "poem.txt"
+            ]),
+            (false, false)
+        );
+        assert!(search(&["--explain-skips", "zebra", "poem.txt"]).0);
     }
 
     #[test]
-    fn config_error_too_few_arguments() {
-        let result = Config::build([String::from("foo")].into_iter());
-        match result {
-            Ok(_) => panic!("This should fail"),
-            Err(e) => assert!(e.to_lowercase().contains("query")),
+    fn regex_engine_finds_leftmost_first_matches() {
+        let regex = |pattern: &str, line: &str| {
+            Matcher::build(pattern, false, Engine::Regex)
+                .unwrap()
+                .find_all(line)
+                .into_iter()
+                .map(|span| line[span].to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(regex(r"\d+", "a1 b22 c333"), ["1", "22", "333"]);
+        assert_eq!(regex("a|ab", "abab"), ["a", "a"]);
+        assert_eq!(regex("<.+?>", "<b>bold</b>"), ["<b>", "</b>"]);
+        assert_eq!(regex("^(ab){2,3}$", "ababab"), ["ababab"]);
+        assert!(regex("^(ab){2,3}$", "abababab").is_empty());
+        assert_eq!(regex(r"\bcat\b", "cat concat cat."), ["cat", "cat"]);
+        assert_eq!(regex("[^a-c]+", "abcxyzabc"), ["xyz"]);
+        assert_eq!(
+            regex(r"(?P<word>\w+)@[\w.]+", "mail bo@x.org"),
+            ["bo@x.org"]
+        );
+        // Linear time: this would take forever with backtracking.
+        assert!(regex("(a*)*b", &"a".repeat(5000)).is_empty());
+        assert_eq!(
+            Matcher::build("STRASSE|é+", true, Engine::Regex)
+                .unwrap()
+                .find_all("straße strasse ÉÉ"),
+            vec![8..15, 16..20]
+        );
+
+        for pattern in ["(a", "a)", "[a", "*a", "a{3,1}", r"\q"] {
+            assert!(Regex::new(pattern, false).is_err(), "{pattern}");
         }
+        assert_eq!(
+            Regex::new("ab(c", false).err().and_then(|e| e.position),
+            Some(2)
+        );
+        let nested = |depth| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Regex::new(&nested(250), false).is_ok());
+        assert_eq!(
+            Regex::new(&nested(100_000), false).err().map(|e| e.message),
+            Some("groups nested too deeply in the regex")
+        );
     }
 
     #[test]
-    fn config_three_arguments() {
-        let result = Config::build(
-            [
-                String::from("minigrep"),
-                String::from("to"),
-                String::from("poem.txt"),
-            ]
-            .into_iter(),
+    fn a_missing_query_can_be_asked_for() {
+        let args = || ["minigrep", "-n", "poem.txt"].into_iter();
+        let config = Config::build_with(args(), &mut || Some("frog".to_string()), false).unwrap();
+        assert_eq!(config.search.queries, ["frog"]);
+        assert_eq!(config.input.file_path, Path::new("poem.txt"));
+        let e = Config::build_with(args(), &mut || None, false)
+            .err()
+            .unwrap();
+        assert_eq!(e.to_string(), "Didn't get a file path");
+        // Nothing is asked when the query is there.
+        let mut asked = false;
+        let both = ["minigrep", "to", "poem.txt"].into_iter();
+        Config::build_with(
+            both,
+            &mut || {
+                asked = true;
+                None
+            },
+            false,
         )
         .unwrap();
+        assert!(!asked);
+    }
+
+    #[test]
+    fn synonyms_expand_a_query() {
+        let synonyms = Synonyms::parse("# spellings\n\nfrog: toad, Dreary\nbog:marsh,\n").unwrap();
+        let queries = |queries: &[&str]| queries.iter().map(|q| q.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            synonyms.expand(&queries(&["frog", "toad"]), false),
+            ["frog", "toad", "Dreary"]
+        );
+        assert_eq!(synonyms.expand(&queries(&["Bog"]), false), ["Bog"]);
+        assert_eq!(synonyms.expand(&queries(&["Bog"]), true), ["Bog", "marsh"]);
+        assert!(Synonyms::parse("frog toad\n").is_err());
 
-        assert_eq!(result.query, "to");
-        assert_eq!(result.file_path, "poem.txt");
+        let dir = TempDir::new("synonyms");
+        let path = dir.join("synonyms.txt");
+        fs::write(&path, "frog: dreary\n").unwrap();
+        let output = output_of(&[
+            "--synonyms",
+            &path.display().to_string(),
+            "frog",
+            "poem.txt",
+        ]);
+        assert_eq!(
+            output,
+            "How dreary to be somebody!\nHow public, like a frog\n"
+        );
     }
 
     #[test]
-    fn config_can_set_case_insensitive() {
-        let result = Config::build(
-            [
-                String::from("minigrep"),
-                String::from("-i"),
-                String::from("to"),
-                String::from("poem.txt"),
-            ]
-            .into_iter(),
+    fn a_line_matches_if_any_query_does() {
+        let config = Config::build(
+            ["minigrep", "frog", "Dreary", "poem.txt"]
+                .into_iter()
+                .map(String::from),
         )
         .unwrap();
+        assert_eq!(config.search.queries, ["frog", "Dreary"]);
+        assert_eq!(config.input.file_path, Path::new("poem.txt"));
+        assert_eq!(
+            output_of(&["-o", "frog", "public", "poem.txt"]),
+            "public\nfrog\n"
+        );
+        assert_eq!(
+            output_of(&["replace", "frog", "public", "X", "poem.txt"]),
+            "How X, like a X\n"
+        );
+
+        let queries = ["ab", "abc", "c"].map(String::from);
+        let matcher = Matcher::any(&queries, false, Engine::Literal).unwrap();
+        // Overlapping matches go to the leftmost, then the longest.
+        assert_eq!(matcher.find_all("abc cab"), [0..3, 4..5, 5..7]);
+        assert_eq!(matcher.find("xabc"), Some(1..4));
+        let queries = ["to", "("].map(String::from);
+        let e = Config::build(
+            ["minigrep", "--engine", "regex", "to", "(", "poem.txt"]
+                .into_iter()
+                .map(String::from),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(e.to_string(), "unclosed group in the regex\n    (\n    ^");
+        assert!(Matcher::any(&queries, false, Engine::Regex).is_err());
+    }
+
+    #[test]
+    fn only_a_group_can_be_printed() {
+        let regex = ["--engine", "regex"];
+        let with = |args: &[&'static str]| [&regex[..], args].concat();
+        assert_eq!(
+            output_of(&with(&["--group", "1", r"(\w+), (too|like)", "poem.txt"])),
+            "nobody\npublic\n"
+        );
+        assert_eq!(
+            output_of(&with(&[
+                "--group",
+                "word",
+                r"an? (?P<word>\w+)",
+                "poem.txt"
+            ])),
+            "pair\nfrog\nadmiring\n"
+        );
+        // Matches the group takes no part in print nothing.
+        assert_eq!(
+            output_of(&with(&["--group", "2", "(frog)|(bog)", "poem.txt"])),
+            "bog\n"
+        );
+        let error = |args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            Config::build(args).err().map(|e| e.to_string())
+        };
+        assert_eq!(
+            error(&["--group", "1", "frog", "poem.txt"]).as_deref(),
+            Some("--group needs a regex, like --engine regex")
+        );
+        assert_eq!(
+            error(&with(&["--group", "2", "(frog)", "poem.txt"])).as_deref(),
+            Some("--group names a group the regex doesn't have")
+        );
+        assert_eq!(
+            error(&with(&["--group", "a", "(?<a>x)(?<a>y)", "poem.txt"])).as_deref(),
+            Some("duplicate group name in the regex\n    (?<a>x)(?<a>y)\n           ^")
+        );
+    }
+
+    #[test]
+    fn glob_patterns_match_line_content() {
+        assert_eq!(
+            output_of(&["--glob-pattern", "-n", "How * a frog", "poem.txt"]),
+            "7:How public, like a frog\n"
+        );
+        assert_eq!(
+            output_of(&["--glob-pattern", "-n", "T? ?n", "poem.txt"]),
+            "9:To an admiring bog!\n"
+        );
+        assert_eq!(
+            output_of(&["--glob-pattern", "-n", "-i", "NOBODY?", "poem.txt"]),
+            "1:I'm nobody! Who are you?\n2:Are you nobody, too?\n"
+        );
+        // Anything but a wildcard is taken literally, unlike a regex.
+        assert_eq!(
+            output_of(&["--glob-pattern", "-c", "o.y", "poem.txt"]),
+            "0\n"
+        );
+        assert_eq!(
+            output_of(&["--engine", "regex", "-c", "o.y", "poem.txt"]),
+            "3\n"
+        );
+    }
+
+    #[test]
+    fn engines_interpret_the_query() {
+        let found = |query: &str, engine: Engine, line: &str| {
+            Matcher::build(query, false, engine).unwrap().is_match(line)
+        };
+        assert!(found("a.c", Engine::Literal, "a.c"));
+        assert!(!found("a.c", Engine::Literal, "abc"));
+        assert!(found("a.c", Engine::Regex, "abc"));
+        assert!(found("a.c", Engine::Auto, "abc"));
+        assert!(found("abc", Engine::Auto, "xabcx"));
+        assert_eq!(Engine::Auto.resolve("plain words"), Engine::Literal);
+        assert!(found(
+            "error: * not found",
+            Engine::Glob,
+            "error: file x not found!"
+        ));
+        assert!(found("v?.[0-9]", Engine::Glob, "at v1.5"));
+        assert!(!found("v?.[0-9]", Engine::Glob, "at v1x5"));
+        assert!(found("[!a]", Engine::Glob, "b"));
+        assert!(found("(a+)", Engine::Glob, "f(a+)"));
+        assert!(found(r"what\?", Engine::Glob, "what?"));
+        assert!(!found(r"what\?", Engine::Glob, "whats"));
+        assert_eq!(
+            output_of(&["--glob-pattern", "-o", r"you * too\?", "poem.txt"]),
+            "you nobody, too?\n"
+        );
+
+        assert_eq!(
+            output_of(&["--engine", "regex", "-c", "^.o", "poem.txt"]),
+            "4\n"
+        );
+        let invalid = Config::build(
+            ["minigrep", "--engine", "regex", "(to", "poem.txt"]
+                .into_iter()
+                .map(String::from),
+        );
+        assert_eq!(
+            invalid.err().map(|e| e.to_string()).as_deref(),
+            Some("unclosed group in the regex\n    (to\n    ^")
+        );
+    }
+
+    #[test]
+    fn optional_engines_need_their_features() {
+        let build = |args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            Config::build(args).err().map(|e| e.to_string())
+        };
+        let args = ["--pcre2", "-c", r"(\w)\1|o(?= )", "poem.txt"];
+        if cfg!(feature = "pcre2") {
+            assert_eq!(output_of(&args), "6\n");
+        } else {
+            assert_eq!(
+                build(&args).as_deref(),
+                Some("--pcre2 needs minigrep built with the pcre2 feature")
+            );
+        }
+
+        let args = ["--engine", "hyperscan", "-o", "-i", "fro?g|bog", "poem.txt"];
+        if cfg!(feature = "hyperscan") {
+            assert_eq!(output_of(&args), "frog\nbog\n");
+        } else {
+            assert_eq!(
+                build(&args).as_deref(),
+                Some("--engine hyperscan needs minigrep built with the hyperscan feature")
+            );
+        }
+    }
 
-        assert!(result.ignore_case)
+    #[test]
+    fn bad_queries_are_reported_up_front() {
+        let error = |args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            Config::build(args).err().map(|e| e.to_string())
+        };
+        assert_eq!(
+            error(&["", "poem.txt"]).as_deref(),
+            Some("the query is empty, and would match every line")
+        );
+        assert_eq!(
+            error(&["--engine", "regex", "frö[g", "poem.txt"]).as_deref(),
+            Some("unclosed character class in the regex\n    frö[g\n       ^")
+        );
+        // A glob's problems are found in the regex it's turned into.
+        assert_eq!(
+            error(&["--glob-pattern", "[z-a]", "poem.txt"]).as_deref(),
+            Some("class range out of order in the regex")
+        );
     }
 
     // In the real world, test for and handle args.len() > 3 && args[1] != "-i"
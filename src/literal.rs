@@ -0,0 +1,117 @@
+//! Searching for a query as plain text, with or without regard to case.
+//!
+//! This is the part of matching that needs nothing from the operating
+//! system, so it only uses `core` and `alloc`: it can be copied into a
+//! `#![no_std]` crate, for embedded tools or WebAssembly, as it is.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::iter;
+use core::ops::Range;
+
+/// A query searched for as plain text.
+#[derive(Clone, Debug)]
+pub struct Literal {
+    /// The query, lowercased when ignoring case.
+    query: String,
+    ignore_case: bool,
+}
+
+impl Literal {
+    pub fn new(query: &str, ignore_case: bool) -> Literal {
+        let query = match ignore_case {
+            true => query.to_lowercase(),
+            false => query.to_string(),
+        };
+        Literal { query, ignore_case }
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        match self.ignore_case {
+            true => line.to_lowercase().contains(&self.query),
+            false => line.contains(&self.query),
+        }
+    }
+
+    /// The byte range of the first match in `line`. An empty query matches
+    /// at the start.
+    pub fn find(&self, line: &str) -> Option<Range<usize>> {
+        if self.query.is_empty() {
+            return Some(0..0);
+        }
+        if !self.ignore_case {
+            let start = line.find(&self.query)?;
+            return Some(start..start + self.query.len());
+        }
+        self.find_all(line, false).into_iter().next()
+    }
+
+    /// Every match in `line`, in order, with those overlapping the one
+    /// before them too if `overlapping` is set. An empty query has none.
+    pub fn find_all(&self, line: &str, overlapping: bool) -> Vec<Range<usize>> {
+        if self.query.is_empty() {
+            return Vec::new();
+        }
+        if !self.ignore_case {
+            return starts(line, &self.query, overlapping)
+                .into_iter()
+                .map(|start| start..start + self.query.len())
+                .collect();
+        }
+
+        // Lowercasing can change a character's length, so remember where
+        // each lowercased byte came from in the original line.
+        let mut lowered = String::with_capacity(line.len());
+        let mut origins = Vec::with_capacity(line.len());
+        for (i, c) in line.char_indices() {
+            for lower in c.to_lowercase() {
+                lowered.push(lower);
+                origins.extend(iter::repeat_n(i, lower.len_utf8()));
+            }
+        }
+
+        let mut spans: Vec<Range<usize>> = starts(&lowered, &self.query, overlapping)
+            .into_iter()
+            .map(|start| {
+                let last = origins[start + self.query.len() - 1];
+                let end = last + line[last..].chars().next().map_or(0, char::len_utf8);
+                origins[start]..end
+            })
+            .collect();
+        // Starting inside what one character lowercased to lands on the
+        // same character.
+        spans.dedup();
+        spans
+    }
+}
+
+/// Where `needle` starts in `haystack`: each place that doesn't overlap
+/// the one before, or every place with `overlapping`.
+fn starts(haystack: &str, needle: &str, overlapping: bool) -> Vec<usize> {
+    if !overlapping {
+        return haystack
+            .match_indices(needle)
+            .map(|(start, _)| start)
+            .collect();
+    }
+    let mut starts = Vec::new();
+    let mut from = 0;
+    while let Some(i) = haystack[from..].find(needle) {
+        let start = from + i;
+        starts.push(start);
+        from = next_start(haystack, &(start..start));
+        if from > haystack.len() {
+            break;
+        }
+    }
+    starts
+}
+
+/// Where to look for the next match after `span`, stepping over a
+/// character if it's empty so the search moves on.
+pub(crate) fn next_start(line: &str, span: &Range<usize>) -> usize {
+    match span.is_empty() {
+        true => span.end + line[span.end..].chars().next().map_or(1, char::len_utf8),
+        false => span.end,
+    }
+}
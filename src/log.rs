@@ -0,0 +1,64 @@
+//! Diagnostic messages on stderr for `-v` and `--debug`.
+
+use std::fmt;
+use std::time::Duration;
+
+/// How much minigrep says about what it's doing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    #[default]
+    Quiet,
+    /// Which files are opened and skipped.
+    Verbose,
+    /// Everything `Verbose` says, plus how long each file and phase took.
+    Debug,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct Log {
+    verbosity: Verbosity,
+}
+
+impl Log {
+    pub(crate) fn new(verbosity: Verbosity) -> Log {
+        Log { verbosity }
+    }
+
+    pub(crate) fn is_quiet(&self) -> bool {
+        self.verbosity == Verbosity::Quiet
+    }
+
+    pub(crate) fn verbose(&self, message: fmt::Arguments) {
+        if self.verbosity >= Verbosity::Verbose {
+            eprintln!("minigrep: {message}");
+        }
+    }
+
+    pub(crate) fn debug(&self, message: fmt::Arguments) {
+        if self.verbosity >= Verbosity::Debug {
+            eprintln!("minigrep: {message}");
+        }
+    }
+}
+
+/// Time spent in each phase of a search.
+///
+/// Files that are streamed (`--max-memory` and the offset flags) are read and
+/// matched in a single pass, so all of that time counts as matching.
+#[derive(Default)]
+pub(crate) struct Timings {
+    pub(crate) walking: Duration,
+    pub(crate) reading: Duration,
+    pub(crate) matching: Duration,
+    pub(crate) printing: Duration,
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "walking {:?}, reading {:?}, matching {:?}, printing {:?}",
+            self.walking, self.reading, self.matching, self.printing
+        )
+    }
+}
@@ -1,16 +1,64 @@
 use std::env;
+use std::ffi::OsString;
+use std::io::{self, Write};
 use std::process;
 
-use minigrep::Config;
+use minigrep::{CancellationToken, Config, ErrorFormat, Lang};
+
+/// Exit status used when nothing matches, as with grep.
+const EXIT_NO_MATCH: i32 = 1;
+/// Exit status used for bad arguments and failed searches, as with grep.
+const EXIT_ERROR: i32 = 2;
+/// Exit status used when `--timeout` cuts the search short.
+const EXIT_TIMED_OUT: i32 = 3;
 
 fn main() {
-    let config = Config::build(env::args()).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {err}");
-        process::exit(1);
+    let args: Vec<OsString> = env::args_os().collect();
+    let config = Config::build_with_prompt(args.iter().cloned()).unwrap_or_else(|err| {
+        if ErrorFormat::from_args(&args) == ErrorFormat::Json {
+            eprintln!(
+                "{}",
+                minigrep::error_json(err.as_ref(), None, "bad-arguments")
+            );
+            process::exit(EXIT_ERROR);
+        }
+        let lang = Lang::from_args(&args);
+        let problem = lang.translate("Problem parsing arguments");
+        eprintln!("{problem}: {}", lang.describe(err.as_ref()));
+        process::exit(EXIT_ERROR);
+    });
+    let (lang, error_format) = (config.lang, config.error_format);
+    let path = config.input.file_path.display().to_string();
+
+    let token = CancellationToken::new();
+    let timeout = config.timeout;
+    if let Some(timeout) = timeout {
+        token.cancel_after(timeout);
+    }
+
+    let result = match config.output.quiet {
+        true => minigrep::any_match(&config, &token),
+        false => minigrep::run_with_cancel(config, &token),
+    };
+    let matched = result.unwrap_or_else(|e| {
+        if error_format == ErrorFormat::Json {
+            // Failing to open or read the input is an error about its path.
+            let path = e.is::<io::Error>().then_some(path.as_str());
+            eprintln!("{}", minigrep::error_json(e.as_ref(), path, "error"));
+            process::exit(EXIT_ERROR);
+        }
+        let problem = lang.translate("Application error");
+        eprintln!("{problem}: {}", lang.describe(e.as_ref()));
+        process::exit(EXIT_ERROR);
     });
 
-    if let Err(e) = minigrep::run(config) {
-        eprintln!("Application error: {e}");
-        process::exit(1);
+    if let (Some(timeout), true) = (timeout, token.cut_short()) {
+        let _ = io::stdout().flush();
+        let seconds = timeout.as_secs_f64();
+        eprintln!("{}", lang.format("Search timed out after {}s", seconds));
+        process::exit(EXIT_TIMED_OUT);
+    }
+    if !matched {
+        process::exit(EXIT_NO_MATCH);
     }
 }
@@ -0,0 +1,567 @@
+//! Deciding whether a line matches the query.
+
+use std::ops::Range;
+
+#[cfg(feature = "hyperscan")]
+use crate::hyperscan::{self, Hyperscan};
+use crate::literal::{self, Literal};
+#[cfg(feature = "pcre2")]
+use crate::pcre2::Pcre2;
+use crate::regex::{self, Regex};
+
+/// How the query is interpreted, chosen with `--engine`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// The query is plain text.
+    #[default]
+    Literal,
+    /// The query is a regular expression.
+    Regex,
+    /// `*` and `?` in the query are shell-style wildcards, and `[...]` a
+    /// set of characters; `\\` makes the next character plain.
+    Glob,
+    /// A regular expression if the query has any of its metacharacters,
+    /// plain text otherwise.
+    Auto,
+    /// The query is a PCRE2 pattern, which needs minigrep built with the
+    /// `pcre2` feature.
+    Pcre2,
+    /// The query is a pattern for Hyperscan, which needs minigrep built with
+    /// the `hyperscan` feature.
+    Hyperscan,
+}
+
+impl Engine {
+    pub(crate) fn parse(name: &str) -> Option<Engine> {
+        match name {
+            "literal" => Some(Engine::Literal),
+            "regex" => Some(Engine::Regex),
+            "glob" => Some(Engine::Glob),
+            "auto" => Some(Engine::Auto),
+            "pcre2" => Some(Engine::Pcre2),
+            "hyperscan" => Some(Engine::Hyperscan),
+            _ => None,
+        }
+    }
+
+    /// The name `--engine` knows this engine by.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Engine::Literal => "literal",
+            Engine::Regex => "regex",
+            Engine::Glob => "glob",
+            Engine::Auto => "auto",
+            Engine::Pcre2 => "pcre2",
+            Engine::Hyperscan => "hyperscan",
+        }
+    }
+
+    /// The engine that will actually be used for `query`, which is only
+    /// different for [`Engine::Auto`].
+    pub(crate) fn resolve(self, query: &str) -> Engine {
+        match self {
+            Engine::Auto if query.contains(REGEX_META) => Engine::Regex,
+            Engine::Auto => Engine::Literal,
+            engine => engine,
+        }
+    }
+}
+
+/// A capture group of a regex query, chosen with `--group`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Group {
+    /// The group by its number, counting the whole match as group 0.
+    Number(usize),
+    /// The group written `(?P<name>...)` or `(?<name>...)`.
+    Name(String),
+}
+
+impl Group {
+    pub(crate) fn parse(group: &str) -> Group {
+        match group.parse() {
+            Ok(number) => Group::Number(number),
+            Err(_) => Group::Name(group.to_string()),
+        }
+    }
+
+    /// The number of this group in `regex`, if it has it.
+    fn index(&self, regex: &Regex) -> Option<usize> {
+        match self {
+            Group::Number(number) => Some(*number).filter(|&number| number < regex.groups()),
+            Group::Name(name) => regex.group_named(name),
+        }
+    }
+}
+
+/// The characters with a meaning of their own in a regular expression.
+const REGEX_META: &[char] = &[
+    '\\', '.', '+', '*', '?', '(', ')', '|', '[', ']', '{', '}', '^', '$',
+];
+
+/// Queries prepared once so they can be checked against many lines. A line
+/// matches if any of them does.
+pub(crate) struct Matcher {
+    queries: Vec<Query>,
+    /// The Hyperscan queries, compiled into one database.
+    #[cfg(feature = "hyperscan")]
+    hyperscan: Option<Hyperscan>,
+}
+
+/// A line being matched. With Hyperscan queries it carries what one scan of
+/// the database found, so the line is scanned once for all of them.
+struct Line<'a> {
+    text: &'a str,
+    #[cfg(feature = "hyperscan")]
+    matches: Vec<hyperscan::Match>,
+}
+
+/// One query, as a [`Matcher`] holds it.
+struct Query {
+    /// The query as it was given.
+    text: String,
+    /// The engine it's searched with, never [`Engine::Auto`].
+    engine: Engine,
+    ignore_case: bool,
+    /// The query as plain text, for a literal search.
+    literal: Literal,
+    pattern: Option<Pattern>,
+}
+
+/// How one match was found, for `--explain`.
+#[derive(Debug)]
+pub(crate) struct Explanation<'a> {
+    /// Which of the queries found it, counting from 0.
+    pub(crate) index: usize,
+    pub(crate) query: &'a str,
+    pub(crate) engine: Engine,
+    pub(crate) ignore_case: bool,
+    pub(crate) span: Range<usize>,
+}
+
+/// A query that isn't searched for as plain text.
+enum Pattern {
+    Regex(Regex),
+    #[cfg(feature = "pcre2")]
+    Pcre2(Pcre2),
+    /// The query's place in the [`Matcher`]'s Hyperscan database.
+    #[cfg(feature = "hyperscan")]
+    Hyperscan(usize),
+}
+
+impl Pattern {
+    fn find_at(&self, line: &Line, start: usize) -> Option<Range<usize>> {
+        match self {
+            Pattern::Regex(regex) => regex.find_at(line.text, start),
+            #[cfg(feature = "pcre2")]
+            Pattern::Pcre2(pcre2) => pcre2.find_at(line.text, start),
+            #[cfg(feature = "hyperscan")]
+            Pattern::Hyperscan(id) => hyperscan::find_at(&line.matches, *id, start),
+        }
+    }
+}
+
+impl Matcher {
+    /// Prepares a literal search for `query`.
+    pub(crate) fn new(query: &str, ignore_case: bool) -> Matcher {
+        Matcher {
+            queries: vec![Query::new(query, ignore_case)],
+            #[cfg(feature = "hyperscan")]
+            hyperscan: None,
+        }
+    }
+
+    /// Prepares a search for `query` as `engine` interprets it.
+    pub(crate) fn build(
+        query: &str,
+        ignore_case: bool,
+        engine: Engine,
+    ) -> Result<Matcher, regex::Error> {
+        Matcher::any(&[query.to_string()], ignore_case, engine)
+    }
+
+    /// Prepares a search for lines matching any of `queries`, each as
+    /// `engine` interprets it.
+    pub(crate) fn any(
+        queries: &[String],
+        ignore_case: bool,
+        engine: Engine,
+    ) -> Result<Matcher, regex::Error> {
+        let queries = queries
+            .iter()
+            .map(|query| Query::build(query, ignore_case, engine))
+            .collect::<Result<_, _>>()?;
+        #[cfg(feature = "hyperscan")]
+        let (queries, hyperscan) = compile_hyperscan(queries, ignore_case)?;
+        Ok(Matcher {
+            queries,
+            #[cfg(feature = "hyperscan")]
+            hyperscan,
+        })
+    }
+
+    /// Like [`Matcher::build`], but searching for a query that isn't a valid
+    /// pattern as plain text, for the interactive modes where it may still
+    /// be being typed.
+    pub(crate) fn lenient(query: &str, ignore_case: bool, engine: Engine) -> Matcher {
+        Matcher::build(query, ignore_case, engine)
+            .unwrap_or_else(|_| Matcher::new(query, ignore_case))
+    }
+
+    /// How many queries there are.
+    pub(crate) fn query_count(&self) -> usize {
+        self.queries.len()
+    }
+
+    /// Prepares `text` to be matched against each of the queries.
+    fn line<'a>(&self, text: &'a str) -> Line<'a> {
+        Line {
+            text,
+            #[cfg(feature = "hyperscan")]
+            matches: self
+                .hyperscan
+                .as_ref()
+                .map_or_else(Vec::new, |hyperscan| hyperscan.scan(text)),
+        }
+    }
+
+    pub(crate) fn is_match(&self, line: &str) -> bool {
+        let line = self.line(line);
+        self.queries.iter().any(|query| query.is_match(&line))
+    }
+
+    /// Returns the byte range of the first match in `line`: the leftmost,
+    /// and the longest of any that start there.
+    pub(crate) fn find(&self, line: &str) -> Option<Range<usize>> {
+        let line = self.line(line);
+        self.queries
+            .iter()
+            .filter_map(|query| query.find(&line))
+            .min_by_key(|span| (span.start, usize::MAX - span.end))
+    }
+
+    /// Returns the byte ranges of every non-overlapping match in `line`, in
+    /// order. An empty query matches every line but has no occurrences, and
+    /// neither do the empty matches a regex can make. Where different
+    /// queries' matches overlap, the leftmost and then longest is kept.
+    pub(crate) fn find_all(&self, line: &str) -> Vec<Range<usize>> {
+        if let [query] = &self.queries[..] {
+            return query.find_all(&self.line(line), false);
+        }
+        self.find_all_by_query(line)
+            .into_iter()
+            .map(|(_, span)| span)
+            .collect()
+    }
+
+    /// Like [`Matcher::find_all`], but with each match the index of the
+    /// query that found it. Where queries find the same match, the first of
+    /// them is given.
+    pub(crate) fn find_all_by_query(&self, line: &str) -> Vec<(usize, Range<usize>)> {
+        let line = self.line(line);
+        let mut spans: Vec<(usize, Range<usize>)> = self
+            .queries
+            .iter()
+            .enumerate()
+            .flat_map(|(index, query)| {
+                query
+                    .find_all(&line, false)
+                    .into_iter()
+                    .map(move |span| (index, span))
+            })
+            .collect();
+        spans.sort_by_key(|(index, span)| (span.start, usize::MAX - span.end, *index));
+        let mut end = 0;
+        spans.retain(|(_, span)| {
+            let kept = span.start >= end;
+            if kept {
+                end = span.end;
+            }
+            kept
+        });
+        spans
+    }
+
+    /// Like [`Matcher::find_all`], but keeping matches that overlap, for
+    /// `--overlapping`: `aa` is found twice in `aaa`.
+    pub(crate) fn find_overlapping(&self, line: &str) -> Vec<Range<usize>> {
+        let line = self.line(line);
+        let mut spans: Vec<Range<usize>> = self
+            .queries
+            .iter()
+            .flat_map(|query| query.find_all(&line, true))
+            .collect();
+        spans.sort_by_key(|span| (span.start, usize::MAX - span.end));
+        spans.dedup();
+        spans
+    }
+
+    /// Whether every query is a regex with `group` in it.
+    pub(crate) fn has_group(&self, group: &Group) -> bool {
+        self.queries
+            .iter()
+            .all(|query| query.group_index(group).is_some())
+    }
+
+    /// Like [`Matcher::find_all`], but the byte ranges of what `group`
+    /// captured in each match rather than of the whole match. Matches
+    /// where the group took no part, or captured nothing, are left out.
+    pub(crate) fn group_spans(&self, line: &str, group: &Group) -> Vec<Range<usize>> {
+        let spans = self
+            .queries
+            .iter()
+            .flat_map(|query| query.group_spans(line, group))
+            .collect();
+        merge(spans)
+    }
+
+    /// Every match in `line` and the query that found it, in order, with
+    /// those overlapping another too if `overlapping` is set. Unlike
+    /// [`Matcher::find_all`], matches of different queries in the same
+    /// place are all kept.
+    pub(crate) fn explain(&self, line: &str, overlapping: bool) -> Vec<Explanation<'_>> {
+        let line = self.line(line);
+        let mut explanations: Vec<Explanation> = self
+            .queries
+            .iter()
+            .enumerate()
+            .flat_map(|(index, query)| {
+                query
+                    .find_all(&line, overlapping)
+                    .into_iter()
+                    .map(move |span| Explanation {
+                        index,
+                        query: &query.text,
+                        engine: query.engine,
+                        ignore_case: query.ignore_case,
+                        span,
+                    })
+            })
+            .collect();
+        explanations.sort_by_key(|explanation| {
+            (
+                explanation.span.start,
+                usize::MAX - explanation.span.end,
+                explanation.index,
+            )
+        });
+        explanations
+    }
+}
+
+/// Compiles the Hyperscan queries among `queries` into one database, and
+/// numbers each by its place in it.
+#[cfg(feature = "hyperscan")]
+fn compile_hyperscan(
+    mut queries: Vec<Query>,
+    ignore_case: bool,
+) -> Result<(Vec<Query>, Option<Hyperscan>), regex::Error> {
+    let mut patterns = Vec::new();
+    for query in &mut queries {
+        if let Some(Pattern::Hyperscan(id)) = &mut query.pattern {
+            *id = patterns.len();
+            patterns.push(query.text.clone());
+        }
+    }
+    if patterns.is_empty() {
+        return Ok((queries, None));
+    }
+    let patterns: Vec<&str> = patterns.iter().map(String::as_str).collect();
+    let hyperscan = Hyperscan::new(&patterns, ignore_case)?;
+    Ok((queries, Some(hyperscan)))
+}
+
+/// Sorts `spans` and drops any that overlap one before them, keeping the
+/// leftmost and then longest.
+fn merge(mut spans: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    spans.sort_by_key(|span| (span.start, usize::MAX - span.end));
+    let mut end = 0;
+    spans.retain(|span| {
+        let kept = span.start >= end;
+        if kept {
+            end = span.end;
+        }
+        kept
+    });
+    spans
+}
+
+impl Query {
+    fn new(query: &str, ignore_case: bool) -> Query {
+        Query {
+            text: query.to_string(),
+            engine: Engine::Literal,
+            ignore_case,
+            literal: Literal::new(query, ignore_case),
+            pattern: None,
+        }
+    }
+
+    fn build(query: &str, ignore_case: bool, engine: Engine) -> Result<Query, regex::Error> {
+        let engine = engine.resolve(query);
+        let pattern = match engine {
+            Engine::Regex => Pattern::Regex(Regex::new(query, ignore_case)?),
+            // Where the problem is in the translated pattern says nothing
+            // about where it is in the glob.
+            Engine::Glob => {
+                Pattern::Regex(Regex::new(&glob_to_regex(query), ignore_case).map_err(|e| {
+                    regex::Error {
+                        position: None,
+                        ..e
+                    }
+                })?)
+            }
+            #[cfg(feature = "pcre2")]
+            Engine::Pcre2 => Pattern::Pcre2(Pcre2::new(query, ignore_case)?),
+            #[cfg(not(feature = "pcre2"))]
+            Engine::Pcre2 => {
+                return Err(regex::Error {
+                    message: "--pcre2 needs minigrep built with the pcre2 feature",
+                    position: None,
+                });
+            }
+            #[cfg(feature = "hyperscan")]
+            // Numbered once all the queries are compiled together.
+            Engine::Hyperscan => Pattern::Hyperscan(0),
+            #[cfg(not(feature = "hyperscan"))]
+            Engine::Hyperscan => {
+                return Err(regex::Error {
+                    message: "--engine hyperscan needs minigrep built with the hyperscan feature",
+                    position: None,
+                });
+            }
+            _ => return Ok(Query::new(query, ignore_case)),
+        };
+        Ok(Query {
+            text: query.to_string(),
+            engine,
+            ignore_case,
+            literal: Literal::new(query, ignore_case),
+            pattern: Some(pattern),
+        })
+    }
+
+    fn is_match(&self, line: &Line) -> bool {
+        if let Some(pattern) = &self.pattern {
+            return pattern.find_at(line, 0).is_some();
+        }
+        self.literal.is_match(line.text)
+    }
+
+    fn find(&self, line: &Line) -> Option<Range<usize>> {
+        if let Some(pattern) = &self.pattern {
+            return pattern.find_at(line, 0);
+        }
+        self.literal.find(line.text)
+    }
+
+    /// Every match in `line`, in order, with those overlapping the one
+    /// before them too if `overlapping` is set.
+    fn find_all(&self, line: &Line, overlapping: bool) -> Vec<Range<usize>> {
+        if let Some(pattern) = &self.pattern {
+            let mut spans = Vec::new();
+            let mut start = 0;
+            while let Some(span) = pattern.find_at(line, start) {
+                start = match overlapping {
+                    true => literal::next_start(line.text, &(span.start..span.start)),
+                    false => literal::next_start(line.text, &span),
+                };
+                if !span.is_empty() {
+                    spans.push(span);
+                }
+                if start > line.text.len() {
+                    break;
+                }
+            }
+            return spans;
+        }
+        self.literal.find_all(line.text, overlapping)
+    }
+
+    fn group_index(&self, group: &Group) -> Option<usize> {
+        match &self.pattern {
+            Some(Pattern::Regex(regex)) => group.index(regex),
+            _ => None,
+        }
+    }
+
+    fn group_spans(&self, line: &str, group: &Group) -> Vec<Range<usize>> {
+        let (Some(Pattern::Regex(regex)), Some(index)) = (&self.pattern, self.group_index(group))
+        else {
+            return Vec::new();
+        };
+        let mut spans = Vec::new();
+        let mut start = 0;
+        while start <= line.len() {
+            let Some(groups) = regex.captures_at(line, start) else {
+                break;
+            };
+            let Some(span) = groups[0].clone() else {
+                break;
+            };
+            start = literal::next_start(line, &span);
+            if let Some(captured) = groups[index]
+                .clone()
+                .filter(|captured| !captured.is_empty())
+            {
+                spans.push(captured);
+            }
+        }
+        spans
+    }
+}
+
+/// The regular expression a glob query stands for: anywhere in the line,
+/// `*` matching any run of characters, `?` any one, and `[...]` (or
+/// `[!...]`) a set, and a backslash taking the character after it literally.
+pub(crate) fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::with_capacity(glob.len() * 2);
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '\\' if chars.peek().is_some() => {
+                let c = chars.next().unwrap_or(c);
+                push_literal(&mut pattern, c);
+            }
+            '[' if glob_class_closes(chars.clone()) => {
+                pattern.push('[');
+                if chars.next_if(|&c| c == '!' || c == '^').is_some() {
+                    pattern.push('^');
+                }
+                // A `]` first in the set is one of its members.
+                if chars.next_if_eq(&']').is_some() {
+                    pattern.push_str("\\]");
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        pattern.push('\\');
+                    }
+                    pattern.push(c);
+                }
+                pattern.push(']');
+            }
+            c => push_literal(&mut pattern, c),
+        }
+    }
+    pattern
+}
+
+fn push_literal(pattern: &mut String, c: char) {
+    if c.is_ascii_punctuation() {
+        pattern.push('\\');
+    }
+    pattern.push(c);
+}
+
+/// Whether the `[` just read starts a set, rather than standing for itself.
+fn glob_class_closes(mut rest: impl Iterator<Item = char>) -> bool {
+    let mut first = rest.next();
+    if matches!(first, Some('!' | '^')) {
+        first = rest.next();
+    }
+    first.is_some() && rest.any(|c| c == ']')
+}
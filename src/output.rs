@@ -0,0 +1,1104 @@
+//! Formatting matched lines for display.
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::iter;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+use crate::events::{ContextLine, Emit, Event};
+use crate::matcher::Matcher;
+use crate::tui;
+use crate::{ColorChoice, Config};
+
+/// Shown in place of the text cut from either side of a long line.
+const ELISION: &str = "[...]";
+
+const RESET: &str = "\x1b[0m";
+
+/// The colours the matches of each query after the first are given in
+/// turn, so several queries can be told apart: yellow, blue, cyan, green
+/// and magenta.
+const QUERY_COLORS: [u8; 5] = [3, 4, 6, 2, 5];
+
+/// How each part of the output is coloured.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Theme {
+    path: Style,
+    line: Style,
+    matched: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            path: Style {
+                fg: Some(Color::Basic(5)),
+                ..Style::default()
+            },
+            line: Style {
+                fg: Some(Color::Basic(2)),
+                ..Style::default()
+            },
+            matched: Style {
+                fg: Some(Color::Basic(1)),
+                bold: true,
+                ..Style::default()
+            },
+        }
+    }
+}
+
+impl Theme {
+    /// Applies a `TYPE:ATTRIBUTE:VALUE` spec such as `match:fg:yellow`, or
+    /// `TYPE:none` to leave that part uncoloured.
+    ///
+    /// Types are `path`, `line` and `match`. Attributes are `fg` and `bg`,
+    /// which take a colour name, a 0-255 palette index or `R,G,B`, and
+    /// `style`, which takes `bold`, `nobold`, `underline` or `nounderline`.
+    pub fn apply(&mut self, spec: &str) -> Result<(), &'static str> {
+        let mut parts = spec.splitn(3, ':');
+        let style = match parts.next() {
+            Some("path") => &mut self.path,
+            Some("line") => &mut self.line,
+            Some("match") => &mut self.matched,
+            _ => return Err("--colors type must be one of: path, line, match"),
+        };
+        match (parts.next(), parts.next()) {
+            (Some("none"), None) => *style = Style::default(),
+            (Some("fg"), Some(color)) => style.fg = Some(Color::parse(color)?),
+            (Some("bg"), Some(color)) => style.bg = Some(Color::parse(color)?),
+            (Some("style"), Some("bold")) => style.bold = true,
+            (Some("style"), Some("nobold")) => style.bold = false,
+            (Some("style"), Some("underline")) => style.underline = true,
+            (Some("style"), Some("nounderline")) => style.underline = false,
+            _ => return Err("--colors needs a spec like match:fg:yellow or path:none"),
+        }
+        Ok(())
+    }
+
+    /// The escape sequence that colours a match.
+    pub(crate) fn match_escape(&self) -> String {
+        self.matched.escape()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Style {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    bold: bool,
+    underline: bool,
+}
+
+impl Style {
+    /// The SGR escape sequence that turns this style on, or an empty string
+    /// for the plain style.
+    fn escape(&self) -> String {
+        let mut codes = Vec::new();
+        if self.bold {
+            codes.push("1".to_string());
+        }
+        if self.underline {
+            codes.push("4".to_string());
+        }
+        if let Some(fg) = self.fg {
+            codes.push(fg.code(30));
+        }
+        if let Some(bg) = self.bg {
+            codes.push(bg.code(40));
+        }
+        if codes.is_empty() {
+            String::new()
+        } else {
+            format!("\x1b[{}m", codes.join(";"))
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Color {
+    /// One of the eight standard terminal colours.
+    Basic(u8),
+    Ansi256(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn parse(color: &str) -> Result<Color, &'static str> {
+        const NAMES: [&str; 8] = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ];
+        if let Some(i) = NAMES.iter().position(|name| *name == color) {
+            return Ok(Color::Basic(i as u8));
+        }
+        if let Ok(i) = color.parse() {
+            return Ok(Color::Ansi256(i));
+        }
+        let rgb: Vec<u8> = color.split(',').filter_map(|c| c.parse().ok()).collect();
+        match rgb[..] {
+            [r, g, b] if color.split(',').count() == 3 => Ok(Color::Rgb(r, g, b)),
+            _ => Err("--colors needs a colour name, a number from 0 to 255 or R,G,B"),
+        }
+    }
+
+    /// The SGR parameters selecting this colour, where `base` is 30 for the
+    /// foreground and 40 for the background.
+    fn code(&self, base: u8) -> String {
+        match *self {
+            Color::Basic(i) => (base + i).to_string(),
+            Color::Ansi256(i) => format!("{};5;{i}", base + 8),
+            Color::Rgb(r, g, b) => format!("{};2;{r};{g};{b}", base + 8),
+        }
+    }
+}
+
+/// Prints the matching lines of one file the way the [`Config`] asks for.
+pub(crate) struct Printer<'a> {
+    config: &'a Config,
+    matcher: &'a Matcher,
+    /// The path shown before each line, if any.
+    path: Option<String>,
+    /// The file's absolute path, when paths should be printed as hyperlinks.
+    link_path: Option<String>,
+    /// The width to wrap lines at, when wrapping is on and stdout is a
+    /// terminal.
+    wrap_width: Option<usize>,
+    color: bool,
+    /// The escape sequences for each part of the output.
+    path_style: String,
+    line_style: String,
+    match_style: String,
+    /// With more than one query, the style of each one's matches, the first
+    /// being the theme's.
+    query_styles: Vec<String>,
+    /// Where context lines are reported instead of printed, and the path to
+    /// report them under.
+    events: Option<(Emit<'a>, &'a Path)>,
+}
+
+impl<'a> Printer<'a> {
+    /// Creates a printer for the file at `path`, which is shown before each
+    /// line when given.
+    pub(crate) fn new(
+        config: &'a Config,
+        matcher: &'a Matcher,
+        path: Option<&Path>,
+    ) -> Printer<'a> {
+        let terminal = io::stdout().is_terminal();
+        let link_path = path
+            .filter(|_| {
+                config.output.hyperlink_format.is_some() && terminal && supports_hyperlinks()
+            })
+            .and_then(|path| fs::canonicalize(path).ok())
+            .map(|path| path.display().to_string());
+        let color = cfg!(feature = "color")
+            && match config.output.color {
+                ColorChoice::Always => {
+                    // Colour regardless, but still give the Windows console the
+                    // chance to render it.
+                    escapes_supported();
+                    true
+                }
+                ColorChoice::Never => false,
+                ColorChoice::Auto => {
+                    terminal
+                        && (config.no_env || env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()))
+                        && escapes_supported()
+                }
+            };
+        Printer {
+            config,
+            matcher,
+            path: path.map(|path| path.display().to_string()),
+            link_path,
+            wrap_width: (config.output.wrap && terminal).then(|| terminal_width(config)),
+            color,
+            path_style: config.output.theme.path.escape(),
+            line_style: config.output.theme.line.escape(),
+            match_style: config.output.theme.matched.escape(),
+            query_styles: query_styles(&config.output.theme, matcher.query_count()),
+            events: None,
+        }
+    }
+
+    /// Makes context lines go to `events`, when given, under `path`.
+    pub(crate) fn reporting_to(mut self, events: Option<Emit<'a>>, path: &'a Path) -> Printer<'a> {
+        self.events = events.map(|emit| (emit, path));
+        self
+    }
+
+    /// Prints one matching line.
+    pub(crate) fn print_match(
+        &self,
+        out: &mut dyn Write,
+        line_number: usize,
+        line: &str,
+    ) -> io::Result<()> {
+        writeln!(out, "{}", self.format_match(line_number, line))
+    }
+
+    /// Prints how each match in `line` was found, a line for each under
+    /// the line itself, for `--explain`. The query is only named when
+    /// there's more than one.
+    pub(crate) fn print_explanation(&self, out: &mut dyn Write, line: &str) -> io::Result<()> {
+        let several = self.matcher.query_count() > 1;
+        for explanation in self.matcher.explain(line, self.config.search.overlapping) {
+            let span = &explanation.span;
+            let query = match several {
+                true => format!("query {} ", explanation.index + 1),
+                false => String::new(),
+            };
+            let case = match explanation.ignore_case {
+                true => "ignoring case",
+                false => "case-sensitive",
+            };
+            writeln!(
+                out,
+                "  explain: bytes {}..{} {:?} matched {query}{} {:?}, {case}",
+                span.start,
+                span.end,
+                &line[span.clone()],
+                explanation.engine.name(),
+                explanation.query,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Prints one match on its own, which starts at byte `column` of its
+    /// line counting from 1, for `-o` and `--vimgrep`. `text` is the match
+    /// alone, or the whole line.
+    pub(crate) fn print_match_at(
+        &self,
+        out: &mut dyn Write,
+        line_number: usize,
+        column: usize,
+        text: &str,
+    ) -> io::Result<()> {
+        let column = (self.config.output.column || self.config.output.porcelain).then_some(column);
+        writeln!(out, "{}", self.format_line(line_number, column, text, ':'))
+    }
+
+    /// Prints a line that didn't match, shown alongside the matches, with its
+    /// number set off by `-` rather than `:` so it can be told apart.
+    pub(crate) fn print_context(
+        &self,
+        out: &mut dyn Write,
+        line_number: usize,
+        line: &str,
+    ) -> io::Result<()> {
+        if let Some((emit, path)) = self.events {
+            emit(Event::ContextLine(ContextLine {
+                path,
+                line_number,
+                line,
+            }));
+            return Ok(());
+        }
+        writeln!(out, "{}", self.format_line(line_number, None, line, '-'))
+    }
+
+    /// Prints the line a function starts on above matches inside it, set
+    /// off by `=` as `git grep --show-function` does.
+    pub(crate) fn print_function(
+        &self,
+        out: &mut dyn Write,
+        line_number: usize,
+        line: &str,
+    ) -> io::Result<()> {
+        writeln!(out, "{}", self.format_line(line_number, None, line, '='))
+    }
+
+    /// Renders one matching line as [`Printer::print_match`] would print it,
+    /// without the line terminator.
+    pub(crate) fn format_match(&self, line_number: usize, line: &str) -> String {
+        let output = &self.config.output;
+        let column = (output.column || output.porcelain)
+            .then(|| self.matcher.find(line).map_or(1, |span| span.start + 1));
+        self.format_line(line_number, column, line, ':')
+    }
+
+    /// Renders a line for `--porcelain`, whose format stays the same from
+    /// one release to the next. Each line is a record of five fields
+    /// separated by tabs:
+    ///
+    /// 1. `match`, `context` or `function` (for `--show-function`);
+    /// 2. the path, or nothing when searching stdin;
+    /// 3. the line number;
+    /// 4. for a match, the byte it starts at counting from 1, and for any
+    ///    other line nothing;
+    /// 5. the line, or with `-o` the match, after any replacement.
+    ///
+    /// In the path and the text, `\`, tab, newline, carriage return and NUL
+    /// are written `\\`, `\t`, `\n`, `\r` and `\0`, so a record never spans
+    /// lines and splitting it on tabs always gives five fields. Nothing is
+    /// colored, truncated or wrapped, and there's no separator between
+    /// blocks of context.
+    fn format_porcelain(
+        &self,
+        line_number: usize,
+        column: Option<usize>,
+        line: &str,
+        separator: char,
+    ) -> String {
+        let kind = match separator {
+            ':' => "match",
+            '=' => "function",
+            _ => "context",
+        };
+        let path = self.path.as_deref().unwrap_or_default();
+        let column = column.map(|column| column.to_string()).unwrap_or_default();
+        let text = match &self.config.replacement {
+            Some(replacement) => substitute(
+                line,
+                self.matcher,
+                Some(replacement),
+                self.config.preserve_case,
+                "",
+            ),
+            None => line.to_string(),
+        };
+        format!(
+            "{kind}\t{}\t{line_number}\t{column}\t{}",
+            escape_porcelain(path),
+            escape_porcelain(&text)
+        )
+    }
+
+    fn format_line(
+        &self,
+        line_number: usize,
+        column: Option<usize>,
+        line: &str,
+        separator: char,
+    ) -> String {
+        if self.config.output.porcelain {
+            return self.format_porcelain(line_number, column, line, separator);
+        }
+        let mut text = String::new();
+        let mut prefix_width = 0;
+        if let Some(path) = &self.path {
+            let styled = self.paint(path, &self.path_style);
+            match (&self.link_path, &self.config.output.hyperlink_format) {
+                (Some(link_path), Some(format)) => {
+                    let column = self.matcher.find(line).map_or(1, |span| span.start + 1);
+                    let url = hyperlink_url(format, link_path, line_number, column);
+                    text.push_str(&format!("\x1b]8;;{url}\x1b\\{styled}\x1b]8;;\x1b\\"));
+                }
+                _ => text.push_str(&styled),
+            }
+            text.push(separator);
+            prefix_width += path.chars().count() + 1;
+        }
+        if self.config.output.line_number {
+            let number = line_number.to_string();
+            text.push_str(&self.paint(&number, &self.line_style));
+            text.push(separator);
+            prefix_width += number.len() + 1;
+        }
+        if let Some(column) = column {
+            let column = column.to_string();
+            text.push_str(&column);
+            text.push(separator);
+            prefix_width += column.len() + 1;
+        }
+
+        let style = if self.color {
+            self.match_style.as_str()
+        } else {
+            ""
+        };
+        let replacement = self.config.replacement.as_deref();
+        let by_query = replacement.is_none() && self.query_styles.len() > 1;
+        // Matches are found in the line as it is, since cutting it down or
+        // expanding its tabs could make or break one, and then moved to
+        // where they're shown.
+        let spans = match replacement {
+            None if style.is_empty() => Vec::new(),
+            None if by_query => self.matcher.find_all_by_query(line),
+            _ => self
+                .matcher
+                .find_all(line)
+                .into_iter()
+                .map(|span| (0, span))
+                .collect(),
+        };
+        let window = self.config.output.max_columns.and_then(|max_columns| {
+            window(line, self.matcher.find(line).unwrap_or(0..0), max_columns)
+        });
+        let (shown, offsets) = reshape(line, window.clone(), self.config.output.tab_width);
+        let kept = window.unwrap_or(0..line.len());
+        let shown_spans: Vec<(usize, Range<usize>)> = spans
+            .iter()
+            .enumerate()
+            .map(|(i, (_, span))| (i, offsets[span.start]..offsets[span.end]))
+            .filter(|(i, moved)| {
+                let span = &spans[*i].1;
+                !moved.is_empty()
+                    || (span.is_empty() && (kept.start..=kept.end).contains(&span.start))
+            })
+            .collect();
+        let line = paint_spans(&shown, &shown_spans, |i, matched| {
+            let (query, span) = &spans[i];
+            match replacement {
+                Some(_) => replace(
+                    &line[span.clone()],
+                    replacement,
+                    self.config.preserve_case,
+                    style,
+                ),
+                None if by_query => format!("{}{matched}{RESET}", self.query_styles[*query]),
+                None => replace(matched, None, false, style),
+            }
+        });
+        match self.wrap_width {
+            Some(width) => text.push_str(&wrap(&line, prefix_width, width)),
+            None => text.push_str(&line),
+        }
+        text
+    }
+
+    /// Prints just the file's path, for listing the files that match.
+    pub(crate) fn print_path(&self, out: &mut dyn Write) -> io::Result<()> {
+        match &self.path {
+            Some(path) => writeln!(out, "{}", self.paint(path, &self.path_style)),
+            None => Ok(()),
+        }
+    }
+
+    /// Prints how many lines of the file matched, after its path.
+    pub(crate) fn print_count(&self, out: &mut dyn Write, count: usize) -> io::Result<()> {
+        match &self.path {
+            Some(path) => writeln!(out, "{}:{count}", self.paint(path, &self.path_style)),
+            None => writeln!(out, "{count}"),
+        }
+    }
+
+    /// Renders `path` as the heading above a file's lines, for
+    /// `--heading`.
+    pub(crate) fn heading(&self, path: &Path) -> String {
+        self.paint(&path.display().to_string(), &self.path_style)
+    }
+
+    fn paint(&self, text: &str, style: &str) -> String {
+        if self.color && !style.is_empty() {
+            format!("{style}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Passes what's printed for one file on to `out`, putting the file's
+/// heading first if there is one, for `--heading`. Files after the first
+/// to print anything are set off from the one before by a blank line.
+pub(crate) struct Headed<'a> {
+    out: &'a mut dyn Write,
+    /// The heading, until it's been printed.
+    heading: Option<String>,
+    /// Whether any file has printed its heading yet, shared by the files
+    /// of a search.
+    started: Rc<Cell<bool>>,
+}
+
+impl<'a> Headed<'a> {
+    pub(crate) fn new(
+        out: &'a mut dyn Write,
+        heading: Option<String>,
+        started: Rc<Cell<bool>>,
+    ) -> Headed<'a> {
+        Headed {
+            out,
+            heading,
+            started,
+        }
+    }
+}
+
+impl Write for Headed<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(heading) = self.heading.take() {
+            if self.started.replace(true) {
+                writeln!(self.out)?;
+            }
+            writeln!(self.out, "{heading}")?;
+        }
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Decides which lines around the matches `-A`, `-B` and `-C` print, so that
+/// overlapping context is printed once, and puts the group separator between
+/// blocks that aren't next to each other. For `--show-function` it also heads
+/// each block with the line its function starts on.
+pub(crate) struct Context {
+    before: usize,
+    after: usize,
+    /// For `--block-context`, whether context runs to the blank lines either
+    /// side of a match instead.
+    block: bool,
+    /// Printed between blocks that aren't next to each other, except with
+    /// `--porcelain`, whose records say which lines they are.
+    separator: Option<String>,
+    /// The lines since the last one printed, at most `before` of them, in
+    /// case a match comes next.
+    recent: VecDeque<(usize, String)>,
+    /// How many more lines to print after the last match.
+    remaining: usize,
+    /// The number of the last line printed from this file.
+    last: Option<usize>,
+    /// Whether anything has been printed yet, from any file.
+    printed: bool,
+    functions: Option<Functions>,
+    /// The last line seen that starts a function, and its number.
+    function: Option<(usize, String)>,
+    /// The number of the function line last printed as a heading.
+    headed: Option<usize>,
+}
+
+/// How `--show-function` spots the line a function starts on.
+enum Functions {
+    Guess,
+    Containing(Matcher),
+}
+
+impl Functions {
+    fn starts(&self, line: &str) -> bool {
+        match self {
+            Functions::Guess => starts_function(line),
+            Functions::Containing(matcher) => matcher.is_match(line),
+        }
+    }
+}
+
+impl Context {
+    pub(crate) fn new(config: &Config) -> Context {
+        let (before, after) = match config.output.block_context {
+            true => (usize::MAX, usize::MAX),
+            false => (config.output.before_context, config.output.after_context),
+        };
+        let functions = match &config.output.function_start {
+            Some(text) => Some(Functions::Containing(Matcher::new(text, false))),
+            None => config.output.show_function.then_some(Functions::Guess),
+        };
+        Context {
+            before,
+            after,
+            block: config.output.block_context,
+            separator: (!config.output.porcelain).then(|| config.output.group_separator.clone()),
+            recent: VecDeque::new(),
+            remaining: 0,
+            last: None,
+            printed: false,
+            functions,
+            function: None,
+            headed: None,
+        }
+    }
+
+    /// Whether any context or headings were asked for.
+    pub(crate) fn wanted(&self) -> bool {
+        self.grouped() || self.functions.is_some()
+    }
+
+    /// Whether matches are printed with the lines around them, in groups.
+    fn grouped(&self) -> bool {
+        self.before > 0 || self.after > 0
+    }
+
+    /// Forgets the file just searched; the next block always gets a
+    /// separator.
+    pub(crate) fn start_file(&mut self) {
+        self.recent.clear();
+        self.remaining = 0;
+        self.last = None;
+        self.function = None;
+        self.headed = None;
+    }
+
+    /// Prints a line that didn't match if it's soon enough after a match,
+    /// or otherwise keeps it in case a match follows.
+    pub(crate) fn other(
+        &mut self,
+        printer: &Printer,
+        out: &mut dyn Write,
+        line_number: usize,
+        line: &str,
+    ) -> io::Result<()> {
+        if self.block && line.trim().is_empty() {
+            self.recent.clear();
+            self.remaining = 0;
+            return Ok(());
+        }
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.separate(printer, out, line_number)?;
+            self.note_function(line_number, line);
+            return printer.print_context(out, line_number, line);
+        }
+        if self.before > 0 {
+            if self.recent.len() == self.before {
+                self.recent.pop_front();
+            }
+            self.recent.push_back((line_number, line.to_string()));
+        }
+        self.note_function(line_number, line);
+        Ok(())
+    }
+
+    /// Prints the lines kept from before the match on `line_number`, which
+    /// the caller prints next.
+    pub(crate) fn matched(
+        &mut self,
+        printer: &Printer,
+        out: &mut dyn Write,
+        line_number: usize,
+        line: &str,
+    ) -> io::Result<()> {
+        for (number, line) in std::mem::take(&mut self.recent) {
+            self.separate(printer, out, number)?;
+            printer.print_context(out, number, &line)?;
+        }
+        self.separate(printer, out, line_number)?;
+        self.note_function(line_number, line);
+        self.remaining = self.after;
+        Ok(())
+    }
+
+    fn note_function(&mut self, line_number: usize, line: &str) {
+        if self.functions.as_ref().is_some_and(|f| f.starts(line)) {
+            self.function = Some((line_number, line.to_string()));
+        }
+    }
+
+    /// Prints the separator if `line_number` doesn't follow on from the last
+    /// line printed, and the function heading if it starts a new block.
+    fn separate(
+        &mut self,
+        printer: &Printer,
+        out: &mut dyn Write,
+        line_number: usize,
+    ) -> io::Result<()> {
+        let apart = match self.last {
+            Some(last) => line_number > last + 1,
+            None => self.printed,
+        };
+        if apart
+            && self.grouped()
+            && let Some(separator) = &self.separator
+        {
+            writeln!(out, "{separator}")?;
+        }
+        if (apart || self.last.is_none())
+            && let Some((number, function)) = &self.function
+            && *number < line_number
+            && self.headed != Some(*number)
+        {
+            printer.print_function(out, *number, function)?;
+            self.headed = Some(*number);
+        }
+        self.last = Some(line_number);
+        self.printed = true;
+        Ok(())
+    }
+}
+
+/// Guesses whether `line` starts a function, or a class or the like: after
+/// any indentation and modifiers it begins with a keyword that introduces
+/// one, or it's an unindented C-style declaration such as `int main(void)`.
+pub(crate) fn starts_function(line: &str) -> bool {
+    const MODIFIERS: [&str; 16] = [
+        "pub",
+        "pub(crate)",
+        "async",
+        "unsafe",
+        "const",
+        "extern",
+        "export",
+        "default",
+        "static",
+        "public",
+        "private",
+        "protected",
+        "abstract",
+        "final",
+        "override",
+        "virtual",
+    ];
+    const KEYWORDS: [&str; 13] = [
+        "fn",
+        "def",
+        "function",
+        "func",
+        "class",
+        "impl",
+        "trait",
+        "struct",
+        "enum",
+        "interface",
+        "module",
+        "sub",
+        "proc",
+    ];
+    let mut words = line
+        .split_whitespace()
+        .skip_while(|word| MODIFIERS.contains(word));
+    if words.next().is_some_and(|word| KEYWORDS.contains(&word)) {
+        return true;
+    }
+    line.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && line.contains('(')
+        && !line.trim_end().ends_with([';', ','])
+}
+
+/// Colours every match in `line` with the escape sequence `style`.
+pub(crate) fn highlight(line: &str, matcher: &Matcher, style: &str) -> String {
+    substitute(line, matcher, None, false, style)
+}
+
+/// `line` with each of `spans`, and the index of the query that matched it,
+/// replaced by what `paint` makes of them and the text matched.
+fn paint_spans(
+    line: &str,
+    spans: &[(usize, Range<usize>)],
+    mut paint: impl FnMut(usize, &str) -> String,
+) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut printed = 0;
+    for (index, span) in spans {
+        out.push_str(&line[printed..span.start]);
+        out.push_str(&paint(*index, &line[span.clone()]));
+        printed = span.end;
+    }
+    out.push_str(&line[printed..]);
+    out
+}
+
+/// The style of each of `queries` queries' matches: the theme's for the
+/// first, and then each of [`QUERY_COLORS`] in bold, over and over. One
+/// query needs no more than the theme's.
+fn query_styles(theme: &Theme, queries: usize) -> Vec<String> {
+    if queries < 2 {
+        return Vec::new();
+    }
+    let others = QUERY_COLORS.iter().cycle().map(|&color| {
+        Style {
+            fg: Some(Color::Basic(color)),
+            bold: true,
+            ..Style::default()
+        }
+        .escape()
+    });
+    iter::once(theme.matched.escape())
+        .chain(others)
+        .take(queries)
+        .collect()
+}
+
+/// Replaces every match in `line` with `replacement`, or leaves it be if
+/// there's none, and colours the result with `style` unless it's empty.
+/// With `preserve_case`, each replacement is given the case of the match.
+pub(crate) fn substitute(
+    line: &str,
+    matcher: &Matcher,
+    replacement: Option<&str>,
+    preserve_case: bool,
+    style: &str,
+) -> String {
+    let spans: Vec<(usize, Range<usize>)> = matcher
+        .find_all(line)
+        .into_iter()
+        .map(|span| (0, span))
+        .collect();
+    paint_spans(line, &spans, |_, matched| {
+        replace(matched, replacement, preserve_case, style)
+    })
+}
+
+/// What a match of `matched` is printed as: `replacement`, cased like the
+/// match with `preserve_case`, or else the match itself, coloured with
+/// `style` unless it's empty.
+fn replace(matched: &str, replacement: Option<&str>, preserve_case: bool, style: &str) -> String {
+    let text = match replacement {
+        Some(replacement) if preserve_case => match_case(replacement, matched),
+        Some(replacement) => replacement.to_string(),
+        None => matched.to_string(),
+    };
+    match style.is_empty() {
+        true => text,
+        false => format!("{style}{text}{RESET}"),
+    }
+}
+
+/// `replacement` in the case of `matched`: all capitals if it's all
+/// capitals, all lower case if it's all lower case, and capitalised if only
+/// its first letter is a capital. Any other mix, or a match with no letters
+/// that have a case, leaves the replacement as it was given.
+pub(crate) fn match_case(replacement: &str, matched: &str) -> String {
+    let mut cased = matched
+        .chars()
+        .filter(|c| c.is_lowercase() || c.is_uppercase());
+    let Some(first) = cased.next() else {
+        return replacement.to_string();
+    };
+    let rest: Vec<char> = cased.collect();
+    if first.is_uppercase() && rest.iter().all(|c| c.is_uppercase()) && !rest.is_empty() {
+        replacement.to_uppercase()
+    } else if first.is_lowercase() && rest.iter().all(|c| c.is_lowercase()) {
+        replacement.to_lowercase()
+    } else if first.is_uppercase() && rest.iter().all(|c| c.is_lowercase()) {
+        let mut chars = replacement.chars();
+        match chars.next() {
+            Some(c) => c
+                .to_uppercase()
+                .chain(chars.as_str().to_lowercase().chars())
+                .collect(),
+            None => String::new(),
+        }
+    } else {
+        replacement.to_string()
+    }
+}
+
+/// Escapes the characters that would break a `--porcelain` record apart.
+fn escape_porcelain(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The width of the longest bar in a histogram.
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// Renders a bar chart with a row for each `(label, count)`, in the order
+/// given, scaling the bars so the largest count fills [`HISTOGRAM_WIDTH`].
+pub(crate) fn histogram(buckets: &[(&str, usize)]) -> String {
+    let label_width = buckets.iter().map(|(label, _)| label.chars().count()).max();
+    let count_width = buckets
+        .iter()
+        .map(|(_, count)| count.to_string().len())
+        .max();
+    let largest = buckets.iter().map(|(_, count)| *count).max().unwrap_or(0);
+
+    let mut out = String::new();
+    for (label, count) in buckets {
+        // Round up so every group with a match gets at least one mark.
+        let bar = (count * HISTOGRAM_WIDTH).div_ceil(largest.max(1));
+        out.push_str(&format!(
+            "{label:<label_width$} {count:>count_width$} {}\n",
+            "#".repeat(bar),
+            label_width = label_width.unwrap_or(0),
+            count_width = count_width.unwrap_or(0),
+        ));
+    }
+    out
+}
+
+/// The bytes of `line` to keep to cut it down to `max_columns` characters,
+/// in a window centred on `span`, or `None` if it fits as it is.
+pub(crate) fn window(line: &str, span: Range<usize>, max_columns: usize) -> Option<Range<usize>> {
+    let starts: Vec<usize> = line.char_indices().map(|(i, _)| i).collect();
+    if starts.len() <= max_columns {
+        return None;
+    }
+
+    let to_column = |byte: usize| starts.partition_point(|&start| start < byte);
+    let (match_start, match_end) = (to_column(span.start), to_column(span.end));
+    let room = max_columns.saturating_sub(match_end - match_start);
+    let first = match_start.saturating_sub(room / 2);
+    let last = (first + max_columns).min(starts.len());
+    let first = last - max_columns;
+    Some(starts[first]..starts.get(last).copied().unwrap_or(line.len()))
+}
+
+/// Replaces each tab with enough spaces to reach the next multiple of
+/// `tab_width` columns.
+pub(crate) fn expand_tabs(line: &str, tab_width: usize) -> String {
+    reshape(line, None, Some(tab_width)).0
+}
+
+/// `line` cut down to `window`, marking each side that was cut with
+/// [`ELISION`], and with its tabs expanded to `tab_width`, along with where
+/// each byte offset of `line` ends up. Offsets that were cut off end up at
+/// the edge of what's left.
+pub(crate) fn reshape(
+    line: &str,
+    window: Option<Range<usize>>,
+    tab_width: Option<usize>,
+) -> (String, Vec<usize>) {
+    let window = window.unwrap_or(0..line.len());
+    let mut out = String::with_capacity(line.len());
+    let mut offsets = vec![0; line.len() + 1];
+    let mut column = 0;
+    if window.start > 0 {
+        out.push_str(ELISION);
+        column += ELISION.len();
+    }
+    offsets[..window.start].fill(out.len());
+    for (i, c) in line[window.clone()].char_indices() {
+        let i = window.start + i;
+        offsets[i..i + c.len_utf8()].fill(out.len());
+        match tab_width {
+            Some(tab_width) if c == '\t' => {
+                let spaces = tab_width - column % tab_width;
+                out.extend(iter::repeat_n(' ', spaces));
+                column += spaces;
+            }
+            _ => {
+                out.push(c);
+                column += 1;
+            }
+        }
+    }
+    offsets[window.end..].fill(out.len());
+    if window.end < line.len() {
+        out.push_str(ELISION);
+    }
+    (out, offsets)
+}
+
+/// Terminal widths narrower than this, after the prefix, aren't worth
+/// wrapping for.
+const MIN_WRAP_WIDTH: usize = 20;
+
+/// The width of the terminal, as the terminal itself reports it, or else
+/// as the shell does in `COLUMNS`, or 80 if neither does or `--no-env` says
+/// not to ask the shell.
+fn terminal_width(config: &Config) -> usize {
+    if let Some((_, columns)) = tui::tty_size() {
+        return columns;
+    }
+    env::var("COLUMNS")
+        .ok()
+        .filter(|_| !config.no_env)
+        .and_then(|columns| columns.parse().ok())
+        .filter(|columns| *columns > 0)
+        .unwrap_or(80)
+}
+
+/// Breaks `line` into rows that fit in `width` columns once the first row
+/// follows a prefix `indent` columns wide, indenting the remaining rows so
+/// they hang under the start of the line.
+///
+/// Colour escape sequences take up no columns.
+pub(crate) fn wrap(line: &str, indent: usize, width: usize) -> String {
+    let room = width.saturating_sub(indent);
+    if room < MIN_WRAP_WIDTH {
+        return line.to_string();
+    }
+
+    let mut out = String::with_capacity(line.len());
+    let mut column = 0;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            out.push(c);
+            for c in chars.by_ref() {
+                out.push(c);
+                if c == 'm' {
+                    break;
+                }
+            }
+            continue;
+        }
+        if column == room {
+            out.push('\n');
+            out.extend(std::iter::repeat_n(' ', indent));
+            column = 0;
+        }
+        out.push(c);
+        column += 1;
+    }
+    out
+}
+
+/// Checks whether the terminal is likely to understand OSC 8 hyperlinks;
+/// terminals that don't will usually ignore them, but dumb ones print them.
+fn supports_hyperlinks() -> bool {
+    escapes_supported() && env::var("TERM").map_or(cfg!(windows), |term| term != "dumb")
+}
+
+/// Checks whether stdout will interpret escape sequences rather than print
+/// them, switching the Windows console into VT mode the first time.
+pub(crate) fn escapes_supported() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(enable_virtual_terminal)
+}
+
+#[cfg(not(windows))]
+fn enable_virtual_terminal() -> bool {
+    true
+}
+
+/// Asks the Windows console to handle ANSI escape sequences itself, which
+/// consoles from Windows 10 onwards can do. Returns false on older consoles.
+#[cfg(windows)]
+fn enable_virtual_terminal() -> bool {
+    use std::ffi::c_void;
+    use std::os::windows::io::AsRawHandle;
+
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+
+    let console = io::stdout().as_raw_handle();
+    let mut mode = 0;
+    // SAFETY: `console` is stdout's handle for the life of the process and
+    // `mode` is a valid place for the console to write to.
+    unsafe {
+        if GetConsoleMode(console, &mut mode) == 0 {
+            // Not a console, e.g. a pipe or a terminal emulator's pty, which
+            // passes escapes through untouched.
+            return true;
+        }
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+            || SetConsoleMode(console, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    }
+}
+
+/// Fills in the `{path}`, `{line}` and `{column}` placeholders of a
+/// hyperlink template such as `vscode://file/{path}:{line}`.
+pub(crate) fn hyperlink_url(format: &str, path: &str, line: usize, column: usize) -> String {
+    format
+        .replace("{path}", &percent_encode(path))
+        .replace("{line}", &line.to_string())
+        .replace("{column}", &column.to_string())
+}
+
+/// Escapes everything in a path except the characters that are safe to leave
+/// as-is in a URL path.
+fn percent_encode(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    out
+}
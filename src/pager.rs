@@ -0,0 +1,147 @@
+//! Where results are written: stdout, or a pager reading from a pipe.
+
+use std::env;
+use std::io::{self, BufWriter, IsTerminal, Stdout, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use crate::{Buffering, LineTerminator};
+
+/// Used when `PAGER` isn't set. `-F` quits straight away when everything fits
+/// on one screen, `-R` passes colours through and `-X` leaves the output on
+/// the screen afterwards.
+const DEFAULT_PAGER: &str = "less -FRX";
+
+/// How much block buffering holds before writing it out.
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// The destination for results.
+pub(crate) enum Output {
+    /// Stdout as the standard library buffers it, a line at a time.
+    Stdout(Stdout),
+    /// Stdout flushed only when a block's worth has been written.
+    Block(BufWriter<Stdout>),
+    Pager {
+        child: Child,
+        stdin: ChildStdin,
+    },
+}
+
+impl Output {
+    /// Starts the pager when one was asked for and stdout is a terminal,
+    /// falling back to stdout if it can't be started. Stdout is buffered as
+    /// `buffering` says; a pager is written to as things are printed.
+    pub(crate) fn open(use_pager: bool, buffering: Buffering) -> Output {
+        let terminal = io::stdout().is_terminal();
+        if !use_pager || !terminal {
+            return match buffering {
+                Buffering::Line => Output::Stdout(io::stdout()),
+                Buffering::Auto if terminal => Output::Stdout(io::stdout()),
+                _ => Output::Block(BufWriter::with_capacity(BLOCK_SIZE, io::stdout())),
+            };
+        }
+
+        let pager = env::var("PAGER")
+            .ok()
+            .filter(|pager| !pager.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+        let mut words = pager.split_whitespace();
+        let Some(program) = words.next() else {
+            return Output::Stdout(io::stdout());
+        };
+
+        match Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .spawn()
+        {
+            Ok(mut child) => match child.stdin.take() {
+                Some(stdin) => Output::Pager { child, stdin },
+                None => Output::Stdout(io::stdout()),
+            },
+            Err(e) => {
+                eprintln!("minigrep: couldn't start pager `{program}`: {e}");
+                Output::Stdout(io::stdout())
+            }
+        }
+    }
+
+    /// Flushes what's left and, for a pager, closes its input and waits for
+    /// the user to quit it.
+    pub(crate) fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Stdout(mut stdout) => stdout.flush(),
+            Output::Block(mut stdout) => stdout.flush(),
+            Output::Pager { mut child, stdin } => {
+                drop(stdin);
+                child.wait()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(stdout) => stdout.write(buf),
+            Output::Block(stdout) => stdout.write(buf),
+            Output::Pager { stdin, .. } => stdin.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::Block(stdout) => stdout.flush(),
+            Output::Pager { stdin, .. } => stdin.flush(),
+        }
+    }
+}
+
+/// Ends each line written through it with a [`LineTerminator`], so the
+/// rest of minigrep can end lines with `\n` whatever's asked for.
+pub(crate) struct LineEnds<'a> {
+    out: &'a mut dyn Write,
+    terminator: LineTerminator,
+    /// Whether the last byte written was `\r`, so a line read from a file
+    /// with CRLF endings doesn't get a second one.
+    after_cr: bool,
+}
+
+impl<'a> LineEnds<'a> {
+    pub(crate) fn new(out: &'a mut dyn Write, terminator: LineTerminator) -> LineEnds<'a> {
+        LineEnds {
+            out,
+            terminator,
+            after_cr: false,
+        }
+    }
+}
+
+impl Write for LineEnds<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.terminator == LineTerminator::Lf {
+            return self.out.write(buf);
+        }
+        for (i, piece) in buf.split(|&b| b == b'\n').enumerate() {
+            if i > 0 {
+                let end: &[u8] = match self.terminator {
+                    LineTerminator::Crlf if self.after_cr => b"\n",
+                    LineTerminator::Crlf => b"\r\n",
+                    _ => b"\0",
+                };
+                self.out.write_all(end)?;
+                self.after_cr = false;
+            }
+            if let Some(&last) = piece.last() {
+                self.out.write_all(piece)?;
+                self.after_cr = last == b'\r';
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
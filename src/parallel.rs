@@ -0,0 +1,192 @@
+//! Searching one big file on several threads. The file is split into
+//! chunks that start and end on line boundaries, each thread finds the
+//! matching lines in its own chunk, and the results are handed back in
+//! order so they're printed as a search on one thread would print them.
+//!
+//! Each thread passes its results on through a bounded channel as it finds
+//! them. The printer reads the channels one chunk at a time, so a thread
+//! ahead of it, or one feeding a slow pager, waits once its channel is full
+//! rather than piling up matches in memory.
+
+use std::ops::Range;
+use std::panic;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::matcher::Matcher;
+use crate::regex;
+use crate::{CancellationToken, Config};
+
+/// How big a file has to be before it's worth splitting up.
+pub(crate) const MIN_SIZE: usize = 32 * 1024 * 1024;
+
+/// How many matching lines a thread sends at once.
+const BATCH: usize = 1024;
+
+/// How many batches a thread can send before it waits for the printer.
+const BACKLOG: usize = 4;
+
+/// Splits `contents` into at most `n` ranges of about the same size, each
+/// ending just after a `\n` or at the end.
+pub(crate) fn chunks(contents: &str, n: usize) -> Vec<Range<usize>> {
+    let size = contents.len().div_ceil(n.max(1)).max(1);
+    let mut chunks = Vec::with_capacity(n);
+    let mut start = 0;
+    while start < contents.len() {
+        let end = match contents.as_bytes()[(start + size).min(contents.len())..]
+            .iter()
+            .position(|&b| b == b'\n')
+        {
+            Some(newline) => (start + size + newline + 1).min(contents.len()),
+            None => contents.len(),
+        };
+        chunks.push(start..end);
+        start = end;
+    }
+    chunks
+}
+
+/// How the threads of one search spent their time.
+pub(crate) struct Work {
+    /// How many threads searched.
+    pub(crate) threads: usize,
+    /// Time the threads spent searching, summed over them, leaving out any
+    /// spent waiting for the printer.
+    pub(crate) busy: Duration,
+    /// Time the calling thread spent waiting for them.
+    pub(crate) waited: Duration,
+}
+
+/// Finds the lines of `contents` that match `config`'s queries on `threads`
+/// threads, and calls `found` with each and its line number, in order,
+/// until it returns `false` or `token` is cancelled. Each thread builds its
+/// own [`Matcher`], since the ones a C library compiles can't be shared.
+pub(crate) fn search<'a, E: From<regex::Error>>(
+    contents: &'a str,
+    config: &Config,
+    threads: usize,
+    token: &CancellationToken,
+    mut found: impl FnMut(usize, &'a str) -> Result<bool, E>,
+) -> Result<Work, E> {
+    thread::scope(|scope| {
+        let (receivers, handles): (Vec<Receiver<Message>>, Vec<_>) = chunks(contents, threads)
+            .into_iter()
+            .map(|chunk| {
+                let (sender, receiver) = mpsc::sync_channel(BACKLOG);
+                let handle =
+                    scope.spawn(move || search_chunk(&contents[chunk], config, token, sender));
+                (receiver, handle)
+            })
+            .collect();
+        let threads = receivers.len();
+        let mut waited = Duration::ZERO;
+        let result = drain(receivers, token, &mut waited, &mut found);
+        let busy = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .sum();
+        result.map(|()| Work {
+            threads,
+            busy,
+            waited,
+        })
+    })
+}
+
+/// Hands the lines from `receivers` to `found` in order, adding the time
+/// spent waiting for them to `waited`. Returning drops the receivers, which
+/// stops any thread still sending.
+fn drain<'a, E: From<regex::Error>>(
+    receivers: Vec<Receiver<Message<'a>>>,
+    token: &CancellationToken,
+    waited: &mut Duration,
+    found: &mut impl FnMut(usize, &'a str) -> Result<bool, E>,
+) -> Result<(), E> {
+    let mut before = 0;
+    for receiver in &receivers {
+        loop {
+            let waiting = Instant::now();
+            let Ok(message) = receiver.recv() else {
+                break;
+            };
+            *waited += waiting.elapsed();
+            if token.is_cancelled() {
+                return Ok(());
+            }
+            match message {
+                Message::Lines(lines) => {
+                    for (i, line) in lines {
+                        if !found(before + i + 1, line)? {
+                            return Ok(());
+                        }
+                    }
+                }
+                Message::Done(lines) => before += lines,
+                Message::Failed(e) => return Err(e.into()),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What a thread sends about its chunk.
+enum Message<'a> {
+    /// Matching lines, numbered from 0 within the chunk.
+    Lines(Vec<(usize, &'a str)>),
+    /// The chunk has been searched, and has this many lines.
+    Done(usize),
+    Failed(regex::Error),
+}
+
+/// Searches `chunk`, sending what it finds through `sender`, and returns
+/// how long that took apart from waiting for the printer.
+fn search_chunk<'a>(
+    chunk: &'a str,
+    config: &Config,
+    token: &CancellationToken,
+    sender: SyncSender<Message<'a>>,
+) -> Duration {
+    let started = Instant::now();
+    let mut blocked = Duration::ZERO;
+    let mut send = |message| {
+        let sending = Instant::now();
+        let sent = sender.send(message).is_ok();
+        blocked += sending.elapsed();
+        sent
+    };
+    let matcher = match Matcher::any(
+        &config.search.queries,
+        config.search.ignore_case,
+        config.search.engine,
+    ) {
+        Ok(matcher) => matcher,
+        Err(e) => {
+            send(Message::Failed(e));
+            return started.elapsed() - blocked;
+        }
+    };
+    let mut lines = 0;
+    let mut batch = Vec::with_capacity(BATCH);
+    for (i, line) in chunk.lines().enumerate() {
+        // Checking every line would cost more than the search.
+        if i % BATCH == 0 && token.is_cancelled() {
+            return started.elapsed() - blocked;
+        }
+        if matcher.is_match(line) {
+            batch.push((i, line));
+        }
+        // A send only fails once the printer has stopped listening.
+        if batch.len() == BATCH {
+            let full = std::mem::replace(&mut batch, Vec::with_capacity(BATCH));
+            if !send(Message::Lines(full)) {
+                return started.elapsed() - blocked;
+            }
+        }
+        lines = i + 1;
+    }
+    if batch.is_empty() || send(Message::Lines(batch)) {
+        send(Message::Done(lines));
+    }
+    started.elapsed() - blocked
+}
@@ -0,0 +1,119 @@
+//! `--pcre2`: patterns compiled and matched by the system's PCRE2 library,
+//! for look-around, backreferences and the rest of what the built-in
+//! engine in [`crate::regex`] doesn't do. Only built with the `pcre2`
+//! feature, which links `libpcre2-8`.
+
+use std::ffi::c_void;
+use std::ops::Range;
+use std::ptr;
+
+use crate::regex::Error;
+
+const PCRE2_CASELESS: u32 = 0x0000_0008;
+const PCRE2_UCP: u32 = 0x0002_0000;
+const PCRE2_UTF: u32 = 0x0008_0000;
+const PCRE2_NO_UTF_CHECK: u32 = 0x4000_0000;
+
+#[link(name = "pcre2-8")]
+unsafe extern "C" {
+    fn pcre2_compile_8(
+        pattern: *const u8,
+        length: usize,
+        options: u32,
+        error_code: *mut i32,
+        error_offset: *mut usize,
+        context: *mut c_void,
+    ) -> *mut c_void;
+    fn pcre2_code_free_8(code: *mut c_void);
+    fn pcre2_match_data_create_from_pattern_8(
+        code: *const c_void,
+        context: *mut c_void,
+    ) -> *mut c_void;
+    fn pcre2_match_data_free_8(data: *mut c_void);
+    fn pcre2_match_8(
+        code: *const c_void,
+        subject: *const u8,
+        length: usize,
+        start: usize,
+        options: u32,
+        data: *mut c_void,
+        context: *mut c_void,
+    ) -> i32;
+    fn pcre2_get_ovector_pointer_8(data: *mut c_void) -> *const usize;
+}
+
+/// A compiled PCRE2 pattern.
+pub(crate) struct Pcre2 {
+    code: *mut c_void,
+}
+
+impl Pcre2 {
+    pub(crate) fn new(pattern: &str, ignore_case: bool) -> Result<Pcre2, Error> {
+        let mut options = PCRE2_UTF | PCRE2_UCP;
+        if ignore_case {
+            options |= PCRE2_CASELESS;
+        }
+        let (mut code, mut offset) = (0, 0);
+        // SAFETY: the pattern pointer and length come from a `&str`, the
+        // error outputs are valid places to write to, and a null context
+        // asks for the defaults.
+        let compiled = unsafe {
+            pcre2_compile_8(
+                pattern.as_ptr(),
+                pattern.len(),
+                options,
+                &mut code,
+                &mut offset,
+                ptr::null_mut(),
+            )
+        };
+        if compiled.is_null() {
+            return Err(Error {
+                message: "the pattern isn't valid PCRE2",
+                position: Some(offset),
+            });
+        }
+        Ok(Pcre2 { code: compiled })
+    }
+
+    /// The first match in `text` starting at or after byte `start`, which
+    /// must be on a character boundary.
+    pub(crate) fn find_at(&self, text: &str, start: usize) -> Option<Range<usize>> {
+        // SAFETY: `self.code` is a compiled pattern until `drop`, the match
+        // data is freed before returning, and `text` is valid UTF-8 so the
+        // library's own check can be skipped. A successful match leaves at
+        // least one pair in the output vector.
+        unsafe {
+            let data = pcre2_match_data_create_from_pattern_8(self.code, ptr::null_mut());
+            if data.is_null() {
+                return None;
+            }
+            let result = pcre2_match_8(
+                self.code,
+                text.as_ptr(),
+                text.len(),
+                start,
+                PCRE2_NO_UTF_CHECK,
+                data,
+                ptr::null_mut(),
+            );
+            // Failing to match, or giving up on a match limit, is negative.
+            let span = match result {
+                result if result < 0 => None,
+                _ => {
+                    let ovector = pcre2_get_ovector_pointer_8(data);
+                    Some(*ovector..*ovector.add(1))
+                }
+            };
+            pcre2_match_data_free_8(data);
+            span
+        }
+    }
+}
+
+impl Drop for Pcre2 {
+    fn drop(&mut self) {
+        // SAFETY: `self.code` came from `pcre2_compile_8` and isn't used again.
+        unsafe { pcre2_code_free_8(self.code) }
+    }
+}
@@ -0,0 +1,92 @@
+//! A progress line on stderr for long recursive searches.
+
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Searches that finish sooner than this never show progress.
+const DELAY: Duration = Duration::from_millis(500);
+
+/// The least time between redraws, so drawing doesn't slow the search down.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Erases the current terminal line.
+const CLEAR_LINE: &str = "\r\x1b[K";
+
+pub(crate) struct Progress {
+    enabled: bool,
+    started: Instant,
+    last_drawn: Option<Instant>,
+    /// Whether the progress line is on screen right now.
+    shown: bool,
+}
+
+impl Progress {
+    /// Creates a progress line that's only ever drawn when `wanted` and
+    /// stderr is a terminal.
+    pub(crate) fn new(wanted: bool) -> Progress {
+        Progress {
+            enabled: wanted && io::stderr().is_terminal(),
+            started: Instant::now(),
+            last_drawn: None,
+            shown: false,
+        }
+    }
+
+    /// Redraws the line if it's due, showing the directory of the file about
+    /// to be searched.
+    pub(crate) fn update(&mut self, next: &Path, files: usize, matches: usize) {
+        let now = Instant::now();
+        if !self.enabled
+            || now.duration_since(self.started) < DELAY
+            || self
+                .last_drawn
+                .is_some_and(|last| now.duration_since(last) < REDRAW_INTERVAL)
+        {
+            return;
+        }
+
+        let dir = next.parent().unwrap_or(next).display();
+        let mut stderr = io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "{CLEAR_LINE}{files} files searched, {matches} matches: {dir}"
+        );
+        let _ = stderr.flush();
+        self.shown = true;
+        self.last_drawn = Some(now);
+    }
+
+    /// Erases the line if it's on screen.
+    pub(crate) fn clear(&mut self) {
+        if self.shown {
+            eprint!("{CLEAR_LINE}");
+            self.shown = false;
+        }
+    }
+
+    /// Wraps `out` so the progress line is erased before any result is
+    /// written.
+    pub(crate) fn clearing<'a>(&'a mut self, out: &'a mut dyn Write) -> ClearOnWrite<'a> {
+        ClearOnWrite {
+            progress: self,
+            out,
+        }
+    }
+}
+
+pub(crate) struct ClearOnWrite<'a> {
+    progress: &'a mut Progress,
+    out: &'a mut dyn Write,
+}
+
+impl Write for ClearOnWrite<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.progress.clear();
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
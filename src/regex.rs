@@ -0,0 +1,774 @@
+//! Regular expressions for `--engine regex`: a parser to a syntax tree,
+//! compiled to instructions for a Pike VM, which runs in time linear in the
+//! length of the line whatever the pattern.
+//!
+//! The syntax is the common core of Perl-style engines: `.`, `[...]`
+//! classes, `\d` `\w` `\s` and their negations, `^` `$` `\b` `\B`, groups
+//! (capturing, `(?:...)` and `(?P<name>...)`), `|`, and the `*` `+` `?`
+//! `{n,m}` repetitions with lazy `?` forms. Matches are leftmost-first, as
+//! in Perl.
+
+use std::fmt;
+use std::ops::Range;
+
+/// Repetition counts above this are refused rather than compiled.
+const MAX_REPEAT: u32 = 1000;
+/// Patterns compiling to more instructions than this are refused.
+const MAX_PROGRAM: usize = 100_000;
+/// Groups nested deeper than this are refused rather than parsed, as each
+/// level is another call on the stack.
+const MAX_NESTING: usize = 250;
+
+/// Why a pattern didn't parse, and where.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Error {
+    pub(crate) message: &'static str,
+    /// The byte offset in the pattern the problem was found at, if it's
+    /// down to one place.
+    pub(crate) position: Option<usize>,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(f, "{} at position {position}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A compiled pattern.
+pub(crate) struct Regex {
+    program: Vec<Inst>,
+    /// How many capture groups there are, the whole match being group 0.
+    groups: usize,
+    /// The named groups, and their numbers.
+    names: Vec<(String, usize)>,
+    ignore_case: bool,
+}
+
+impl Regex {
+    pub(crate) fn new(pattern: &str, ignore_case: bool) -> Result<Regex, Error> {
+        let mut parser = Parser {
+            chars: pattern.char_indices().collect(),
+            next: 0,
+            end: pattern.len(),
+            groups: 1,
+            names: Vec::new(),
+            depth: 0,
+        };
+        let tree = parser.alternation()?;
+        if let Some(&(position, _)) = parser.chars.get(parser.next) {
+            // Only an unmatched `)` stops the top-level alternation early.
+            return Err(Error {
+                message: "unmatched ) in the regex",
+                position: Some(position),
+            });
+        }
+
+        let mut compiler = Compiler {
+            program: Vec::new(),
+            ignore_case,
+        };
+        compiler.push(Inst::Save(0));
+        compiler.node(&tree)?;
+        compiler.push(Inst::Save(1));
+        compiler.push(Inst::Match);
+        Ok(Regex {
+            program: compiler.program,
+            groups: parser.groups,
+            names: parser.names,
+            ignore_case,
+        })
+    }
+
+    /// The number of the group called `name`.
+    pub(crate) fn group_named(&self, name: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .find(|(group, _)| group == name)
+            .map(|&(_, number)| number)
+    }
+
+    /// How many groups there are, counting the whole match as group 0.
+    pub(crate) fn groups(&self) -> usize {
+        self.groups
+    }
+
+    /// Like [`Regex::find_at`], but with where each group matched too,
+    /// numbered from the whole match as group 0. A group that took no part
+    /// in the match is `None`.
+    pub(crate) fn captures_at(
+        &self,
+        text: &str,
+        start: usize,
+    ) -> Option<Vec<Option<Range<usize>>>> {
+        let slots = self.run(text, start)?;
+        let spans = slots
+            .chunks(2)
+            .map(|pair| Some(pair[0]?..pair[1]?))
+            .collect();
+        Some(spans)
+    }
+
+    /// The first match in `text` starting at or after byte `start`, which
+    /// must be on a character boundary. `^` still means the start of `text`.
+    pub(crate) fn find_at(&self, text: &str, start: usize) -> Option<Range<usize>> {
+        let slots = self.run(text, start)?;
+        Some(slots[0]?..slots[1]?)
+    }
+
+    /// Runs the program from `start`, returning the capture slots of the
+    /// highest-priority thread to match at the leftmost position.
+    fn run(&self, text: &str, start: usize) -> Option<Vec<Option<usize>>> {
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut matched = None;
+        let mut position = start;
+        loop {
+            // Threads started further left take priority, so a new start is
+            // added last, and none once something has matched.
+            if matched.is_none() {
+                let slots = vec![None; self.groups * 2];
+                self.add(&mut current, 0, text, position, slots);
+            }
+            if current.list.is_empty() && matched.is_some() {
+                break;
+            }
+            let c = text[position..].chars().next();
+            for (pc, slots) in current.list.drain(..) {
+                let consumed = match &self.program[pc] {
+                    Inst::Match => {
+                        matched = Some(slots);
+                        // Lower-priority threads can only give worse matches.
+                        break;
+                    }
+                    Inst::Char(want) => c.filter(|&c| self.fold(c) == *want),
+                    Inst::Any => c.filter(|&c| c != '\n'),
+                    Inst::Class(class) => c.filter(|&c| class.matches(c, self.ignore_case)),
+                    _ => None,
+                };
+                if let Some(c) = consumed {
+                    self.add(&mut next, pc + 1, text, position + c.len_utf8(), slots);
+                }
+            }
+            let Some(c) = c else {
+                break;
+            };
+            position += c.len_utf8();
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        matched
+    }
+
+    /// Adds the thread at `pc` to `threads`, following jumps, splits, saves
+    /// and assertions so that only instructions that consume a character
+    /// (or match) are queued, in priority order.
+    fn add(
+        &self,
+        threads: &mut Threads,
+        pc: usize,
+        text: &str,
+        position: usize,
+        slots: Vec<Option<usize>>,
+    ) {
+        let mut stack = vec![(pc, slots)];
+        while let Some((pc, mut slots)) = stack.pop() {
+            if std::mem::replace(&mut threads.seen[pc], true) {
+                continue;
+            }
+            match &self.program[pc] {
+                Inst::Jump(to) => stack.push((*to, slots)),
+                Inst::Split(first, second) => {
+                    stack.push((*second, slots.clone()));
+                    stack.push((*first, slots));
+                }
+                Inst::Save(slot) => {
+                    slots[*slot] = Some(position);
+                    stack.push((pc + 1, slots));
+                }
+                Inst::Assert(assertion) => {
+                    if assertion.holds(text, position) {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                _ => threads.list.push((pc, slots)),
+            }
+        }
+    }
+
+    fn fold(&self, c: char) -> char {
+        match self.ignore_case {
+            true => fold(c),
+            false => c,
+        }
+    }
+}
+
+/// The threads of the VM at one position, in priority order.
+struct Threads {
+    list: Vec<(usize, Vec<Option<usize>>)>,
+    /// Which instructions already have a thread, since a second one could
+    /// only match the same way with lower priority.
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Threads {
+        Threads {
+            list: Vec::new(),
+            seen: vec![false; size],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.seen.fill(false);
+    }
+}
+
+/// A character's simple lowercase form, used on both sides of a
+/// case-insensitive comparison.
+fn fold(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(lower), None) => lower,
+        _ => c,
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+enum Inst {
+    Char(char),
+    /// Any character but a newline.
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    /// Carry on at both, preferring the first.
+    Split(usize, usize),
+    Jump(usize),
+    /// Record the position in a capture slot.
+    Save(usize),
+    Match,
+}
+
+#[derive(Clone, Copy)]
+enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+impl Assertion {
+    fn holds(self, text: &str, position: usize) -> bool {
+        let before = text[..position].chars().next_back().is_some_and(is_word);
+        let after = text[position..].chars().next().is_some_and(is_word);
+        match self {
+            Assertion::Start => position == 0,
+            Assertion::End => position == text.len(),
+            Assertion::WordBoundary => before != after,
+            Assertion::NotWordBoundary => before == after,
+        }
+    }
+}
+
+/// A `[...]` class, or one of the `\d` `\w` `\s` shorthands.
+#[derive(Clone)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+#[derive(Clone)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl Class {
+    fn perl(item: ClassItem) -> Class {
+        Class {
+            items: vec![item],
+            negated: false,
+        }
+    }
+
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let contains = |c: char| {
+            self.items.iter().any(|item| match *item {
+                ClassItem::Range(first, last) => (first..=last).contains(&c),
+                ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+                ClassItem::Word(negated) => is_word(c) != negated,
+                ClassItem::Space(negated) => c.is_whitespace() != negated,
+            })
+        };
+        let found = contains(c)
+            || (ignore_case
+                && (contains(fold(c)) || c.to_uppercase().count() == 1 && contains(upper(c))));
+        found != self.negated
+    }
+}
+
+fn upper(c: char) -> char {
+    c.to_uppercase().next().unwrap_or(c)
+}
+
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    /// A group, capturing into the numbered group if it has one.
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+struct Parser {
+    chars: Vec<(usize, char)>,
+    next: usize,
+    /// The pattern's length, as the position of errors at its end.
+    end: usize,
+    /// How many capture groups have been opened, counting group 0.
+    groups: usize,
+    names: Vec<(String, usize)>,
+    /// How many groups the parser is inside.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.next).map(|&(_, c)| c)
+    }
+
+    fn position(&self) -> usize {
+        self.chars.get(self.next).map_or(self.end, |&(i, _)| i)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        self.next += usize::from(found);
+        found
+    }
+
+    fn error(&self, message: &'static str, position: usize) -> Error {
+        Error {
+            message,
+            position: Some(position),
+        }
+    }
+
+    fn alternation(&mut self) -> Result<Node, Error> {
+        let mut branches = vec![self.concatenation()?];
+        while self.eat('|') {
+            branches.push(self.concatenation()?);
+        }
+        Ok(match branches.len() {
+            1 => branches.pop().unwrap_or(Node::Empty),
+            _ => Node::Alternate(branches),
+        })
+    }
+
+    fn concatenation(&mut self) -> Result<Node, Error> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.repetition(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap_or(Node::Empty),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn atom(&mut self) -> Result<Node, Error> {
+        let start = self.position();
+        let Some(c) = self.peek() else {
+            return Ok(Node::Empty);
+        };
+        self.next += 1;
+        Ok(match c {
+            '(' => self.group(start)?,
+            '[' => Node::Class(self.class(start)?),
+            '.' => Node::Any,
+            '^' => Node::Assert(Assertion::Start),
+            '$' => Node::Assert(Assertion::End),
+            '\\' => self.escape(start)?,
+            '*' | '+' | '?' => return Err(self.error("nothing to repeat in the regex", start)),
+            c => Node::Char(c),
+        })
+    }
+
+    fn group(&mut self, start: usize) -> Result<Node, Error> {
+        if self.depth == MAX_NESTING {
+            return Err(self.error("groups nested too deeply in the regex", start));
+        }
+        let capture = if self.eat('?') {
+            if self.eat(':') {
+                None
+            } else if self.eat('<') || (self.eat('P') && self.eat('<')) {
+                let name = self.group_name()?;
+                if self.names.iter().any(|(other, _)| *other == name) {
+                    return Err(self.error("duplicate group name in the regex", start));
+                }
+                let group = self.open_group();
+                self.names.push((name, group));
+                Some(group)
+            } else {
+                return Err(self.error("unknown group flag in the regex", self.position()));
+            }
+        } else {
+            Some(self.open_group())
+        };
+        self.depth += 1;
+        let inner = self.alternation();
+        self.depth -= 1;
+        let inner = inner?;
+        if !self.eat(')') {
+            return Err(self.error("unclosed group in the regex", start));
+        }
+        Ok(Node::Group(Box::new(inner), capture))
+    }
+
+    fn open_group(&mut self) -> usize {
+        self.groups += 1;
+        self.groups - 1
+    }
+
+    /// Reads a group's name up to the closing `>`.
+    fn group_name(&mut self) -> Result<String, Error> {
+        let start = self.position();
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(|&c| is_word(c)) {
+            name.push(c);
+            self.next += 1;
+        }
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) || !self.eat('>') {
+            return Err(self.error("bad group name in the regex", start));
+        }
+        Ok(name)
+    }
+
+    fn escape(&mut self, start: usize) -> Result<Node, Error> {
+        let Some(c) = self.peek() else {
+            return Err(self.error("trailing backslash in the regex", start));
+        };
+        self.next += 1;
+        Ok(match c {
+            'b' => Node::Assert(Assertion::WordBoundary),
+            'B' => Node::Assert(Assertion::NotWordBoundary),
+            _ => match self.class_escape(c, start)? {
+                Ok(c) => Node::Char(c),
+                Err(item) => Node::Class(Class::perl(item)),
+            },
+        })
+    }
+
+    /// What `\c` means where a class item is allowed: a character, or one of
+    /// the shorthand classes.
+    fn class_escape(&mut self, c: char, start: usize) -> Result<Result<char, ClassItem>, Error> {
+        Ok(Ok(match c {
+            'd' => return Ok(Err(ClassItem::Digit(false))),
+            'D' => return Ok(Err(ClassItem::Digit(true))),
+            'w' => return Ok(Err(ClassItem::Word(false))),
+            'W' => return Ok(Err(ClassItem::Word(true))),
+            's' => return Ok(Err(ClassItem::Space(false))),
+            'S' => return Ok(Err(ClassItem::Space(true))),
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'f' => '\x0c',
+            'v' => '\x0b',
+            '0' => '\0',
+            'x' => self.hex(start)?,
+            c if c.is_alphanumeric() => {
+                return Err(self.error("unknown escape in the regex", start));
+            }
+            c => c,
+        }))
+    }
+
+    /// Reads the `NN` or `{N...}` after `\x`.
+    fn hex(&mut self, start: usize) -> Result<char, Error> {
+        let mut digits = String::new();
+        if self.eat('{') {
+            while let Some(c) = self.peek().filter(|&c| c != '}') {
+                digits.push(c);
+                self.next += 1;
+            }
+            if !self.eat('}') {
+                return Err(self.error("unclosed \\x{...} in the regex", start));
+            }
+        } else {
+            for _ in 0..2 {
+                digits.extend(self.peek());
+                self.next += 1;
+            }
+        }
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error("bad \\x escape in the regex", start))
+    }
+
+    fn class(&mut self, start: usize) -> Result<Class, Error> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let position = self.position();
+            let Some(c) = self.peek() else {
+                return Err(self.error("unclosed character class in the regex", start));
+            };
+            self.next += 1;
+            let low = match c {
+                ']' if !first => break,
+                '\\' => {
+                    let Some(c) = self.peek() else {
+                        return Err(self.error("unclosed character class in the regex", start));
+                    };
+                    self.next += 1;
+                    match self.class_escape(c, position)? {
+                        Ok(c) => c,
+                        Err(item) => {
+                            items.push(item);
+                            first = false;
+                            continue;
+                        }
+                    }
+                }
+                c => c,
+            };
+            first = false;
+            // A `-` is a range unless it's last.
+            let ranged = self.peek() == Some('-')
+                && self
+                    .chars
+                    .get(self.next + 1)
+                    .is_some_and(|&(_, c)| c != ']');
+            if !ranged {
+                items.push(ClassItem::Range(low, low));
+                continue;
+            }
+            self.next += 1;
+            let high = match self.peek() {
+                Some('\\') => {
+                    let escape = self.position();
+                    self.next += 1;
+                    let c = self.peek().ok_or_else(|| {
+                        self.error("unclosed character class in the regex", start)
+                    })?;
+                    self.next += 1;
+                    self.class_escape(c, escape)?
+                        .map_err(|_| self.error("bad class range in the regex", position))?
+                }
+                Some(c) => {
+                    self.next += 1;
+                    c
+                }
+                None => return Err(self.error("unclosed character class in the regex", start)),
+            };
+            if high < low {
+                return Err(self.error("class range out of order in the regex", position));
+            }
+            items.push(ClassItem::Range(low, high));
+        }
+        Ok(Class { items, negated })
+    }
+
+    /// Applies any repetition operator following `atom`.
+    fn repetition(&mut self, atom: Node) -> Result<Node, Error> {
+        let start = self.position();
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => match self.counts()? {
+                Some(counts) => counts,
+                None => return Ok(atom),
+            },
+            _ => return Ok(atom),
+        };
+        // `counts` has already read past the braces.
+        if self.position() == start {
+            self.next += 1;
+        }
+        if matches!(atom, Node::Empty) {
+            return Err(self.error("nothing to repeat in the regex", start));
+        }
+        let greedy = !self.eat('?');
+        if matches!(self.peek(), Some('*' | '+' | '?')) {
+            return Err(self.error("nothing to repeat in the regex", self.position()));
+        }
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    /// Reads `{n}`, `{n,}` or `{n,m}`, leaving the parser after it. Anything
+    /// else starting with `{` is left alone, to be read as literal text.
+    fn counts(&mut self) -> Result<Option<(u32, Option<u32>)>, Error> {
+        let start = self.position();
+        let mut end = self.next + 1;
+        let mut text = String::new();
+        while let Some(&(_, c)) = self.chars.get(end).filter(|&&(_, c)| c != '}') {
+            text.push(c);
+            end += 1;
+        }
+        if self.chars.get(end).is_none() {
+            return Ok(None);
+        }
+        let number = |digits: &str| match digits.is_empty() {
+            true => None,
+            false => digits.parse::<u32>().ok(),
+        };
+        let (min, max) = match text.split_once(',') {
+            Some((min, "")) => (number(min), None),
+            Some((min, max)) => match number(max) {
+                Some(max) => (number(min), Some(max)),
+                None => return Ok(None),
+            },
+            None => (number(&text), number(&text)),
+        };
+        let Some(min) = min else {
+            return Ok(None);
+        };
+        if max.is_some_and(|max| max < min) {
+            return Err(self.error("repetition range out of order in the regex", start));
+        }
+        if min.max(max.unwrap_or(0)) > MAX_REPEAT {
+            return Err(self.error("repetition count too large in the regex", start));
+        }
+        self.next = end + 1;
+        Ok(Some((min, max)))
+    }
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+    ignore_case: bool,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.program.push(inst);
+        self.program.len() - 1
+    }
+
+    fn node(&mut self, node: &Node) -> Result<(), Error> {
+        if self.program.len() > MAX_PROGRAM {
+            return Err(Error {
+                message: "the regex is too large",
+                position: None,
+            });
+        }
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => {
+                let c = if self.ignore_case { fold(*c) } else { *c };
+                self.push(Inst::Char(c));
+            }
+            Node::Any => {
+                self.push(Inst::Any);
+            }
+            Node::Class(class) => {
+                self.push(Inst::Class(class.clone()));
+            }
+            Node::Assert(assertion) => {
+                self.push(Inst::Assert(*assertion));
+            }
+            Node::Group(inner, capture) => {
+                if let Some(group) = capture {
+                    self.push(Inst::Save(group * 2));
+                }
+                self.node(inner)?;
+                if let Some(group) = capture {
+                    self.push(Inst::Save(group * 2 + 1));
+                }
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.node(node)?;
+                }
+            }
+            Node::Alternate(branches) => {
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 == branches.len() {
+                        self.node(branch)?;
+                        break;
+                    }
+                    let split = self.push(Inst::Split(0, 0));
+                    self.node(branch)?;
+                    jumps.push(self.push(Inst::Jump(0)));
+                    let next = self.program.len();
+                    self.program[split] = Inst::Split(split + 1, next);
+                }
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.node(node)?;
+                }
+                let split = |this: &mut Compiler, at: usize, body: usize, out: usize| {
+                    this.program[at] = match greedy {
+                        true => Inst::Split(body, out),
+                        false => Inst::Split(out, body),
+                    };
+                };
+                match max {
+                    None => {
+                        let at = self.push(Inst::Split(0, 0));
+                        self.node(node)?;
+                        self.push(Inst::Jump(at));
+                        let out = self.program.len();
+                        split(self, at, at + 1, out);
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0)));
+                            self.node(node)?;
+                        }
+                        let out = self.program.len();
+                        for at in splits {
+                            split(self, at, at + 1, out);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+//! `-I`: a prompt for one query after another over the same files, which
+//! are read once (see [`cache`]) and kept in memory between queries.
+
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::Config;
+use crate::cache::{self, File};
+use crate::matcher::Matcher;
+use crate::output::Printer;
+use crate::vfs::Disk;
+
+pub(crate) fn run(config: &Config, out: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+    let mut files = cache::load(config, &Disk);
+    eprintln!(
+        "minigrep: {} files loaded; type a query, :reload to read them again, or :quit",
+        files.len()
+    );
+
+    let mut queries = io::stdin().lock().lines();
+    loop {
+        // The prompt and summaries go to stderr so stdout holds only matches.
+        eprint!("minigrep> ");
+        let Some(query) = queries.next() else {
+            eprintln!();
+            return Ok(());
+        };
+        let query = query?;
+        match query.trim() {
+            "" => continue,
+            ":q" | ":quit" => return Ok(()),
+            ":r" | ":reload" => {
+                files = cache::load(config, &Disk);
+                eprintln!("minigrep: {} files loaded", files.len());
+                continue;
+            }
+            _ => {}
+        }
+
+        let started = Instant::now();
+        let found = search(config, &files, &query, out)?;
+        out.flush()?;
+        eprintln!("{found} matching lines in {:.1?}", started.elapsed());
+    }
+}
+
+/// Prints the lines of `files` that match `query`, returning how many did.
+pub(crate) fn search(
+    config: &Config,
+    files: &[File],
+    query: &str,
+    out: &mut dyn Write,
+) -> io::Result<usize> {
+    let matcher = Matcher::lenient(query, config.search.ignore_case, config.search.engine);
+    let show_path = Path::new(&config.input.file_path).is_dir();
+    let mut found = 0;
+    for file in files {
+        let printer = Printer::new(config, &matcher, show_path.then_some(&file.path));
+        for (line_number, line) in (1..).zip(file.contents.lines()) {
+            if matcher.is_match(line) {
+                printer.print_match(out, line_number, line)?;
+                found += 1;
+            }
+        }
+    }
+    Ok(found)
+}
@@ -0,0 +1,340 @@
+//! `--serve`: answering searches over a socket, so an editor can skip
+//! starting a new process for every search.
+//!
+//! Each request is one line of JSON naming the query, the path and any other
+//! command-line options:
+//!
+//! ```text
+//! {"query": "fn", "path": "src", "args": ["-r", "-n"]}
+//! ```
+//!
+//! The reply is one JSON object per line: `{"line": ...}` for each line of
+//! output, then `{"done": true}`, or `{"error": ...}` if the search couldn't
+//! run. A connection can send any number of requests, one after another.
+//!
+//! Only the options in [`ALLOWED`] can be given, and the path must be a
+//! file or directory, so a request can read files but never write them,
+//! fetch URLs, run git or tie the server up waiting for input.
+
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::thread;
+
+use crate::completions::FLAGS;
+use crate::download;
+use crate::json::{self, Value};
+use crate::{CancellationToken, ColorChoice, Config, Searcher};
+
+/// The subcommands a request can start with.
+const SUBCOMMANDS: [&str; 3] = ["search", "count", "files"];
+
+/// The options a request can give, as they're spelled on the command line:
+/// ones that only change what's searched for and how it's printed.
+const ALLOWED: &[&str] = &[
+    "-i",
+    "-s",
+    "--case-sensitive",
+    "--no-env",
+    "-r",
+    "--recursive",
+    "-n",
+    "--line-number",
+    "-N",
+    "--no-line-number",
+    "--heading",
+    "--no-heading",
+    "-c",
+    "--count",
+    "--count-matches",
+    "--count-per-file",
+    "--count-files",
+    "--include-zero",
+    "-o",
+    "--only-matching",
+    "--column",
+    "--overlapping",
+    "--escapes",
+    "--vimgrep",
+    "--porcelain",
+    "--explain",
+    "--group",
+    "--top",
+    "--histogram",
+    "--unique",
+    "--unique-count",
+    "-q",
+    "--quiet",
+    "-l",
+    "--files-with-matches",
+    "--max-total",
+    "--head",
+    "--tail",
+    "--timeout",
+    "--max-memory",
+    "--buffer-size",
+    "--max-filesize",
+    "--newer-than",
+    "--older-than",
+    "--sort",
+    "--sortr",
+    "--sort-lines",
+    "--sort-scope",
+    "--from-line",
+    "--to-line",
+    "--start-offset",
+    "--end-offset",
+    "-M",
+    "--max-columns",
+    "--tabs",
+    "--glob-pattern",
+    "--pcre2",
+    "--engine",
+    "--color",
+    "--colors",
+    "--lang",
+    "--crlf",
+    "--null",
+    "--passthru",
+    "-A",
+    "--after-context",
+    "-B",
+    "--before-context",
+    "-C",
+    "--context",
+    "--block-context",
+    "--show-function",
+    "--function-start",
+    "--json-input",
+    "--csv",
+    "--field",
+    "--delimiter",
+    "--html",
+    "--selector",
+    "--group-separator",
+    "--no-ignore",
+    "--no-ignore-vcs",
+    "--no-ignore-global",
+    "-L",
+    "--follow-links",
+    "--no-sniff",
+    "--directories",
+];
+
+/// Listens on `address`, a `host:port` on this machine or otherwise the path
+/// of a Unix socket, and answers requests until killed.
+pub(crate) fn serve(address: &str) -> Result<(), Box<dyn Error>> {
+    if address.contains(':') && !address.contains('/') {
+        // Anyone who can connect can read any file we can, so stay local,
+        // and check before anything else can connect.
+        let addresses: Vec<_> = address.to_socket_addrs()?.collect();
+        if addresses.is_empty() || !addresses.iter().all(|address| address.ip().is_loopback()) {
+            return Err("--serve only listens on localhost".into());
+        }
+        let listener = TcpListener::bind(&addresses[..])?;
+        eprintln!("minigrep: listening on {}", listener.local_addr()?);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let reader = BufReader::new(stream.try_clone()?);
+            thread::spawn(move || report(handle(reader, stream)));
+        }
+        return Ok(());
+    }
+    serve_unix(address)
+}
+
+#[cfg(unix)]
+fn serve_unix(path: &str) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let listener = match UnixListener::bind(path) {
+        // A socket left behind by a server that's gone can be reused.
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse && UnixStream::connect(path).is_err() => {
+            std::fs::remove_file(path)?;
+            UnixListener::bind(path)
+        }
+        result => result,
+    }?;
+    eprintln!("minigrep: listening on {path}");
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let reader = BufReader::new(stream.try_clone()?);
+        thread::spawn(move || report(handle(reader, stream)));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_unix(_path: &str) -> Result<(), Box<dyn Error>> {
+    Err("Unix sockets aren't available here; use --serve localhost:PORT".into())
+}
+
+fn report(result: io::Result<()>) {
+    if let Err(e) = result
+        && e.kind() != io::ErrorKind::BrokenPipe
+    {
+        eprintln!("minigrep: {e}");
+    }
+}
+
+/// Answers each request read from one connection.
+fn handle(reader: impl BufRead, mut out: impl Write) -> io::Result<()> {
+    for request in reader.lines() {
+        let request = request?;
+        if !request.trim().is_empty() {
+            answer(&request, &mut out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the search `request` asks for and writes the reply to `out`.
+pub(crate) fn answer(request: &str, out: &mut impl Write) -> io::Result<()> {
+    let token = CancellationToken::new();
+    let mut lines = JsonLines {
+        out: &mut *out,
+        line: Vec::new(),
+    };
+    let result = config_from(request)
+        .and_then(|config| {
+            if let Some(timeout) = config.timeout {
+                token.cancel_after(timeout);
+            }
+            Searcher::new(&config, &token)
+                .search_all(&mut lines)
+                .map(|_| ())
+        })
+        .and(lines.finish().map_err(Into::into));
+
+    match result {
+        Ok(()) => writeln!(out, "{{\"done\":true}}")?,
+        Err(e) => match e.downcast::<io::Error>() {
+            Ok(e) if e.kind() == io::ErrorKind::BrokenPipe => return Err(*e),
+            Ok(e) => writeln!(out, "{{\"error\":{}}}", json::string(&e.to_string()))?,
+            Err(e) => writeln!(out, "{{\"error\":{}}}", json::string(&e.to_string()))?,
+        },
+    }
+    out.flush()
+}
+
+fn config_from(request: &str) -> Result<Config, Box<dyn Error>> {
+    let request = json::parse(request)?;
+    let query = request.get("query").and_then(Value::as_str);
+    let query = query.ok_or("request needs a \"query\" string")?;
+    let path = request.get("path").and_then(Value::as_str);
+    let path = path.ok_or("request needs a \"path\" string")?;
+
+    let mut args = vec!["minigrep".to_string()];
+    match request.get("args") {
+        None => {}
+        Some(Value::Array(items)) => {
+            for item in items {
+                let arg = item
+                    .as_str()
+                    .ok_or("\"args\" must be an array of strings")?;
+                args.push(arg.to_string());
+            }
+        }
+        Some(_) => return Err("\"args\" must be an array of strings".into()),
+    }
+    check_allowed(&args[1..])?;
+    // Only files here are searched; a URL would have the server fetch it.
+    if download::is_url(path) {
+        return Err("a request can only search files, not URLs".into());
+    }
+    // After `--`, a query or path starting with `-` isn't taken for an
+    // option, and the path is checked like any other.
+    args.extend(["--".to_string(), query.to_string(), path.to_string()]);
+
+    let mut config = Config::parse(args.into_iter())?;
+    if config.serve.is_some() {
+        return Err("a request can't start another server".into());
+    }
+    if config.input.git_rev.is_some() || config.input.changed {
+        return Err("a request can't run git".into());
+    }
+    config.check_query()?;
+    // There's no terminal at the other end, and nobody to page or watch
+    // progress on this one.
+    if config.output.color == ColorChoice::Auto {
+        config.output.color = ColorChoice::Never;
+    }
+    config.output.pager = false;
+    config.output.progress = false;
+    Ok(config)
+}
+
+/// Refuses `args` unless they're a subcommand from [`SUBCOMMANDS`] and
+/// options from [`ALLOWED`], each with its value if it takes one.
+fn check_allowed(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut args = args.iter().peekable();
+    args.next_if(|arg| SUBCOMMANDS.contains(&arg.as_str()));
+    while let Some(arg) = args.next() {
+        // The value can be attached, as in `--context=2` or `-C2`.
+        let (name, attached) = match arg.split_once('=') {
+            Some((name, _)) if name.starts_with("--") => (name, true),
+            _ if !arg.starts_with("--") && arg.len() > 2 => (arg.get(..2).unwrap_or(arg), true),
+            _ => (arg.as_str(), false),
+        };
+        if !name.starts_with('-') {
+            return Err(
+                "\"args\" can only hold options; the query and path go in \"query\" and \"path\""
+                    .into(),
+            );
+        }
+        if !ALLOWED.contains(&name) {
+            return Err(format!("{name} can't be used in a request").into());
+        }
+        let takes_value = FLAGS.iter().any(|flag| {
+            let named = match name.strip_prefix("--") {
+                Some(long) => flag.long == Some(long),
+                None => flag.short.is_some() && name.chars().nth(1) == flag.short,
+            };
+            named && flag.value.is_some()
+        });
+        if takes_value && !attached {
+            args.next();
+        }
+    }
+    Ok(())
+}
+
+/// Wraps each line written to it as a `{"line": ...}` reply.
+struct JsonLines<'a, W: Write> {
+    out: &'a mut W,
+    /// Output since the last newline.
+    line: Vec<u8>,
+}
+
+impl<W: Write> JsonLines<'_, W> {
+    /// Sends anything written after the last newline.
+    fn finish(mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        let line = json::string(&String::from_utf8_lossy(&self.line));
+        self.line.clear();
+        writeln!(self.out, "{{\"line\":{line}}}")
+    }
+}
+
+impl<W: Write> Write for JsonLines<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if byte == b'\n' {
+                self.send()?;
+            } else {
+                self.line.push(byte);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
@@ -0,0 +1,71 @@
+//! Recognizing images, audio, video and executables by their first bytes,
+//! so recursive searches can skip them without reading them through.
+
+use std::io::Read;
+use std::path::Path;
+
+use crate::vfs::{self, FileSystem};
+
+/// How much of a file the signatures below need.
+const HEADER: usize = 16;
+
+/// Why the file of `fs` at `path` isn't worth searching, if its first bytes say
+/// it's media or a program. Files that can't be read aren't judged here;
+/// searching them reports the error. Nor are pipes and devices, whose
+/// first bytes would be taken from the search.
+pub(crate) fn skip_reason(fs: &dyn FileSystem, path: &Path) -> Option<&'static str> {
+    if !vfs::followed(fs, path).ok()?.is_file() {
+        return None;
+    }
+    let mut header = Vec::with_capacity(HEADER);
+    fs.open(path)
+        .and_then(|file| file.take(HEADER as u64).read_to_end(&mut header))
+        .ok()?;
+    kind(&header)
+}
+
+/// Matches `header` against the signatures of common binary formats. Those
+/// made of printable characters are checked further, so text that happens to
+/// start with `MZ` or `ID3` is still searched.
+fn kind(header: &[u8]) -> Option<&'static str> {
+    const IMAGE: &str = "looks like an image";
+    const AUDIO: &str = "looks like audio";
+    const VIDEO: &str = "looks like video";
+    const EXECUTABLE: &str = "looks like an executable";
+
+    // RIFF and IFF files name their format after the first chunk's size.
+    let chunks = |container: &[u8], format: &[u8]| {
+        header.starts_with(container) && header.get(8..12) == Some(format)
+    };
+    let kind = match header {
+        [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..]
+        | [0xff, 0xd8, 0xff, ..]
+        | [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..]
+        | [b'I', b'I', b'*', 0, ..]
+        | [b'M', b'M', 0, b'*', ..]
+        | [0, 0, 1, 0, ..] => IMAGE,
+        _ if chunks(b"RIFF", b"WEBP") => IMAGE,
+        [b'I', b'D', b'3', 2..=4, 0, ..]
+        | [0xff, 0xfb | 0xf3 | 0xf2, ..]
+        | [b'f', b'L', b'a', b'C', ..]
+        | [b'O', b'g', b'g', b'S', 0, ..] => AUDIO,
+        _ if chunks(b"RIFF", b"WAVE") => AUDIO,
+        _ if chunks(b"FORM", b"AIFF") || chunks(b"FORM", b"AIFC") => AUDIO,
+        [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..] => match brand.get(..4) {
+            Some(b"M4A " | b"M4B ") => AUDIO,
+            Some(b"heic" | b"heix" | b"mif1" | b"avif") => IMAGE,
+            _ => VIDEO,
+        },
+        [0x1a, 0x45, 0xdf, 0xa3, ..] | [0, 0, 1, 0xba | 0xb3, ..] => VIDEO,
+        _ if chunks(b"RIFF", b"AVI ") => VIDEO,
+        [0x7f, b'E', b'L', b'F', ..]
+        | [0xfe, 0xed, 0xfa, 0xce | 0xcf, ..]
+        | [0xce | 0xcf, 0xfa, 0xed, 0xfe, ..]
+        | [0xca, 0xfe, 0xba, 0xbe, ..]
+        | [0, b'a', b's', b'm', ..] => EXECUTABLE,
+        // A DOS or Windows program; its header is full of NUL bytes.
+        [b'M', b'Z', rest @ ..] if rest.contains(&0) => EXECUTABLE,
+        _ => return None,
+    };
+    Some(kind)
+}
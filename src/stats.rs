@@ -0,0 +1,106 @@
+//! The summary printed after a search with `--stats`.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::{StatsLevel, json};
+
+/// What a search got through, for `--stats`.
+#[derive(Default)]
+pub(crate) struct Stats {
+    pub(crate) files_searched: usize,
+    pub(crate) files_matched: usize,
+    pub(crate) matched_lines: usize,
+    pub(crate) bytes_read: u64,
+    /// The most memory held at once for reading a file.
+    pub(crate) peak_buffer: usize,
+    /// How many files each rule kept out of the search.
+    pub(crate) skipped: BTreeMap<&'static str, usize>,
+    pub(crate) elapsed: Duration,
+    /// The most threads that searched one file at once, or 0 for one.
+    pub(crate) threads: usize,
+    /// Time spent reading, matching and printing, as opposed to waiting,
+    /// summed over the threads.
+    pub(crate) busy: Duration,
+    /// Time the printing thread spent waiting for the others, which the
+    /// timings count as matching.
+    pub(crate) waited: Duration,
+}
+
+impl Stats {
+    pub(crate) fn skip(&mut self, reason: &'static str) {
+        *self.skipped.entry(reason).or_default() += 1;
+    }
+
+    pub(crate) fn write(&self, out: &mut dyn Write, level: StatsLevel) -> io::Result<()> {
+        writeln!(out)?;
+        writeln!(out, "{} matched lines", self.matched_lines)?;
+        writeln!(out, "{} files contained matches", self.files_matched)?;
+        writeln!(out, "{} files searched", self.files_searched)?;
+        writeln!(out, "{:.6} seconds elapsed", self.elapsed.as_secs_f64())?;
+        if level == StatsLevel::Basic {
+            return Ok(());
+        }
+
+        writeln!(out, "{} bytes read", self.bytes_read)?;
+        writeln!(out, "{} bytes peak buffer memory", self.peak_buffer)?;
+        let threads = self.threads();
+        let plural = if threads == 1 { "" } else { "s" };
+        writeln!(
+            out,
+            "{threads} search thread{plural}, {:.0}% busy",
+            self.busy_percent()
+        )?;
+        let skipped: usize = self.skipped.values().sum();
+        writeln!(out, "{skipped} files skipped")?;
+        for (reason, count) in &self.skipped {
+            writeln!(out, "  {count} {reason}")?;
+        }
+        Ok(())
+    }
+
+    /// The same numbers as [`Stats::write`] prints, as a JSON object on one
+    /// line, with the time in seconds and the files skipped counted by
+    /// reason.
+    pub(crate) fn json(&self, level: StatsLevel) -> String {
+        let mut json = format!(
+            "{{\"matched_lines\":{},\"files_matched\":{},\"files_searched\":{},\"elapsed_seconds\":{:.6}",
+            self.matched_lines,
+            self.files_matched,
+            self.files_searched,
+            self.elapsed.as_secs_f64()
+        );
+        if level == StatsLevel::Full {
+            json += &format!(
+                ",\"bytes_read\":{},\"peak_buffer_bytes\":{},\"threads\":{},\"busy_percent\":{:.0}",
+                self.bytes_read,
+                self.peak_buffer,
+                self.threads(),
+                self.busy_percent()
+            );
+            let skipped: Vec<String> = self
+                .skipped
+                .iter()
+                .map(|(reason, count)| format!("{}:{count}", json::string(reason)))
+                .collect();
+            json += &format!(",\"skipped\":{{{}}}", skipped.join(","));
+        }
+        json.push('}');
+        json
+    }
+
+    fn threads(&self) -> usize {
+        self.threads.max(1)
+    }
+
+    /// How much of the time elapsed the threads spent working, on average.
+    fn busy_percent(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            0.0
+        } else {
+            let available = self.elapsed.as_secs_f64() * self.threads() as f64;
+            100.0 * self.busy.as_secs_f64() / available
+        }
+    }
+}
@@ -0,0 +1,459 @@
+//! Searching structured files by their values instead of line by line.
+//!
+//! Each hit is reported as `PATH: VALUE`, where the path says where the
+//! value sits in the document, like `config.users[3].email`.
+
+#[cfg(feature = "json")]
+use std::fmt::Write;
+
+#[cfg(feature = "json")]
+use crate::json::{self, Value};
+use crate::matcher::Matcher;
+
+/// For `--json-input`, the values in `text` that match, each with the line
+/// it was found on. `text` is either one JSON document, whose values are
+/// all reported as line 1, or newline-delimited JSON with a document on
+/// each line.
+#[cfg(feature = "json")]
+pub(crate) fn json_hits(
+    text: &str,
+    matcher: &Matcher,
+) -> Result<Vec<(usize, String)>, &'static str> {
+    let mut hits = Vec::new();
+    match json::parse(text) {
+        Ok(document) => json_values(&document, &mut String::new(), &mut |path, value| {
+            if matcher.is_match(value) {
+                hits.push((1, hit(path, value)));
+            }
+        }),
+        Err(e) => {
+            for (line_number, line) in (1..).zip(text.lines()) {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // A line that isn't JSON means the file is neither, and the
+                // error from reading it whole says more.
+                let document = json::parse(line).map_err(|_| e)?;
+                json_values(&document, &mut String::new(), &mut |path, value| {
+                    if matcher.is_match(value) {
+                        hits.push((line_number, hit(path, value)));
+                    }
+                });
+            }
+        }
+    }
+    Ok(hits)
+}
+
+/// Calls `found` with the path and text of every scalar in `value`.
+#[cfg(feature = "json")]
+fn json_values(value: &Value, path: &mut String, found: &mut dyn FnMut(&str, &str)) {
+    let len = path.len();
+    match value {
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                let _ = write!(path, "[{i}]");
+                json_values(item, path, found);
+                path.truncate(len);
+            }
+        }
+        Value::Object(members) => {
+            for (key, member) in members {
+                let plain = !key.is_empty()
+                    && !key.starts_with(|c: char| c.is_ascii_digit())
+                    && key
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+                if !plain {
+                    let _ = write!(path, "[{}]", json::string(key));
+                } else if path.is_empty() {
+                    path.push_str(key);
+                } else {
+                    let _ = write!(path, ".{key}");
+                }
+                json_values(member, path, found);
+                path.truncate(len);
+            }
+        }
+        Value::String(s) => found(path, s),
+        Value::Number(n) => found(path, &n.to_string()),
+        Value::Bool(b) => found(path, &b.to_string()),
+        Value::Null => found(path, "null"),
+    }
+}
+
+#[cfg(not(feature = "json"))]
+pub(crate) fn json_hits(
+    _text: &str,
+    _matcher: &Matcher,
+) -> Result<Vec<(usize, String)>, &'static str> {
+    Err("--json-input needs minigrep built with the json feature")
+}
+
+#[cfg(feature = "json")]
+fn hit(path: &str, value: &str) -> String {
+    // Keep each hit on one line.
+    let value = value.replace('\n', "\\n");
+    if path.is_empty() {
+        value
+    } else {
+        format!("{path}: {value}")
+    }
+}
+
+/// For `--csv`, the records in `text` with a match in one of the `fields`
+/// (header names or 1-based column numbers), or in any field if none are
+/// given. Each comes with its row number, counting the header as row 1.
+pub(crate) fn csv_hits(
+    text: &str,
+    matcher: &Matcher,
+    delimiter: char,
+    fields: &[String],
+) -> Result<Vec<(usize, String)>, &'static str> {
+    let mut records = csv_records(text, delimiter).into_iter();
+    let Some((_, header)) = records.next() else {
+        return Ok(Vec::new());
+    };
+    let columns = fields
+        .iter()
+        .map(|field| match header.iter().position(|name| name == field) {
+            Some(column) => Ok(column),
+            None => field
+                .parse::<usize>()
+                .ok()
+                .filter(|n| *n > 0)
+                .map(|n| n - 1)
+                .ok_or("--field names a column that isn't in the header"),
+        })
+        .collect::<Result<Vec<usize>, _>>()?;
+
+    let mut hits = Vec::new();
+    for (row, (raw, values)) in (2..).zip(records) {
+        let found = if columns.is_empty() {
+            values.iter().any(|value| matcher.is_match(value))
+        } else {
+            columns.iter().any(|&column| {
+                values
+                    .get(column)
+                    .is_some_and(|value| matcher.is_match(value))
+            })
+        };
+        if found {
+            hits.push((row, raw.replace('\n', "\\n")));
+        }
+    }
+    Ok(hits)
+}
+
+/// Splits `text` into records, each with its text as written (without the
+/// line ending) and its fields with any quoting removed. Quoted fields can
+/// hold delimiters, newlines and doubled quotes.
+fn csv_records(text: &str, delimiter: char) -> Vec<(&str, Vec<String>)> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.next_if(|&(_, c)| c == '"').is_some() {
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            '\n' if !quoted => {
+                fields.push(std::mem::take(&mut field));
+                let raw = text[start..i].strip_suffix('\r').unwrap_or(&text[start..i]);
+                records.push((raw, std::mem::take(&mut fields)));
+                start = i + 1;
+            }
+            '\r' if !quoted && chars.peek().is_some_and(|&(_, c)| c == '\n') => {}
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if start < text.len() {
+        fields.push(field);
+        records.push((&text[start..], fields));
+    }
+    records
+}
+
+/// A CSS-like selector for `--selector`: compound selectors such as `div`,
+/// `.note`, `#main` or `p.intro`, separated by spaces to match descendants.
+pub(crate) struct Selector(Vec<Compound>);
+
+#[derive(Default)]
+struct Compound {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+}
+
+/// An element the text being read is inside.
+struct Element {
+    name: String,
+    id: Option<String>,
+    classes: Vec<String>,
+}
+
+impl Selector {
+    pub(crate) fn parse(selector: &str) -> Option<Selector> {
+        let mut compounds = Vec::new();
+        for word in selector.split_whitespace() {
+            // Split `p.intro#top` into the tag and each `.class` or `#id`.
+            let mut parts = vec![(None, String::new())];
+            for c in word.chars() {
+                match c {
+                    '.' | '#' => parts.push((Some(c), String::new())),
+                    c => parts.last_mut()?.1.push(c),
+                }
+            }
+            let mut compound = Compound::default();
+            for (marker, name) in parts {
+                match marker {
+                    None if name.is_empty() || name == "*" => {}
+                    None => compound.tag = Some(name.to_ascii_lowercase()),
+                    Some(_) if name.is_empty() => return None,
+                    Some('.') => compound.classes.push(name),
+                    Some(_) => compound.id = Some(name),
+                }
+            }
+            compounds.push(compound);
+        }
+        (!compounds.is_empty()).then_some(Selector(compounds))
+    }
+
+    /// Whether text inside the elements `open` (outermost first) is selected.
+    fn selects(&self, open: &[Element]) -> bool {
+        let Some((last, ancestors)) = self.0.split_last() else {
+            return false;
+        };
+        open.iter().enumerate().any(|(i, element)| {
+            last.matches(element) && {
+                let mut outer = open[..i].iter().rev();
+                ancestors
+                    .iter()
+                    .rev()
+                    .all(|compound| outer.any(|element| compound.matches(element)))
+            }
+        })
+    }
+}
+
+impl Compound {
+    fn matches(&self, element: &Element) -> bool {
+        self.tag.as_ref().is_none_or(|tag| *tag == element.name)
+            && self
+                .id
+                .as_ref()
+                .is_none_or(|id| element.id.as_ref() == Some(id))
+            && self
+                .classes
+                .iter()
+                .all(|class| element.classes.contains(class))
+    }
+}
+
+/// Elements that never have content or a closing tag.
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose text runs on from the text around them.
+const INLINE_ELEMENTS: [&str; 18] = [
+    "a", "abbr", "b", "cite", "code", "em", "font", "i", "kbd", "label", "mark", "q", "s", "small",
+    "span", "strong", "sub", "sup",
+];
+
+/// For `--html`, the lines of text content in `text` that match, ignoring
+/// tags, attributes, comments and scripts, and only counting text inside
+/// elements `selector` picks out when there is one. Each line keeps the
+/// number of the line it came from.
+pub(crate) fn html_hits(
+    text: &str,
+    matcher: &Matcher,
+    selector: Option<&Selector>,
+) -> Vec<(usize, String)> {
+    let mut lines = vec![String::new()];
+    let mut open: Vec<Element> = Vec::new();
+    let mut selected = selector.is_none();
+    let skip = |lines: &mut Vec<String>, skipped: &str| {
+        lines.extend(skipped.matches('\n').map(|_| String::new()));
+    };
+
+    let mut pos = 0;
+    while pos < text.len() {
+        let rest = &text[pos..];
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").map_or(rest.len(), |end| end + 3);
+            skip(&mut lines, &rest[..end]);
+            pos += end;
+            continue;
+        }
+        let is_tag = rest.starts_with('<')
+            && rest[1..]
+                .starts_with(|c: char| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?'));
+        if !is_tag {
+            let end = rest[1..].find('<').map_or(rest.len(), |end| end + 1);
+            for (i, piece) in rest[..end].split('\n').enumerate() {
+                if i > 0 {
+                    lines.push(String::new());
+                }
+                if selected && let Some(line) = lines.last_mut() {
+                    line.push_str(&decode_entities(piece));
+                }
+            }
+            pos += end;
+            continue;
+        }
+
+        let end = tag_end(rest);
+        let tag = &rest[..end];
+        skip(&mut lines, tag);
+        pos += end;
+        let Some(element) = parse_tag(tag) else {
+            continue;
+        };
+        if !INLINE_ELEMENTS.contains(&element.name.as_str())
+            && let Some(line) = lines.last_mut()
+        {
+            line.push(' ');
+        }
+        if tag.starts_with("</") {
+            if let Some(i) = open.iter().rposition(|open| open.name == element.name) {
+                open.truncate(i);
+            }
+        } else if matches!(element.name.as_str(), "script" | "style") {
+            let close = format!("</{}", element.name);
+            let body = rest[end..]
+                .to_ascii_lowercase()
+                .find(&close)
+                .unwrap_or(rest.len() - end);
+            skip(&mut lines, &rest[end..end + body]);
+            pos += body;
+        } else if !VOID_ELEMENTS.contains(&element.name.as_str()) && !tag.ends_with("/>") {
+            open.push(element);
+        }
+        selected = selector.is_none_or(|selector| selector.selects(&open));
+    }
+
+    (1..)
+        .zip(lines)
+        .filter_map(|(line_number, line)| {
+            let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+            (!line.is_empty() && matcher.is_match(&line)).then_some((line_number, line))
+        })
+        .collect()
+}
+
+/// The length of the tag at the start of `rest`, up to and including its
+/// `>`, skipping any in quoted attribute values.
+fn tag_end(rest: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in rest.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '>') => return i + 1,
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            _ => {}
+        }
+    }
+    rest.len()
+}
+
+/// Reads the name, id and classes from a start or end tag, or `None` for
+/// a doctype, comment or processing instruction.
+fn parse_tag(tag: &str) -> Option<Element> {
+    let inner = tag.strip_prefix('<')?.trim_start_matches('/');
+    if inner.starts_with(['!', '?']) {
+        return None;
+    }
+    let inner = inner.trim_end_matches('>').trim_end_matches('/');
+    let name_end = inner
+        .find(|c: char| c.is_whitespace())
+        .unwrap_or(inner.len());
+    let mut element = Element {
+        name: inner[..name_end].to_ascii_lowercase(),
+        id: None,
+        classes: Vec::new(),
+    };
+
+    let mut attributes = &inner[name_end..];
+    loop {
+        attributes = attributes.trim_start();
+        let name_end = attributes
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(attributes.len());
+        if name_end == 0 {
+            break;
+        }
+        let name = attributes[..name_end].to_ascii_lowercase();
+        attributes = attributes[name_end..].trim_start();
+        let mut value = "";
+        if let Some(after) = attributes.strip_prefix('=') {
+            let after = after.trim_start();
+            let (found, rest) = match after.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let close = after[1..].find(q).map_or(after.len(), |close| close + 1);
+                    (&after[1..close], after.get(close + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = found;
+            attributes = rest;
+        }
+        match name.as_str() {
+            "id" => element.id = Some(decode_entities(value)),
+            "class" => element.classes = value.split_whitespace().map(String::from).collect(),
+            _ => {}
+        }
+    }
+    Some(element)
+}
+
+/// Replaces the character references in `text` with what they stand for.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..].find(';').map(|end| &rest[1..end + 1]);
+        let c = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let code = match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#')?.parse().ok(),
+                };
+                code.and_then(char::from_u32)
+            }
+        });
+        match (c, entity) {
+            (Some(c), Some(entity)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
@@ -0,0 +1,73 @@
+//! Alternatives to search for along with a query, read from the file given
+//! with `--synonyms`, so spellings and old names a codebase mixes are
+//! written down once rather than remembered at every search.
+//!
+//! Each line maps a term to its alternatives:
+//!
+//! ```text
+//! # British spellings
+//! color: colour
+//! err: error, failure
+//! ```
+//!
+//! Blank lines and lines starting with `#` are skipped. A query that's
+//! exactly one of the terms is searched for along with its alternatives;
+//! the alternatives aren't expanded in turn.
+
+use std::error::Error;
+
+pub(crate) struct Synonyms {
+    /// Each term with its alternatives, in the order of the file.
+    entries: Vec<(String, Vec<String>)>,
+}
+
+impl Synonyms {
+    pub(crate) fn parse(text: &str) -> Result<Synonyms, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((term, alternatives)) = line.split_once(':') else {
+                return Err(format!(
+                    "line {} of the synonyms file needs to be `term: alternative, ...`",
+                    i + 1
+                )
+                .into());
+            };
+            let alternatives = alternatives
+                .split(',')
+                .map(str::trim)
+                .filter(|alternative| !alternative.is_empty())
+                .map(String::from)
+                .collect();
+            entries.push((term.trim().to_string(), alternatives));
+        }
+        Ok(Synonyms { entries })
+    }
+
+    /// `queries` with the alternatives of each that's a term added after
+    /// it, leaving out any query already there. Terms are compared without
+    /// regard to case when `ignore_case` is set.
+    pub(crate) fn expand(&self, queries: &[String], ignore_case: bool) -> Vec<String> {
+        let same = |a: &str, b: &str| match ignore_case {
+            true => a.to_lowercase() == b.to_lowercase(),
+            false => a == b,
+        };
+        let mut expanded: Vec<String> = Vec::with_capacity(queries.len());
+        for query in queries {
+            let alternatives = self
+                .entries
+                .iter()
+                .filter(|(term, _)| same(term, query))
+                .flat_map(|(_, alternatives)| alternatives);
+            for query in Some(query).into_iter().chain(alternatives) {
+                if !expanded.contains(query) {
+                    expanded.push(query.clone());
+                }
+            }
+        }
+        expanded
+    }
+}
@@ -0,0 +1,455 @@
+//! `--tui`: a full-screen front end that searches as the query is typed,
+//! with a preview of the selected match and a key to open it in `$EDITOR`.
+//!
+//! The files are read into memory once up front (see [`cache`]), so each
+//! keystroke only costs a scan of text that's already loaded.
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::process::Command;
+
+use crate::Config;
+use crate::cache::{self, File};
+use crate::matcher::Matcher;
+use crate::output::{self, expand_tabs, highlight};
+use crate::vfs::Disk;
+
+/// Only this many matches are listed, so a short query doesn't stall typing.
+const MAX_HITS: usize = 1000;
+
+const REVERSE: &str = "\x1b[7m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+/// Erases the rest of the line the cursor is on.
+const CLEAR_LINE: &str = "\x1b[K";
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Key {
+    Char(char),
+    Backspace,
+    /// Ctrl-U, which clears the query.
+    ClearQuery,
+    Up,
+    Down,
+    Enter,
+    /// Escape or Ctrl-C.
+    Quit,
+}
+
+/// Decodes the bytes a terminal in raw mode sends for one burst of typing.
+pub(crate) fn keys(input: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < input.len() {
+        let key = match input[i] {
+            // A lone escape is the Escape key; otherwise it starts a
+            // sequence like `ESC [ A` for the arrow keys.
+            0x1b if i + 1 == input.len() => Some(Key::Quit),
+            0x1b => {
+                let end = input[i + 1..]
+                    .iter()
+                    .skip(1)
+                    .position(|b| (0x40..=0x7e).contains(b))
+                    .map_or(input.len(), |end| i + end + 3);
+                let key = match &input[i..end] {
+                    b"\x1b[A" | b"\x1bOA" => Some(Key::Up),
+                    b"\x1b[B" | b"\x1bOB" => Some(Key::Down),
+                    _ => None,
+                };
+                i = end;
+                keys.extend(key);
+                continue;
+            }
+            0x03 => Some(Key::Quit),
+            b'\r' | b'\n' => Some(Key::Enter),
+            0x7f | 0x08 => Some(Key::Backspace),
+            0x15 => Some(Key::ClearQuery),
+            0x10 => Some(Key::Up),
+            0x0e => Some(Key::Down),
+            byte if byte < b' ' => None,
+            _ => {
+                let len = match input[i] {
+                    0xf0.. => 4,
+                    0xe0.. => 3,
+                    0xc0.. => 2,
+                    _ => 1,
+                };
+                let end = (i + len).min(input.len());
+                if let Ok(text) = std::str::from_utf8(&input[i..end]) {
+                    keys.extend(text.chars().map(Key::Char));
+                }
+                i = end;
+                continue;
+            }
+        };
+        keys.extend(key);
+        i += 1;
+    }
+    keys
+}
+
+/// One matching line.
+pub(crate) struct Hit {
+    file: usize,
+    line_number: usize,
+    /// Where the line is in the file's contents.
+    line: Range<usize>,
+}
+
+pub(crate) fn run(config: &Config) -> Result<(), Box<dyn Error>> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err("--tui needs a terminal".into());
+    }
+
+    let mut files = cache::load(config, &Disk);
+    let mut query = config.search.queries.join(" ");
+    let mut hits = search(&files, &query, config);
+    let mut selected = 0;
+    let mut terminal = Terminal::enter()?;
+    let mut input = [0; 64];
+
+    loop {
+        let matcher = Matcher::lenient(&query, config.search.ignore_case, config.search.engine);
+        let view = View {
+            query: &query,
+            matcher: &matcher,
+            hits: &hits,
+            selected,
+            match_style: config.output.theme.match_escape(),
+        };
+        terminal.draw(&view.render(&files, terminal_size()))?;
+
+        let read = io::stdin().read(&mut input)?;
+        if read == 0 {
+            return Ok(());
+        }
+        let mut edited = false;
+        for key in keys(&input[..read]) {
+            match key {
+                Key::Char(c) => {
+                    query.push(c);
+                    edited = true;
+                }
+                Key::Backspace => edited |= query.pop().is_some(),
+                Key::ClearQuery => {
+                    edited |= !query.is_empty();
+                    query.clear();
+                }
+                Key::Up => selected = selected.saturating_sub(1),
+                Key::Down => selected = (selected + 1).min(hits.len().saturating_sub(1)),
+                Key::Enter => {
+                    let Some(hit) = hits.get(selected) else {
+                        continue;
+                    };
+                    let (file, line_number) = (hit.file, hit.line_number);
+                    drop(terminal);
+                    let opened = open_in_editor(&files[file].path, line_number);
+                    terminal = Terminal::enter()?;
+                    opened?;
+                    // The file may well have been edited.
+                    if let Ok(contents) = fs::read_to_string(&files[file].path) {
+                        files[file].contents = contents;
+                    }
+                    hits = search(&files, &query, config);
+                    selected = selected.min(hits.len().saturating_sub(1));
+                }
+                Key::Quit => return Ok(()),
+            }
+        }
+        if edited {
+            hits = search(&files, &query, config);
+            selected = 0;
+        }
+    }
+}
+
+/// Finds up to [`MAX_HITS`] matching lines. An empty query matches nothing
+/// here, rather than everything.
+fn search(files: &[File], query: &str, config: &Config) -> Vec<Hit> {
+    let mut hits = Vec::new();
+    if query.is_empty() {
+        return hits;
+    }
+    let matcher = Matcher::lenient(query, config.search.ignore_case, config.search.engine);
+    for (file, File { contents, .. }) in files.iter().enumerate() {
+        let mut start = 0;
+        for (line_number, line) in (1..).zip(contents.split_inclusive('\n')) {
+            let range = start..start + line.trim_end_matches(['\n', '\r']).len();
+            start += line.len();
+            if matcher.is_match(&contents[range.clone()]) {
+                hits.push(Hit {
+                    file,
+                    line_number,
+                    line: range,
+                });
+                if hits.len() == MAX_HITS {
+                    return hits;
+                }
+            }
+        }
+    }
+    hits
+}
+
+/// What's on screen.
+struct View<'a> {
+    query: &'a str,
+    matcher: &'a Matcher,
+    hits: &'a [Hit],
+    selected: usize,
+    match_style: String,
+}
+
+impl View<'_> {
+    /// Draws a whole screen `rows` by `cols`: the query, a status line, the
+    /// list of hits and a preview of the selected one.
+    fn render(&self, files: &[File], (rows, cols): (usize, usize)) -> String {
+        let mut lines = Vec::with_capacity(rows);
+        let mut line = |text: String| lines.push(text);
+
+        line(clip(&format!("> {}", self.query), cols));
+        let status = match self.hits.len() {
+            _ if self.query.is_empty() => {
+                "type to search, up/down to choose, enter to edit, esc to quit".to_string()
+            }
+            MAX_HITS => format!("{MAX_HITS}+ matches"),
+            1 => "1 match".to_string(),
+            n => format!("{n} matches"),
+        };
+        line(format!("{BOLD}{}{RESET}", clip(&status, cols)));
+
+        let body = rows.saturating_sub(3);
+        let list_rows = body / 2;
+        let first = self.selected.saturating_sub(list_rows.saturating_sub(1));
+        for i in first..first + list_rows {
+            let Some(hit) = self.hits.get(i) else {
+                line(String::new());
+                continue;
+            };
+            let file = &files[hit.file];
+            let text = format!(
+                "{}:{}: {}",
+                file.path.display(),
+                hit.line_number,
+                file.contents[hit.line.clone()].trim_start()
+            );
+            let text = clip(&text, cols);
+            if i == self.selected {
+                let padding = cols.saturating_sub(text.chars().count());
+                line(format!("{REVERSE}{text}{}{RESET}", " ".repeat(padding)));
+            } else {
+                line(highlight(&text, self.matcher, &self.match_style));
+            }
+        }
+
+        line("─".repeat(cols));
+        let preview_rows = body - list_rows;
+        if let Some(hit) = self.hits.get(self.selected) {
+            let first = hit.line_number.saturating_sub(preview_rows / 2).max(1);
+            let lines = files[hit.file].contents.lines();
+            for (line_number, text) in (first..).zip(lines.skip(first - 1)).take(preview_rows) {
+                let marker = if line_number == hit.line_number {
+                    '>'
+                } else {
+                    ' '
+                };
+                let text = clip(&format!("{marker}{line_number:>6} {text}"), cols);
+                line(highlight(&text, self.matcher, &self.match_style));
+            }
+        }
+        lines.resize(rows, String::new());
+
+        // The last row mustn't end in a newline, or the screen scrolls.
+        let mut screen = lines.join(&format!("{CLEAR_LINE}\r\n"));
+        screen.push_str(CLEAR_LINE);
+        let column = self.query.chars().count() + 3;
+        screen.push_str(&format!("\x1b[1;{}H", column.min(cols)));
+        screen
+    }
+}
+
+/// Cuts `text` to fit in `width` columns, with tabs expanded and control
+/// characters, which would move the cursor, left out.
+fn clip(text: &str, width: usize) -> String {
+    expand_tabs(text, 4)
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(width)
+        .collect()
+}
+
+/// Runs `$VISUAL` or `$EDITOR` on `path`, asking it to start at
+/// `line_number` the way most terminal editors understand.
+fn open_in_editor(path: &Path, line_number: usize) -> io::Result<()> {
+    let default = if cfg!(windows) { "notepad" } else { "vi" };
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| default.to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(default);
+
+    let mut command = Command::new(program);
+    command.args(words);
+    let stem = Path::new(program).file_stem();
+    if stem.is_none_or(|stem| !stem.eq_ignore_ascii_case("notepad")) {
+        command.arg(format!("+{line_number}"));
+    }
+    let status = command.arg(path).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("{editor} exited with {status}")));
+    }
+    Ok(())
+}
+
+/// The terminal in raw mode on the alternate screen, put back how it was
+/// when dropped.
+struct Terminal {
+    raw: RawMode,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Terminal> {
+        output::escapes_supported();
+        let raw = RawMode::enable()?;
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\x1b[?1049h")?;
+        stdout.flush()?;
+        Ok(Terminal { raw })
+    }
+
+    fn draw(&mut self, screen: &str) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        write!(stdout, "\x1b[H{screen}")?;
+        stdout.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(b"\x1b[?1049l");
+        let _ = stdout.flush();
+        self.raw.restore();
+    }
+}
+
+/// The size in rows and columns of the terminal stdin is connected to, if
+/// it is one and knows it.
+pub(crate) fn tty_size() -> Option<(usize, usize)> {
+    // A terminal that was never told its size reports zero.
+    RawMode::size().filter(|&(rows, cols)| rows > 0 && cols > 0)
+}
+
+/// The terminal's size in rows and columns.
+fn terminal_size() -> (usize, usize) {
+    if let Some(size) = tty_size() {
+        return size;
+    }
+    let var = |name, default| {
+        env::var(name)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|value| *value > 0)
+            .unwrap_or(default)
+    };
+    (var("LINES", 24), var("COLUMNS", 80))
+}
+
+/// Keystrokes arriving one at a time, unechoed, instead of a line at a time.
+#[cfg(unix)]
+struct RawMode {
+    /// The settings to go back to, as `stty -g` printed them.
+    saved: String,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        Ok(RawMode { saved })
+    }
+
+    fn restore(&self) {
+        let _ = stty(&[&self.saved]);
+    }
+
+    fn size() -> Option<(usize, usize)> {
+        let size = stty(&["size"]).ok()?;
+        let (rows, cols) = size.split_once(' ')?;
+        Some((rows.parse().ok()?, cols.parse().ok()?))
+    }
+}
+
+/// Runs `stty` on the terminal stdin is connected to.
+#[cfg(unix)]
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(std::process::Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty couldn't change the terminal's mode"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(windows)]
+struct RawMode {
+    saved: u32,
+}
+
+#[cfg(windows)]
+mod console {
+    use std::ffi::c_void;
+
+    pub(super) const ENABLE_PROCESSED_INPUT: u32 = 0x0001;
+    pub(super) const ENABLE_LINE_INPUT: u32 = 0x0002;
+    pub(super) const ENABLE_ECHO_INPUT: u32 = 0x0004;
+    pub(super) const ENABLE_VIRTUAL_TERMINAL_INPUT: u32 = 0x0200;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        pub(super) fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        pub(super) fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+}
+
+#[cfg(windows)]
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        use console::*;
+        use std::os::windows::io::AsRawHandle;
+
+        let stdin = io::stdin().as_raw_handle();
+        let mut saved = 0;
+        let raw_flags = ENABLE_PROCESSED_INPUT | ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT;
+        // SAFETY: `stdin` is the console's input handle for the life of the
+        // process and `saved` is a valid place for the console to write to.
+        unsafe {
+            if GetConsoleMode(stdin, &mut saved) == 0
+                || SetConsoleMode(stdin, saved & !raw_flags | ENABLE_VIRTUAL_TERMINAL_INPUT) == 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(RawMode { saved })
+    }
+
+    fn restore(&self) {
+        use std::os::windows::io::AsRawHandle;
+
+        // SAFETY: as in `enable`.
+        unsafe {
+            console::SetConsoleMode(io::stdin().as_raw_handle(), self.saved);
+        }
+    }
+
+    fn size() -> Option<(usize, usize)> {
+        None
+    }
+}
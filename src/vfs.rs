@@ -0,0 +1,274 @@
+//! Where searched files are read from. Walking a tree and reading its files
+//! go through a [`FileSystem`], which is the disk for the command line, and
+//! can be a [`MemoryFs`] for tests or for a program searching files it
+//! holds itself, like an editor's unsaved buffers.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::walk;
+
+/// Something files can be read from.
+pub trait FileSystem {
+    /// Opens the file at `path` for reading.
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Source + '_>>;
+
+    /// What's at `path`, without following a symlink there.
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// The entries of the directory at `path`, in the order it lists them.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<Entry>>;
+
+    /// Where `path` leads once every symlink in it is followed. Without
+    /// symlinks that's `path` itself.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        Ok(path.to_path_buf())
+    }
+}
+
+/// A file opened for reading, which can be read from partway through.
+pub trait Source: Read + Seek {}
+
+impl<T: Read + Seek> Source for T {}
+
+/// What kind of thing a path names.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    /// A named pipe, socket or device, described as `--explain-skips` says.
+    Special(&'static str),
+    /// Anything else there is.
+    Other,
+}
+
+/// What's known about a file without opening it.
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    pub kind: FileKind,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    /// The device and inode, where they tell hard links apart.
+    pub identity: Option<(u64, u64)>,
+}
+
+impl Metadata {
+    pub fn is_file(&self) -> bool {
+        self.kind == FileKind::File
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == FileKind::Dir
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.kind == FileKind::Symlink
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn modified(&self) -> io::Result<SystemTime> {
+        self.modified
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "no modification time"))
+    }
+
+    /// The kind of special file this is, if it's one.
+    pub(crate) fn special_kind(&self) -> Option<&'static str> {
+        match self.kind {
+            FileKind::Special(kind) => Some(kind),
+            _ => None,
+        }
+    }
+}
+
+impl From<fs::Metadata> for Metadata {
+    fn from(metadata: fs::Metadata) -> Metadata {
+        let kind = if metadata.is_symlink() {
+            FileKind::Symlink
+        } else if metadata.is_dir() {
+            FileKind::Dir
+        } else if metadata.is_file() {
+            FileKind::File
+        } else {
+            walk::special_kind(&metadata).map_or(FileKind::Other, FileKind::Special)
+        };
+        Metadata {
+            kind,
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            identity: walk::identity(&metadata),
+        }
+    }
+}
+
+/// One entry in a directory.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+/// The files on disk.
+pub struct Disk;
+
+impl FileSystem for Disk {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Source + '_>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        fs::symlink_metadata(path).map(Metadata::from)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<Entry>> {
+        Ok(fs::read_dir(path)?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                Some(Entry {
+                    is_dir: entry.file_type().is_ok_and(|kind| kind.is_dir()),
+                    path: entry.path(),
+                })
+            })
+            .collect())
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+}
+
+/// Files held in memory, under the paths they were added with. The
+/// directories are the ones those paths go through, listed in order of
+/// name.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFs {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+    /// The modification times that have been set, by path.
+    modified: BTreeMap<PathBuf, SystemTime>,
+}
+
+impl MemoryFs {
+    pub fn new() -> MemoryFs {
+        MemoryFs::default()
+    }
+
+    /// Adds a file, or replaces the one already at `path`.
+    pub fn insert(&mut self, path: impl Into<PathBuf>, contents: impl Into<Vec<u8>>) {
+        self.files.insert(path.into(), contents.into());
+    }
+
+    /// Gives the file at `path` a modification time, which files have none
+    /// of until they're given one.
+    pub fn set_modified(&mut self, path: impl Into<PathBuf>, modified: SystemTime) {
+        self.modified.insert(path.into(), modified);
+    }
+
+    fn not_found(path: &Path) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} isn't in the file system", path.display()),
+        )
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Source + '_>> {
+        let contents = self
+            .files
+            .get(path)
+            .ok_or_else(|| MemoryFs::not_found(path))?;
+        Ok(Box::new(Cursor::new(contents.as_slice())))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let kind = match self.files.get(path) {
+            Some(_) => FileKind::File,
+            None if self.files.keys().any(|file| file.starts_with(path)) => FileKind::Dir,
+            None => return Err(MemoryFs::not_found(path)),
+        };
+        Ok(Metadata {
+            kind,
+            len: self
+                .files
+                .get(path)
+                .map_or(0, |contents| contents.len() as u64),
+            modified: self.modified.get(path).copied(),
+            identity: None,
+        })
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<Entry>> {
+        if !self.metadata(path)?.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                format!("{} isn't a directory", path.display()),
+            ));
+        }
+        let mut entries: Vec<Entry> = Vec::new();
+        for file in self.files.keys() {
+            let Ok(relative) = file.strip_prefix(path) else {
+                continue;
+            };
+            let mut components = relative.components();
+            let Some(name) = components.next() else {
+                continue;
+            };
+            let child = path.join(name);
+            // Files under the same directory are next to each other.
+            if entries.last().is_some_and(|entry| entry.path == child) {
+                continue;
+            }
+            entries.push(Entry {
+                path: child,
+                is_dir: components.next().is_some(),
+            });
+        }
+        Ok(entries)
+    }
+}
+
+/// What `path` leads to, following a symlink there.
+pub(crate) fn followed(fs: &dyn FileSystem, path: &Path) -> io::Result<Metadata> {
+    fs.metadata(&fs.canonicalize(path)?)
+}
+
+/// Reads the whole file at `path` as text.
+pub(crate) fn read_to_string(fs: &dyn FileSystem, path: &Path) -> io::Result<String> {
+    let mut contents = String::new();
+    fs.open(path)?.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// Like [`read_to_string`], but asking for at most `size` bytes at a time.
+pub(crate) fn read_to_string_by(
+    fs: &dyn FileSystem,
+    path: &Path,
+    size: usize,
+) -> io::Result<String> {
+    let mut file = fs.open(path)?;
+    let mut contents = Vec::new();
+    loop {
+        let start = contents.len();
+        contents.resize(start + size, 0);
+        match file.read(&mut contents[start..]) {
+            Ok(0) => {
+                contents.truncate(start);
+                break;
+            }
+            Ok(read) => contents.truncate(start + read),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => contents.truncate(start),
+            Err(e) => return Err(e),
+        }
+    }
+    String::from_utf8(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,211 @@
+//! Directory traversal for recursive (`-r`) searches.
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use crate::SortKey;
+use crate::ignore::{Ignored, Ignores, Level};
+use crate::vfs::{self, FileSystem};
+
+/// Collects the entries of `fs` under `root` that `keep` accepts, descending into
+/// subdirectories and leaving out whatever `ignores` says to.
+///
+/// `keep` sees every entry that isn't a directory, including symlinks, but
+/// only its metadata, so rejected files are never opened. With
+/// `follow_links`, symlinks are replaced by the canonical path and metadata
+/// of what they point to, and each directory is walked only once however
+/// many links lead to it. Directories that can't be read are reported on
+/// stderr and skipped so one bad entry doesn't abort the whole search.
+///
+/// Paths longer than Windows' `MAX_PATH` need no special care here: the
+/// standard library gives them the `\\?\` prefix itself when opening them.
+pub(crate) fn walk(
+    fs: &dyn FileSystem,
+    root: &Path,
+    ignores: &Ignores,
+    follow_links: bool,
+    keep: impl FnMut(&Path, &vfs::Metadata) -> bool,
+) -> Vec<PathBuf> {
+    walk_noting_ignored(fs, root, ignores, follow_links, keep, |_, _| {})
+}
+
+/// Like [`walk`], but also calling `ignored` with each entry an ignore rule
+/// left out, and the rule, for `--explain-skips`. What's inside an ignored
+/// directory isn't looked at.
+pub(crate) fn walk_noting_ignored(
+    fs: &dyn FileSystem,
+    root: &Path,
+    ignores: &Ignores,
+    follow_links: bool,
+    mut keep: impl FnMut(&Path, &vfs::Metadata) -> bool,
+    mut ignored: impl FnMut(&Path, &Ignored),
+) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut walked = HashSet::new();
+    let mut pending = vec![(root.to_path_buf(), ignores.top(root))];
+
+    while let Some((path, level)) = pending.pop() {
+        let metadata = match fs.metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!("minigrep: {}: {e}", path.display());
+                continue;
+            }
+        };
+        let (path, metadata) = if follow_links && metadata.is_symlink() {
+            match fs
+                .canonicalize(&path)
+                .and_then(|target| Ok((fs.metadata(&target)?, target)))
+            {
+                Ok((metadata, target)) => (displayable(target), metadata),
+                Err(e) => {
+                    eprintln!("minigrep: {}: {e}", path.display());
+                    continue;
+                }
+            }
+        } else {
+            (path, metadata)
+        };
+
+        if metadata.is_dir() {
+            if path != root && ignores.skips_dir(&path) {
+                continue;
+            }
+            if follow_links && !walked.insert(fs.canonicalize(&path).unwrap_or(path.clone())) {
+                continue;
+            }
+            let entries = match fs.read_dir(&path) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    eprintln!("minigrep: {}: {e}", path.display());
+                    continue;
+                }
+            };
+            let level: Option<Rc<Level>> = ignores.enter(fs, &path, level);
+            // Push in reverse so entries come back out of the stack in the
+            // order the directory listed them.
+            let mut children: Vec<_> = entries
+                .into_iter()
+                .filter_map(|entry| {
+                    let rule = level
+                        .as_ref()
+                        .and_then(|level| level.ignored(&entry.path, entry.is_dir));
+                    match rule {
+                        Some(rule) => {
+                            ignored(&entry.path, &rule);
+                            None
+                        }
+                        None => Some((entry.path, level.clone())),
+                    }
+                })
+                .collect();
+            children.reverse();
+            pending.extend(children);
+        } else if keep(&path, &metadata) {
+            files.push(path);
+        }
+    }
+
+    files
+}
+
+/// `path` without the `\\?\` prefix that canonical paths on Windows have,
+/// so it prints the way paths are usually written. Where the prefix is
+/// still needed, because the path is long, it's put back when the path is
+/// opened.
+#[cfg(windows)]
+fn displayable(path: PathBuf) -> PathBuf {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Component, Prefix};
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().collect();
+    let plain = match path.components().next() {
+        // `\\?\C:\...` becomes `C:\...`.
+        Some(Component::Prefix(prefix)) if matches!(prefix.kind(), Prefix::VerbatimDisk(_)) => {
+            wide[4..].to_vec()
+        }
+        // `\\?\UNC\server\...` becomes `\\server\...`.
+        Some(Component::Prefix(prefix)) if matches!(prefix.kind(), Prefix::VerbatimUNC(..)) => {
+            [&[u16::from(b'\\')], &wide[7..]].concat()
+        }
+        _ => return path,
+    };
+    PathBuf::from(OsString::from_wide(&plain))
+}
+
+#[cfg(not(windows))]
+fn displayable(path: PathBuf) -> PathBuf {
+    path
+}
+
+/// Orders `files`, as found in `fs`, by `key`, largest or newest last unless
+/// `reverse` is set.
+///
+/// Files whose metadata can no longer be read sort as if they were empty and
+/// infinitely old.
+pub(crate) fn sort(fs: &dyn FileSystem, files: &mut [PathBuf], key: SortKey, reverse: bool) {
+    match key {
+        SortKey::Path => files.sort(),
+        SortKey::Modified => files.sort_by_cached_key(|path| {
+            vfs::followed(fs, path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        }),
+        SortKey::Size => files.sort_by_cached_key(|path| {
+            vfs::followed(fs, path).map_or(0, |metadata| metadata.len())
+        }),
+        SortKey::Priority => files.sort_by_cached_key(|path| {
+            let metadata = vfs::followed(fs, path).ok();
+            let len = metadata.as_ref().map_or(0, vfs::Metadata::len);
+            let modified = metadata
+                .and_then(|metadata| metadata.modified().ok())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            // Sizes within a factor of 16 of each other count as the same.
+            (len.max(1).ilog2() / 4, Reverse(modified))
+        }),
+    }
+
+    if reverse {
+        files.reverse();
+    }
+}
+
+/// What kind of special file `metadata` describes, if it's a named pipe, a
+/// socket or a device rather than a file, directory or symlink.
+#[cfg(unix)]
+pub(crate) fn special_kind(metadata: &Metadata) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
+    let kind = metadata.file_type();
+    if kind.is_fifo() {
+        Some("named pipe")
+    } else if kind.is_socket() {
+        Some("socket")
+    } else if kind.is_char_device() || kind.is_block_device() {
+        Some("device")
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn special_kind(_metadata: &Metadata) -> Option<&'static str> {
+    None
+}
+
+/// The device and inode that identify a file however it's reached, on
+/// platforms that have them.
+#[cfg(unix)]
+pub(crate) fn identity(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn identity(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}